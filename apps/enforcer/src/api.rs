@@ -1,37 +1,29 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use axum_macros::debug_handler;
 use dotenv::dotenv;
-use entity::nonces;
 use ethers::{
-    core::abi::{encode, Token},
     core::utils::keccak256,
     signers::{LocalWallet, Signer},
     types::{Address, Bytes, Signature, TxHash, U256},
 };
-use sea_orm::{Database, DatabaseConnection, DbBackend, EntityTrait, QueryFilter, Statement};
+use sea_orm::{Database, TransactionTrait};
 use serde::{Deserialize, Serialize};
-use spvm_rs::{decode_tx_content, encode_tx_content, Transaction};
+use serde_json::json;
+use spvm_rs::{
+    decode_tx_content, encode_preconf_payload, PreconfirmationCommitment, PreconfirmationPayload,
+    SpvmError, Transaction,
+};
 use std::env;
 use std::str::FromStr;
 use tracing::{error, info};
 
 use crate::AppState;
 
-#[derive(Deserialize, Serialize, Debug)]
-pub struct PreconfirmationPayload {
-    pub transaction: Transaction,
-    pub tip_tx: Transaction,
-    pub preconfer_contract: Address,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-pub struct PreconfirmationCommitment {
-    pub preconfirmation_request: PreconfirmationPayload,
-    pub commitment: Signature,
-    pub signer: Address,
-    pub block_number: U256,
-}
-
 // Used for recieving requests ONLY from the setup script
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PrivilegedTransaction {
@@ -41,28 +33,25 @@ pub struct PrivilegedTransaction {
     pub preconfer_contract: Address,
 }
 
-pub fn encode_preconf_payload(payload: &PreconfirmationPayload) -> Bytes {
-    let encoded_tx = encode_transaction(&payload.transaction);
-    let encoded_tip_tx = encode_transaction(&payload.tip_tx);
-
-    let tokens = Token::Tuple(vec![
-        Token::Bytes(encoded_tx.to_vec()),
-        Token::Bytes(encoded_tip_tx.to_vec()),
-        Token::Address(payload.preconfer_contract),
-    ]);
-
-    encode(&[tokens]).into()
+// Maps an SPVM error to an HTTP status. Client errors are reported as 400, storage failures
+// as 500; the body carries the stable SPVM error code.
+fn spvm_error_status(e: &SpvmError) -> StatusCode {
+    match e {
+        SpvmError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    }
 }
 
-pub fn encode_transaction(tx: &Transaction) -> Bytes {
-    let encoded_cont = encode_tx_content(&tx.tx_content);
-    let tokens = Token::Tuple(vec![
-        Token::Bytes(encoded_cont.to_vec()),
-        Token::Bytes(tx.tx_hash.as_bytes().to_vec()),
-        Token::Bytes(tx.signature.into()),
-    ]);
-
-    encode(&[tokens]).into()
+fn spvm_error_response(context: &str, e: SpvmError) -> Response {
+    (
+        spvm_error_status(&e),
+        Json(json!({
+            "status": "error",
+            "code": e.code(),
+            "message": format!("{}: {}", context, e),
+        })),
+    )
+        .into_response()
 }
 
 #[debug_handler]
@@ -73,29 +62,37 @@ pub async fn request_preconfirmation(
     dotenv().ok();
     info!("Received request: {:?}", payload);
 
-    let db = state.db;
+    // The user tx and the tip tx are applied atomically: if the tip fails, the user tx is
+    // rolled back as well.
+    let txn = match state.db.begin().await {
+        Ok(txn) => txn,
+        Err(e) => {
+            error!("Failed to start database transaction: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response();
+        }
+    };
 
     println!("payload: {:?}", payload);
     // checks the tx is valid, makes state changes
-    let transaction_result = payload.transaction.execute_transaction(&db).await;
+    let transaction_result = payload
+        .transaction
+        .execute_transaction(&state.signature_scheme, &state.appchain(&txn))
+        .await;
 
     if let Err(e) = transaction_result {
         error!("Transaction execution failed: {}", e);
-        return (StatusCode::BAD_REQUEST, format!("Transaction Error: {}", e)).into_response();
+        return spvm_error_response("Transaction Error", e);
     }
-    drop(transaction_result);
 
-    let tip_tx_result = payload.tip_tx.execute_transaction(&db).await;
+    let tip_tx_result = payload
+        .tip_tx
+        .execute_transaction(&state.signature_scheme, &state.appchain(&txn))
+        .await;
 
     if let Err(e) = tip_tx_result {
         error!("Tip transaction execution failed: {}", e);
-        return (
-            StatusCode::BAD_REQUEST,
-            format!("Tip Transaction Error: {}", e),
-        )
-            .into_response();
+        return spvm_error_response("Tip Transaction Error", e);
     }
-    drop(tip_tx_result);
 
     let pv_key = match env::var("PRIVATE_KEY") {
         Ok(key) => key,
@@ -126,6 +123,11 @@ pub async fn request_preconfirmation(
         }
     };
 
+    if let Err(e) = txn.commit().await {
+        error!("Failed to commit transactions: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Error: {}", e)).into_response();
+    }
+
     // Pushes the txs to be added to the contract by the cron job
     let validity_txs = &state.validity_txs;
     let mut validity_txs = validity_txs
@@ -233,7 +235,7 @@ pub async fn apply_tx(
         Ok(content) => content,
         Err(e) => {
             println!("Error decoding tx content: {:?}", e);
-            return StatusCode::BAD_REQUEST;
+            return spvm_error_status(&e);
         }
     };
 
@@ -243,7 +245,9 @@ pub async fn apply_tx(
         signature: Signature::from_str(&payload.signature.to_string()).unwrap(),
     };
 
-    let transaction_result = tx.execute_transaction(&db).await;
+    let transaction_result = tx
+        .execute_transaction(&state.signature_scheme, &state.appchain(&db))
+        .await;
 
     let validity_txs = &state.validity_txs;
     let mut validity_txs = validity_txs
@@ -258,7 +262,7 @@ pub async fn apply_tx(
 
     if let Err(e) = transaction_result {
         error!("Transaction execution failed: {}", e);
-        return StatusCode::BAD_REQUEST;
+        return spvm_error_status(&e);
     }
 
     StatusCode::OK
@@ -9,12 +9,11 @@ use ethers::{
     utils::keccak256,
 };
 use migration::{Migrator, MigratorTrait};
-use sea_orm::{Database, DatabaseConnection};
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection};
 use serde::{Deserialize, Serialize};
 use spvm_rs::*;
 use std::collections::HashMap;
 use std::env;
-use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use tokio::time::{self, Duration};
 
@@ -27,6 +26,24 @@ pub struct AppState {
     pub validity_txs: ValidityConditions,
     pub db: Arc<DatabaseConnection>,
     pub block_num: Arc<Mutex<u64>>,
+    // Appchain whose state the enforcer keeps in `db`
+    pub chain_id: u64,
+    // Whether the appchain accepts legacy transactions, which carry no chain id
+    pub accept_legacy_txs: bool,
+    // How transaction hashes are derived from their content
+    pub signature_scheme: SignatureScheme,
+}
+
+impl AppState {
+    // The enforcer's appchain through `db`, the connection or a transaction started on it
+    pub fn appchain<'a, C: ConnectionTrait>(&self, db: &'a C) -> Appchain<'a, C> {
+        let appchain = Appchain::new(self.chain_id, db);
+        if self.accept_legacy_txs {
+            appchain.accept_legacy_txs()
+        } else {
+            appchain
+        }
+    }
 }
 
 struct TxEncoded {
@@ -70,6 +87,7 @@ async fn main() {
         .init();
     dotenv().ok();
 
+    let chain_id = appchain_id();
     let validity_txs: ValidityConditions = Arc::new(Mutex::new(HashMap::new()));
 
     tokio::time::sleep(Duration::from_secs(10)).await;
@@ -113,10 +131,22 @@ async fn main() {
         .unwrap();
     tracing::debug!("Enforcer listening on {}", listener.local_addr().unwrap());
 
-    axum::serve(listener, app(db)).await.unwrap();
+    axum::serve(listener, app(db, chain_id)).await.unwrap();
 }
 
-fn app(db: DatabaseConnection) -> Router {
+// Appchain the enforcer keeps the state of, from APPCHAIN_ID. Chain id 0 is what legacy
+// transactions carry, so it can't be used.
+fn appchain_id() -> u64 {
+    let chain_id = env::var("APPCHAIN_ID")
+        .expect("APPCHAIN_ID is not set in .env file")
+        .parse::<u64>()
+        .expect("APPCHAIN_ID must be a valid u64");
+    assert_ne!(chain_id, 0, "APPCHAIN_ID must not be 0");
+
+    chain_id
+}
+
+fn app(db: DatabaseConnection, chain_id: u64) -> Router {
     let validity_txs: ValidityConditions = Arc::new(Mutex::new(HashMap::new()));
 
     let start_block_num = env::var("BLOCK_NUM")
@@ -124,10 +154,27 @@ fn app(db: DatabaseConnection) -> Router {
         .and_then(|v| v.parse::<u64>().ok())
         .unwrap_or(1);
 
+    // Off unless set: legacy transactions signed for one appchain are valid on all of them
+    let accept_legacy_txs = env::var("ACCEPT_LEGACY_TXS")
+        .map(|v| {
+            v.parse::<bool>()
+                .expect("ACCEPT_LEGACY_TXS must be true or false")
+        })
+        .unwrap_or(false);
+
+    // Required, so that legacy hashing is only used when asked for
+    let signature_scheme = env::var("SIGNATURE_SCHEME")
+        .expect("SIGNATURE_SCHEME is not set in .env file")
+        .parse::<SignatureScheme>()
+        .unwrap_or_else(|e| panic!("SIGNATURE_SCHEME: {}", e));
+
     let shared_state = AppState {
         validity_txs: validity_txs.clone(),
         db: Arc::new(db),
         block_num: Arc::new(Mutex::new(start_block_num)),
+        chain_id,
+        accept_legacy_txs,
+        signature_scheme,
     };
 
     Router::new()
@@ -296,7 +343,7 @@ async fn register_with_gateway() {
 /*
 #[cfg(test)]
 mod tests {
-    use self::api::PreconfirmationPayload;
+    use spvm_rs::PreconfirmationPayload;
 
     use super::*;
     use axum::{
@@ -0,0 +1,4 @@
+-- Run by the postgres image when it initializes an empty data directory. The `spvm` database
+-- holds the appchains' SPVM state: the proposer writes it and the gateway reads it. Both apply
+-- the SPVM migrations on startup.
+CREATE DATABASE spvm;
@@ -1,5 +1,5 @@
 use axum::extract::Query;
-use axum::response::IntoResponse;
+use axum::response::{IntoResponse, Response};
 use axum::{http::StatusCode, Json};
 use axum_macros::debug_handler;
 use ethers::abi::Log;
@@ -23,9 +23,7 @@ use tracing_subscriber::fmt::format;
 
 use crate::router::AppState;
 use crate::services::{MutationDB, QueryDB};
-use crate::utils::abi::{
-    decode_tx_content, encode_preconf_payload, encode_tx_content, strip_0x_prefix,
-};
+use crate::utils::abi::strip_0x_prefix;
 use crate::utils::{response::*, types::*};
 
 use entity::{enforcer_metadata, preconf_commitment, preconf_status};
@@ -51,7 +49,7 @@ pub async fn not_found_404() -> (StatusCode, Json<Value>) {
 pub async fn request_preconfirmation(
     state: axum::extract::State<AppState>,
     data: Json<SubmitPreconfirmationRequest>,
-) -> StatusCode {
+) -> Response {
     // for debugging purposes, print the data
     println!("[Debug] request_preconfirmation handler");
     let tx_content_string = data.tx_content.to_string();
@@ -62,7 +60,7 @@ pub async fn request_preconfirmation(
         Ok(content) => content,
         Err(e) => {
             println!("Error decoding tx content: {:?}", e);
-            return StatusCode::BAD_REQUEST;
+            return to_spvm_error_response(e).into_response();
         }
     };
 
@@ -77,17 +75,23 @@ pub async fn request_preconfirmation(
         Err(e) => {
             println!("Error inserting: {:?}", e);
             // do not return, sometimes request_preconfirmation gets called twice for some reason
-            // return StatusCode::INTERNAL_SERVER_ERROR;
+            // return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
-    // check that the transaction hash is a keccak256 hash of the transaction content
-    let tx_hash = keccak256(&data.tx_content);
-    if tx_hash.to_vec() == data.tx_hash.to_vec() {
+    let rollup = if data.chain == "chain_a" {
+        state.rollup_a
+    } else {
+        state.rollup_b
+    };
+
+    // check that the transaction hash is the hash of the transaction content
+    let tx_hash = rollup.signature_scheme.tx_hash(&tx_content);
+    if tx_hash.as_bytes() == data.tx_hash.as_ref() {
         println!("Transaction hash is valid");
     } else {
         println!("Transaction hash is invalid");
-        return StatusCode::BAD_REQUEST;
+        return StatusCode::BAD_REQUEST.into_response();
     }
 
     let election_address = {
@@ -108,7 +112,7 @@ pub async fn request_preconfirmation(
         Ok(provider) => provider,
         Err(e) => {
             println!("Error creating provider: {:?}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
@@ -134,7 +138,7 @@ pub async fn request_preconfirmation(
         println!("Next Enforcer: {:?}", next_enforcer);
     } else {
         println!("Error getting winner");
-        return StatusCode::INTERNAL_SERVER_ERROR;
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
     }
 
     // determine the amount to tip
@@ -144,14 +148,14 @@ pub async fn request_preconfirmation(
         Ok(key) => key,
         Err(e) => {
             println!("Error getting private key: {:?}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
     let wallet = match pv_key.parse::<LocalWallet>() {
         Ok(wallet) => wallet,
         Err(_e) => {
             println!("Error parsing private key");
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
@@ -164,32 +168,29 @@ pub async fn request_preconfirmation(
     // generate tip_tx to send
     let raw_tip_tx = TransactionContent {
         from: wallet.address(),
-        tx_type: 1,
+        tx_type: TX_FORMAT_V2 | 1,
         tx_param: TransactionParams::Transfer(TransferTransactionParams {
             token_ticker: "ETH".to_string(),
             to: next_enforcer,
-            amount: tip,
+            amount: U256::from(tip),
         }),
         nonce,
+        chain_id: rollup.chain_id,
     };
 
-    let raw_tip_tx_encoded = encode_tx_content(&raw_tip_tx);
-
     // sign the tip_tx
-    let tip_hash = TxHash::from_slice(&keccak256(&raw_tip_tx_encoded));
+    let tip_hash = rollup.signature_scheme.tx_hash(&raw_tip_tx);
     let signed_tip_tx = match wallet.sign_hash(tip_hash) {
         Ok(sig) => sig,
         Err(e) => {
             println!("Error signing tip tx: {:?}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
-    let tip_tx_hash = keccak256(&raw_tip_tx_encoded);
-
     let tip_tx = Transaction {
         tx_content: raw_tip_tx,
-        tx_hash: tip_tx_hash.into(),
+        tx_hash: tip_hash,
         signature: signed_tip_tx,
     };
 
@@ -235,9 +236,9 @@ pub async fn request_preconfirmation(
         };
         if let Err(e) = tx.update(&*state.db).await {
             println!("Error inserting: {:?}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
-        return StatusCode::BAD_REQUEST;
+        return StatusCode::BAD_REQUEST.into_response();
     }
 
     // check that the preconf commitment is valid - Signature and Block
@@ -249,7 +250,7 @@ pub async fn request_preconfirmation(
         .unwrap();
 
     if U256::from(block_num) != preconf_commitment.block_number {
-        return StatusCode::BAD_REQUEST;
+        return StatusCode::BAD_REQUEST.into_response();
     }
 
     // update status of this transaction to APPROVED
@@ -261,7 +262,7 @@ pub async fn request_preconfirmation(
         Ok(_) => (),
         Err(e) => {
             println!("Error inserting: {:?}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
@@ -276,12 +277,12 @@ pub async fn request_preconfirmation(
         Ok(_) => (),
         Err(e) => {
             println!("Error inserting: {:?}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     }
 
     // return 200 OK
-    StatusCode::OK
+    StatusCode::OK.into_response()
 }
 
 // send preconf request to enforcer's api
@@ -336,8 +337,17 @@ pub async fn get_preconf_status(
 
     match status {
         Ok(status) => {
+            // Inclusion is extra information: without the SPVM state, report the enforcer's
+            // decision alone
+            let block_number = match included_in_block(&state, &params.tx_hash).await {
+                Ok(block_number) => block_number,
+                Err(e) => {
+                    println!("Error looking up receipts: {:?}", e);
+                    None
+                }
+            };
             let status = json!({
-                "data": to_status_response(status.unwrap())
+                "data": to_status_response(status.unwrap(), block_number)
             });
             return Ok(Json(status));
         }
@@ -347,6 +357,23 @@ pub async fn get_preconf_status(
     }
 }
 
+// Block of the first successful receipt of the transaction on either rollup
+async fn included_in_block(state: &AppState, tx_hash: &str) -> Result<Option<u32>, SpvmError> {
+    let Ok(tx_hash) = TxHash::from_str(tx_hash) else {
+        return Ok(None);
+    };
+
+    for rollup in [state.rollup_a, state.rollup_b] {
+        let appchain = Appchain::new(rollup.chain_id, state.spvm_db.as_ref());
+        let receipts = get_receipts(tx_hash, &appchain).await?;
+        if let Some(receipt) = receipts.iter().find(|receipt| receipt.is_success()) {
+            return Ok(Some(receipt.block_number));
+        }
+    }
+
+    Ok(None)
+}
+
 #[debug_handler]
 pub async fn get_wallet_balance(
     state: axum::extract::State<AppState>,
@@ -399,6 +426,45 @@ pub async fn get_wallet_balance(
     }
 }
 
+// Balance from the SPVM state of the rollup, with a Merkle proof against its state root
+#[debug_handler]
+pub async fn get_balance_proof(
+    state: axum::extract::State<AppState>,
+    params: Query<CheckBalance>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let contract_data = state.contract_data.lock().unwrap().clone();
+
+    let rollup = if params.rollup_contract == contract_data.chain_a.spvm.address {
+        state.rollup_a
+    } else if params.rollup_contract == contract_data.chain_b.spvm.address {
+        state.rollup_b
+    } else {
+        return Err(to_wrong_address_error());
+    };
+    let appchain = Appchain::new(rollup.chain_id, state.spvm_db.as_ref());
+
+    // Proofs are against the state root of the latest stored block. The root is not on L1, the
+    // SPVM-1 blocks have no field for it, so clients have to trust the gateway for it. The state
+    // may already include the next block while it is proposed.
+    let block = get_latest_block(&appchain)
+        .await
+        .map_err(to_spvm_error_response)?
+        .ok_or_else(to_no_block_error)?;
+    let tree = StateTree::load(&appchain)
+        .await
+        .map_err(to_spvm_error_response)?;
+    if tree.root() != block.state_root {
+        return Err(to_state_ahead_of_block_error(block.number));
+    }
+    let proof = tree.balance_proof(&params.token_ticker, params.address);
+
+    Ok(to_balance_proof_response(
+        block.number,
+        block.state_root,
+        proof,
+    ))
+}
+
 #[debug_handler]
 pub async fn create_enforcer_challenge(
     state: axum::extract::State<AppState>,
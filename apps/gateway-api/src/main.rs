@@ -1,6 +1,7 @@
 use ethers::providers::{Http, Middleware, Provider};
 use migration::{Migrator, MigratorTrait};
 use sea_orm::{Database, DatabaseConnection};
+use spvm_migration::Migrator as SpvmMigrator;
 use std::env;
 use std::sync::{Arc, Mutex};
 use tracing_subscriber;
@@ -14,6 +15,17 @@ mod utils;
 use crate::router::*;
 use crate::utils::abi::*;
 
+// SPVM state of the appchains, written by the proposer. Both run the SPVM migrations, so
+// whichever starts first creates the tables.
+async fn connect_spvm_db() -> Result<DatabaseConnection, Box<dyn std::error::Error>> {
+    let spvm_db_url =
+        env::var("SPVM_DATABASE_URL").map_err(|_| "SPVM_DATABASE_URL is not set in .env file")?;
+    let spvm_database = Database::connect(spvm_db_url).await?;
+    SpvmMigrator::up(&spvm_database, None).await?;
+
+    Ok(spvm_database)
+}
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt()
@@ -23,6 +35,9 @@ async fn main() {
 
     println!("yay!");
 
+    let rollup_a = Rollup::from_env("A").unwrap_or_else(|e| panic!("{}", e));
+    let rollup_b = Rollup::from_env("B").unwrap_or_else(|e| panic!("{}", e));
+
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
     let database: DatabaseConnection = Database::connect(db_url).await.unwrap();
 
@@ -30,6 +45,10 @@ async fn main() {
         .await
         .expect("Database connection failed");
 
+    let spvm_database = connect_spvm_db()
+        .await
+        .unwrap_or_else(|e| panic!("SPVM database: {}", e));
+
     println!("let's do this!");
 
     // fetch deployed contract addresses and abis once on startup, and store in shared state
@@ -44,9 +63,12 @@ async fn main() {
     let current_block = provider.get_block_number().await.unwrap();
     let app_state = AppState {
         db: Arc::new(database),
+        spvm_db: Arc::new(spvm_database),
         contract_data: shared_data,
         nonce: Arc::new(Mutex::new(0)),
         block_number: Arc::new(Mutex::new(current_block.clone().as_u64())),
+        rollup_a,
+        rollup_b,
     };
 
     println!("here we go!");
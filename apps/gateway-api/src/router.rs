@@ -3,6 +3,8 @@ use axum::{
     Router,
 };
 use sea_orm::DatabaseConnection;
+use spvm_rs::SignatureScheme;
+use std::env;
 use std::sync::{Arc, Mutex};
 use tower_http::cors::{Any, CorsLayer};
 
@@ -12,9 +14,43 @@ use crate::utils::abi::ContractData;
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub db: Arc<DatabaseConnection>,
+    pub spvm_db: Arc<DatabaseConnection>,
     pub contract_data: Arc<Mutex<ContractData>>,
     pub nonce: Arc<Mutex<u32>>,
     pub block_number: Arc<Mutex<u64>>,
+    pub rollup_a: Rollup,
+    pub rollup_b: Rollup,
+}
+
+// Settings of a rollup the gateway serves, read once on startup
+#[derive(Debug, Clone, Copy)]
+pub struct Rollup {
+    // Appchain id the rollup's SPVM state is kept under
+    pub chain_id: u64,
+    // How the rollup derives transaction hashes from their content
+    pub signature_scheme: SignatureScheme,
+}
+
+impl Rollup {
+    // Reads APPCHAIN_ID_<name> and SIGNATURE_SCHEME_<name>
+    pub fn from_env(name: &str) -> Result<Self, String> {
+        let chain_id_var = format!("APPCHAIN_ID_{}", name);
+        let chain_id = env::var(&chain_id_var)
+            .map_err(|_| format!("{} is not set in .env file", chain_id_var))?
+            .parse::<u64>()
+            .map_err(|_| format!("{} must be a valid u64", chain_id_var))?;
+
+        let scheme_var = format!("SIGNATURE_SCHEME_{}", name);
+        let signature_scheme = env::var(&scheme_var)
+            .map_err(|_| format!("{} is not set in .env file", scheme_var))?
+            .parse::<SignatureScheme>()
+            .map_err(|e| format!("{}: {}", scheme_var, e))?;
+
+        Ok(Self {
+            chain_id,
+            signature_scheme,
+        })
+    }
 }
 
 pub fn router() -> Router<AppState> {
@@ -27,6 +63,7 @@ pub fn router() -> Router<AppState> {
         .route("/", get(health_check))
         .route("/request_preconfirmation", post(request_preconfirmation))
         .route("/request_balance", get(get_wallet_balance))
+        .route("/request_balance_proof", get(get_balance_proof))
         .route("/preconfirmation_status", get(get_preconf_status))
         .route("/enforcer_metadata", get(create_enforcer_challenge))
         .route("/enforcer_metadata", post(register_enforcer))
@@ -1,10 +1,12 @@
 use axum::{http::StatusCode, Json};
 use ethers::abi::Uint;
+use ethers::types::H256;
 use hex::FromHexError;
 use k256::ecdsa;
 use sea_orm::DbErr;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use spvm_rs::{BalanceProof, SpvmError};
 
 use ::entity::{
     challenge::Model as ChallengeModel, enforcer_metadata::Model as EnforcerMetadataModel,
@@ -28,6 +30,7 @@ pub struct RegisterEnforcerResponse {
 pub struct PreconfirmationStatusResponse {
     pub tx_hash: String,
     pub status: String,
+    pub block_number: Option<u32>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -35,10 +38,18 @@ pub struct BalanceResponse {
     pub balance: Uint,
 }
 
-pub fn to_status_response(status: StatusModel) -> PreconfirmationStatusResponse {
+// Transactions included in a block report INCLUDED instead of the enforcer's decision
+pub fn to_status_response(
+    status: StatusModel,
+    block_number: Option<u32>,
+) -> PreconfirmationStatusResponse {
     PreconfirmationStatusResponse {
         tx_hash: status.tx_hash.to_owned(),
-        status: status.status.to_owned(),
+        status: match block_number {
+            Some(_) => "INCLUDED".to_string(),
+            None => status.status.to_owned(),
+        },
+        block_number,
     }
 }
 
@@ -62,6 +73,22 @@ pub fn to_balance_response(balance: Uint) -> (StatusCode, axum::Json<serde_json:
     );
 }
 
+pub fn to_balance_proof_response(
+    block_number: u32,
+    state_root: H256,
+    proof: BalanceProof,
+) -> (StatusCode, axum::Json<serde_json::Value>) {
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status": "ok",
+            "block_number": block_number,
+            "state_root": state_root,
+            "proof": proof,
+        })),
+    )
+}
+
 pub fn to_register_enforcer_response(enforcer: EnforcerMetadataModel) -> RegisterEnforcerResponse {
     RegisterEnforcerResponse {
         address: enforcer.address.to_owned(),
@@ -108,9 +135,42 @@ pub fn to_wrong_enforcer_address_error() -> (StatusCode, axum::Json<serde_json::
     );
 }
 
+pub fn to_no_block_error() -> (StatusCode, axum::Json<serde_json::Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"status": "error", "message": "The rollup has no block yet"})),
+    )
+}
+
+pub fn to_state_ahead_of_block_error(
+    block_number: u32,
+) -> (StatusCode, axum::Json<serde_json::Value>) {
+    let message = format!(
+        "The state is ahead of block {}, retry once the next block is stored",
+        block_number
+    );
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({"status": "error", "message": message})),
+    )
+}
+
 pub fn to_already_registered_response() -> (StatusCode, axum::Json<serde_json::Value>) {
     return (
         StatusCode::OK,
         Json(json!({"status": "ok", "message": "Enforcer metadata already registered"})),
     );
 }
+
+pub fn to_spvm_error_response(error: SpvmError) -> (StatusCode, axum::Json<serde_json::Value>) {
+    let status = match error {
+        SpvmError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    (
+        status,
+        Json(json!({"status": "error", "code": error.code(), "message": error.to_string()})),
+    )
+}
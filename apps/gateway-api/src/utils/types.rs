@@ -1,6 +1,6 @@
-use ethers::types::{Address, Bytes, Signature, U256};
+use ethers::types::{Address, Bytes};
 use serde::{Deserialize, Serialize};
-use spvm_rs::Transaction;
+use spvm_rs::PreconfirmationCommitment;
 
 //For use by the wallet to query a user's balance on the spvm contract
 #[derive(Deserialize, Serialize, Debug)]
@@ -26,24 +26,6 @@ pub struct SubmitPreconfirmationRequest {
     pub chain: String,
 }
 
-//Sent to enforcer after checking metadata associated with address
-#[derive(Deserialize, Serialize, Debug)]
-pub struct PreconfirmationPayload {
-    pub transaction: Transaction,
-    pub tip_tx: Transaction,
-    pub preconfer_contract: Address,
-}
-
-// This is a response type. TODO: move to utils/response.rs
-// To be submitted by the enforcer and stored in db for future use by slashing contract
-#[derive(Deserialize, Serialize, Debug)]
-pub struct PreconfirmationCommitment {
-    pub preconfirmation_request: PreconfirmationPayload,
-    pub commitment: Signature,
-    pub signer: Address,
-    pub block_number: U256,
-}
-
 // I think this is a response type, TODO: move to utils/response.rs
 #[derive(Deserialize, Serialize, Debug)]
 pub struct RequestPreconfirmationCommitment {
@@ -1,11 +1,9 @@
 use ethers::{
     contract::abigen,
-    core::abi::{decode, encode, ParamType, Token},
     middleware::SignerMiddleware,
     providers::{Http, Middleware, Provider},
     signers::{LocalWallet, Signer},
-    types::{Address, Bytes, Signature, TxHash, U256},
-    utils::keccak256,
+    types::{Address, BlockNumber, Bytes, Signature, TxHash, H256, U256},
 };
 use migration::{Migrator, MigratorTrait};
 use sea_orm::{Database, DatabaseConnection};
@@ -13,6 +11,7 @@ use spvm_rs::*;
 use std::env;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 use tokio::time::{self, Duration};
 
@@ -31,24 +30,119 @@ struct TxContentEncoded {
     nonce: u32,
 }
 
-// struct Block {
-//     transactions: Vec<TxEncoded>,
-//     block_hash: Bytes,
-//     parent_hash: Bytes,
-//     block_number: u32,
-//     proposer: Address,
-//     proposer_signature: Bytes,
-// }
-
 struct AppState {
-    appchain_a: Appchain,
-    appchain_b: Appchain,
+    appchain_a: Rollup,
+    appchain_b: Rollup,
     db: DatabaseConnection,
 }
 
-struct Appchain {
+// A rollup tracked by the proposer. All of them keep their state in the same database, each
+// under its own chain id.
+struct Rollup {
+    chain_id: u64,
+    // Whether the appchain accepts legacy transactions, which carry no chain id
+    accept_legacy_txs: bool,
+    // How transaction hashes are derived from their content
+    signature_scheme: SignatureScheme,
+    spvm_address: Address,
+    slashing_address: Address,
     parent_hash: [u8; 32],
     block_number: u32,
+    // Number of the first block, proposed again if every block is rolled back
+    start_block_number: u32,
+    // Root of the SPVM state after the last executed block
+    state_root: H256,
+}
+
+impl Rollup {
+    // Reads APPCHAIN_ID_<name>, SIGNATURE_SCHEME_<name>, SPVM_ADDRESS_<name> and
+    // SLASHING_ADDRESS_<name>, and ACCEPT_LEGACY_TXS_<name> if set
+    fn from_env(name: &str, block_number: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            chain_id: env::var(format!("APPCHAIN_ID_{}", name))?.parse()?,
+            accept_legacy_txs: match env::var(format!("ACCEPT_LEGACY_TXS_{}", name)) {
+                Ok(accept) => accept.parse()?,
+                Err(_) => false,
+            },
+            signature_scheme: env::var(format!("SIGNATURE_SCHEME_{}", name))?.parse()?,
+            spvm_address: env::var(format!("SPVM_ADDRESS_{}", name))?.parse()?,
+            slashing_address: env::var(format!("SLASHING_ADDRESS_{}", name))?.parse()?,
+            parent_hash: [0u8; 32],
+            block_number,
+            start_block_number: block_number,
+            state_root: H256::zero(),
+        })
+    }
+
+    fn appchain<'a>(&self, db: &'a DatabaseConnection) -> Appchain<'a, DatabaseConnection> {
+        let appchain = Appchain::new(self.chain_id, db);
+        if self.accept_legacy_txs {
+            appchain.accept_legacy_txs()
+        } else {
+            appchain
+        }
+    }
+
+    // Continues after the last block stored for the rollup, if any. Blocks that were executed
+    // but never proposed are rolled back first.
+    async fn resume(&mut self, db: &DatabaseConnection) -> Result<(), SpvmError> {
+        let appchain = self.appchain(db);
+        match get_latest_block(&appchain).await? {
+            Some(block) => {
+                rollback_to(Some(block.number), &appchain).await?;
+                self.parent_hash = block.contract_hash().0;
+                self.block_number = block.number + 2;
+                self.state_root = block.state_root;
+            }
+            None => {
+                rollback_to(None, &appchain).await?;
+                self.parent_hash = [0u8; 32];
+                self.block_number = self.start_block_number;
+                self.state_root = state_root(&appchain).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Rolls back the blocks built on L1 blocks that were reorged out, then resumes after the
+    // last block left. Blocks without a recorded L1 origin are considered final.
+    async fn handle_reorgs(
+        &mut self,
+        db: &DatabaseConnection,
+        client: &Provider<Http>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let appchain = self.appchain(db);
+        let mut kept = get_latest_block(&appchain).await?;
+        while let Some(block) = &kept {
+            match get_l1_origin(block.number, &appchain).await? {
+                Some(origin) if !is_canonical(origin, client).await? => {
+                    kept = get_block_by_contract_hash(block.parent_hash, &appchain).await?;
+                }
+                _ => break,
+            }
+        }
+
+        let undone = rollback_to(kept.map(|block| block.number), &appchain).await?;
+        if !undone.is_empty() {
+            println!(
+                "L1 reorg: rolled back blocks {:?} of appchain {}",
+                undone, self.chain_id
+            );
+        }
+        self.resume(db).await?;
+
+        Ok(())
+    }
+}
+
+async fn is_canonical(
+    origin: L1Origin,
+    client: &Provider<Http>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let block = client.get_block(origin.number).await?;
+
+    Ok(block.and_then(|block| block.hash) == Some(origin.hash))
 }
 
 #[tokio::main]
@@ -65,15 +159,18 @@ async fn main() {
         .and_then(|v| v.parse::<u32>().ok())
         .unwrap_or(0u32);
 
+    let mut appchain_a = Rollup::from_env("A", start_block_num).unwrap();
+    let mut appchain_b = Rollup::from_env("B", start_block_num).unwrap();
+    // Blocks stored before their contract hash was recorded can't be found as parents otherwise
+    for rollup in [&appchain_a, &appchain_b] {
+        index_contract_hashes(&rollup.appchain(&db)).await.unwrap();
+    }
+    appchain_a.resume(&db).await.unwrap();
+    appchain_b.resume(&db).await.unwrap();
+
     let app_state = AppState {
-        appchain_a: Appchain {
-            parent_hash: [0u8; 32],
-            block_number: start_block_num,
-        },
-        appchain_b: Appchain {
-            parent_hash: [0u8; 32],
-            block_number: start_block_num,
-        },
+        appchain_a,
+        appchain_b,
         db,
     };
 
@@ -92,22 +189,45 @@ async fn main() {
         let provider = Provider::<Http>::try_from(provider_url).unwrap();
         let client = Arc::new(provider);
 
-        let block_num = client.get_block_number().await.unwrap().as_u32();
+        // The L1 block the next blocks are built on, to detect when it gets reorged out
+        let l1_block = client
+            .get_block(BlockNumber::Latest)
+            .await
+            .unwrap()
+            .unwrap();
+        let origin = L1Origin {
+            number: l1_block.number.unwrap().as_u64(),
+            hash: l1_block.hash.unwrap(),
+        };
+        let block_num = origin.number as u32;
 
         let state = Arc::clone(&shared_state);
         tokio::spawn(async move {
             let mut guard = state.lock().await;
-            let a_txs = get_validity_conditions(block_num /* L1 Block number */, true)
+            let guard = &mut *guard;
+            guard
+                .appchain_a
+                .handle_reorgs(&guard.db, &client)
                 .await
                 .unwrap();
-            let b_txs = get_validity_conditions(block_num /* L1 Block number */, false)
+            guard
+                .appchain_b
+                .handle_reorgs(&guard.db, &client)
                 .await
                 .unwrap();
 
+            let a_txs =
+                get_validity_conditions(block_num /* L1 Block number */, &guard.appchain_a)
+                    .await
+                    .unwrap();
+            let b_txs =
+                get_validity_conditions(block_num /* L1 Block number */, &guard.appchain_b)
+                    .await
+                    .unwrap();
+
             let mut new_b_txs: Vec<Transaction> = b_txs;
 
             for tx in &a_txs {
-                tx.execute_transaction(&guard.db).await.unwrap();
                 // check for certain tx to sponsor
                 let pv_key = env::var("PRIVATE_KEY").unwrap();
                 let wallet = LocalWallet::from_str(&pv_key)
@@ -119,7 +239,7 @@ async fn main() {
                     if tx_params.to == wallet.address() {
                         // nowe we know this transaction is a bridge, next step is to check if it is a cross-chain swap or just a simple bridge
                         // for thie PoC, we assume every transfer with an odd amount is a bridge
-                        if tx_params.amount % 2 == 1 {
+                        if tx_params.amount % 2 == U256::one() {
                             // bridge
                             // construct a transfer transaction on appchain B that sends the bridged amount of the same token to the from address
                             // add it to new_b_txs
@@ -128,7 +248,8 @@ async fn main() {
                                 &wallet,
                                 tx.tx_content.from,
                                 tx_params.amount,
-                                get_nonce_on_appchain(false).await.unwrap(),
+                                get_nonce_on_appchain(&guard.appchain_b).await.unwrap(),
+                                &guard.appchain_b,
                             );
 
                             new_b_txs.push(new_tx);
@@ -141,8 +262,9 @@ async fn main() {
                                 env::var("BRIDGE_TICKER").unwrap().as_str(),
                                 &wallet,
                                 tx.tx_content.from,
-                                env::var("BRIDGE_AMOUNT").unwrap().parse::<u16>().unwrap(),
-                                get_nonce_on_appchain(false).await.unwrap(),
+                                U256::from_dec_str(&env::var("BRIDGE_AMOUNT").unwrap()).unwrap(),
+                                get_nonce_on_appchain(&guard.appchain_b).await.unwrap(),
+                                &guard.appchain_b,
                             );
 
                             new_b_txs.push(new_tx);
@@ -150,14 +272,52 @@ async fn main() {
                     }
                 }
             }
-            for tx in &new_b_txs {
-                tx.execute_transaction(&guard.db).await.unwrap();
+            // Each appchain has its own state, so each candidate block is checked against its own
+            // chain. Nothing is proposed if either of them would be rejected.
+            for (name, txs, appchain) in [
+                ("A", &a_txs, &guard.appchain_a),
+                ("B", &new_b_txs, &guard.appchain_b),
+            ] {
+                let simulation = simulate_transactions(
+                    txs,
+                    &appchain.signature_scheme,
+                    &appchain.appchain(&guard.db),
+                )
+                .await
+                .unwrap();
+                if let Some(failure) = simulation.failure {
+                    println!(
+                        "Candidate block of appchain {} fails at transaction {}: {}",
+                        name, failure.index, failure.error
+                    );
+                    return;
+                }
             }
+            execute_block(
+                guard.appchain_a.block_number,
+                &a_txs,
+                &guard.appchain_a.signature_scheme,
+                &guard.appchain_a.appchain(&guard.db),
+            )
+            .await
+            .unwrap();
+            execute_block(
+                guard.appchain_b.block_number,
+                &new_b_txs,
+                &guard.appchain_b.signature_scheme,
+                &guard.appchain_b.appchain(&guard.db),
+            )
+            .await
+            .unwrap();
 
-            let a_encoded_txs = encode_transactions(&a_txs);
-            let b_encoded_txs = encode_transactions(&new_b_txs);
+            guard.appchain_a.state_root = state_root(&guard.appchain_a.appchain(&guard.db))
+                .await
+                .unwrap();
+            guard.appchain_b.state_root = state_root(&guard.appchain_b.appchain(&guard.db))
+                .await
+                .unwrap();
 
-            let a_txs = a_txs
+            let a_encoded_txs = a_txs
                 .iter()
                 .map(|tx| TxEncoded {
                     tx_hash: tx.tx_hash,
@@ -171,7 +331,7 @@ async fn main() {
                 })
                 .collect();
 
-            let b_txs = new_b_txs
+            let b_encoded_txs = new_b_txs
                 .iter()
                 .map(|tx| TxEncoded {
                     tx_hash: tx.tx_hash,
@@ -185,21 +345,16 @@ async fn main() {
                 })
                 .collect();
 
-            let a_new_hash = propose_block(
-                a_txs,
-                a_encoded_txs,
-                guard.appchain_a.parent_hash,
-                guard.appchain_a.block_number,
-                true,
-            )
-            .await
-            .unwrap();
+            let a_new_hash =
+                propose_block(a_encoded_txs, a_txs, origin, &guard.appchain_a, &guard.db)
+                    .await
+                    .unwrap();
             let b_new_hash = propose_block(
-                b_txs,
                 b_encoded_txs,
-                guard.appchain_b.parent_hash,
-                guard.appchain_b.block_number,
-                false,
+                new_b_txs,
+                origin,
+                &guard.appchain_b,
+                &guard.db,
             )
             .await
             .unwrap();
@@ -217,37 +372,31 @@ fn create_transfer_transaction(
     ticker: &str,
     from: &LocalWallet,
     to: Address,
-    amount: u16,
+    amount: U256,
     nonce: u32,
+    appchain: &Rollup,
 ) -> Transaction {
     create_transaction(
         from,
-        1,
+        TX_FORMAT_V2 | 1,
         TransactionParams::Transfer(TransferTransactionParams {
             token_ticker: ticker.to_string(),
             to,
             amount,
         }),
         nonce,
+        appchain,
     )
 }
 
-async fn get_nonce_on_appchain(appchain_a: bool) -> Result<u32, Box<dyn std::error::Error>> {
+async fn get_nonce_on_appchain(rollup: &Rollup) -> Result<u32, Box<dyn std::error::Error>> {
     let provider_url = env::var("PROVIDER")?;
     let provider = Provider::<Http>::try_from(provider_url)?;
     let client = Arc::new(provider);
 
-    let spvm = if appchain_a {
-        env::var("SPVM_ADDRESS_A")?
-    } else {
-        env::var("SPVM_ADDRESS_B")?
-    };
-
-    let spvm_address = spvm.parse::<Address>()?;
-
     abigen!(Spvm, "contracts/SPVM.json");
 
-    let spvm = Spvm::new(spvm_address, client.clone());
+    let spvm = Spvm::new(rollup.spvm_address, client.clone());
 
     let pv_key = env::var("PRIVATE_KEY")?;
     let wallet = LocalWallet::from_str(&pv_key)?.with_chain_id(31337u64);
@@ -262,16 +411,22 @@ fn create_transaction(
     tx_type: u8,
     tx_param: TransactionParams,
     nonce: u32,
+    appchain: &Rollup,
 ) -> Transaction {
+    // Legacy transactions have no chain id
     let tx_content = TransactionContent {
         from: signer.address(),
         tx_type,
         tx_param,
         nonce,
+        chain_id: if tx_type & TX_FORMAT_V2 == 0 {
+            0
+        } else {
+            appchain.chain_id
+        },
     };
 
-    let message = encode_tx_content(&tx_content);
-    let tx_hash = TxHash::from_slice(&keccak256(message));
+    let tx_hash = appchain.signature_scheme.tx_hash(&tx_content);
     let signature = signer.sign_hash(tx_hash).unwrap();
 
     Transaction {
@@ -283,22 +438,14 @@ fn create_transaction(
 
 async fn get_validity_conditions(
     block_num: u32,
-    appchain_a: bool,
+    rollup: &Rollup,
 ) -> Result<Vec<spvm_rs::Transaction>, Box<dyn std::error::Error>> {
     abigen!(Slashing, "contracts/Slashing.json");
     let provider_url = env::var("PROVIDER")?;
     let provider = Provider::<Http>::try_from(provider_url)?;
     let client = Arc::new(provider);
 
-    let slashing_address: String = if appchain_a {
-        env::var("SLASHING_ADDRESS_A")?
-    } else {
-        env::var("SLASHING_ADDRESS_B")?
-    };
-
-    let slashing_address = slashing_address.parse::<Address>()?;
-
-    let slashing = Slashing::new(slashing_address, client.clone());
+    let slashing = Slashing::new(rollup.slashing_address, client.clone());
 
     let validity_conditions = slashing
         .get_validity_conditions(U256::from(block_num))
@@ -309,23 +456,19 @@ async fn get_validity_conditions(
     let mut spvm_txs = Vec::new();
 
     for tx in validity_conditions {
-        let tx_param_tokens = decode(
-            &[ParamType::Tuple(vec![
-                ParamType::String,
-                ParamType::Address,
-                ParamType::Uint(16),
-            ])],
-            &tx.tx_content.tx_param,
-        )?;
-
         let spvm_tx = spvm_rs::Transaction {
             tx_hash: tx.tx_hash.into(),
             tx_content: TransactionContent {
                 tx_type: tx.tx_content.tx_type,
                 from: tx.tx_content.from,
-                tx_param: decode_transaction_params(tx.tx_content.tx_type, tx_param_tokens)
-                    .unwrap(),
+                tx_param: decode_tx_params(tx.tx_content.tx_type, &tx.tx_content.tx_param)?,
                 nonce: tx.tx_content.nonce,
+                // The SPVM-1 layout has no chain id, v2 transactions were signed for this rollup
+                chain_id: if tx.tx_content.tx_type & TX_FORMAT_V2 == 0 {
+                    0
+                } else {
+                    rollup.chain_id
+                },
             },
             signature: Signature::from_str(&tx.signature.to_string()).unwrap(),
         };
@@ -335,20 +478,34 @@ async fn get_validity_conditions(
     Ok(spvm_txs)
 }
 
+// Proposes the block to the SPVM contract of the rollup, then stores it with the L1 block it
+// was built on
 async fn propose_block(
     txs: Vec<TxEncoded>,
-    tx_encoded: Bytes,
-    parent_hash: [u8; 32],
-    block_number: u32,
-    appchain_a: bool,
+    transactions: Vec<Transaction>,
+    origin: L1Origin,
+    rollup: &Rollup,
+    db: &DatabaseConnection,
 ) -> Result<[u8; 32], Box<dyn std::error::Error>> {
-    let tx_hash = keccak256(&tx_encoded);
-
     let pv_key = env::var("PRIVATE_KEY")?;
     let wallet = LocalWallet::from_str(&pv_key)?.with_chain_id(31337u64);
 
-    let proposer_address = wallet.address();
-    let signature = wallet.sign_hash(tx_hash.into())?;
+    // The SPVM-1 block has no field for the state root, so the root is not proposed and L1 can't
+    // vouch for it. Only the stored block, signed over `Block::hash`, commits to it. The contract
+    // checks its own hash of the parent and the transactions, which also links the blocks it
+    // stores.
+    let mut block = spvm_rs::Block {
+        number: rollup.block_number,
+        parent_hash: H256(rollup.parent_hash),
+        transactions,
+        proposer: wallet.address(),
+        state_root: rollup.state_root,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        ..Default::default()
+    };
+    block.signature = wallet.sign_hash(block.hash())?;
+    let block_hash = block.contract_hash();
+    let proposer_signature = wallet.sign_hash(block_hash)?;
 
     let provider_url = env::var("PROVIDER")?;
     let provider = Provider::<Http>::try_from(provider_url)?;
@@ -356,17 +513,9 @@ async fn propose_block(
     let signer = SignerMiddleware::new(provider, wallet);
     let client = Arc::new(signer);
 
-    let spvm = if appchain_a {
-        env::var("SPVM_ADDRESS_A")?
-    } else {
-        env::var("SPVM_ADDRESS_B")?
-    };
-
-    let spvm_address = spvm.parse::<Address>()?;
-
     abigen!(Spvm, "contracts/SPVM.json");
 
-    let spvm = Spvm::new(spvm_address, client.clone());
+    let spvm = Spvm::new(rollup.spvm_address, client.clone());
 
     // convert txs to Vec<SpvmTransaction>
     let txs = txs
@@ -391,37 +540,24 @@ async fn propose_block(
     }
 
     println!("block_num: {:?}", block_num);
+    println!("state_root: {:?}", rollup.state_root);
     println!("txs: {:?}", txs);
 
     let _ = spvm
         .propose_block(Block {
             transactions: txs,
-            block_hash: tx_hash,
-            parent_hash,
-            block_number,
-            proposer: proposer_address,
-            proposer_signature: signature.to_vec().into(),
+            block_hash: block_hash.0,
+            parent_hash: rollup.parent_hash,
+            block_number: rollup.block_number,
+            proposer: block.proposer,
+            proposer_signature: proposer_signature.to_vec().into(),
         })
         .send()
         .await?
         .await?;
 
-    Ok(tx_hash)
-}
+    insert_block(&block, &rollup.appchain(db)).await?;
+    set_l1_origin(block.number, origin, &rollup.appchain(db)).await?;
 
-fn encode_transactions(txs: &[Transaction]) -> Bytes {
-    let tokens = Token::Array(
-        txs.iter()
-            .map(|tx| {
-                let encoded_content = encode_tx_content(&tx.tx_content);
-                Token::Tuple(vec![
-                    Token::Bytes(encoded_content.to_vec()),
-                    Token::Bytes(tx.tx_hash.as_bytes().to_vec()),
-                    Token::Bytes(tx.signature.to_vec()),
-                ])
-            })
-            .collect(),
-    );
-
-    encode(&[tokens]).into()
+    Ok(block_hash.0)
 }
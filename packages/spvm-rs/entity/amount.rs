@@ -0,0 +1,75 @@
+use std::fmt;
+use std::ops::Deref;
+
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{ArrayType, Nullable, ValueType, ValueTypeErr};
+use sea_orm::{ColIdx, TryGetError, TryGetable};
+
+/// A uint256 amount as a decimal string.
+///
+/// The column is TEXT on SQLite and NUMERIC(78,0) on Postgres, see the numeric_amounts migration,
+/// so it is read from either. Writes cast the string to `decimal_text`, which the column
+/// attributes name as `save_as`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Amount(pub String);
+
+impl Deref for Amount {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for Amount {
+    fn from(amount: String) -> Self {
+        Amount(amount)
+    }
+}
+
+impl From<Amount> for Value {
+    fn from(amount: Amount) -> Self {
+        amount.0.into()
+    }
+}
+
+impl TryGetable for Amount {
+    fn try_get_by<I: ColIdx>(res: &QueryResult, idx: I) -> Result<Self, TryGetError> {
+        match String::try_get_by(res, idx) {
+            Ok(amount) => Ok(Amount(amount)),
+            Err(TryGetError::DbErr(_)) => BigDecimal::try_get_by(res, idx)
+                .map(|amount| Amount(amount.with_scale(0).to_string())),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl ValueType for Amount {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        <String as ValueType>::try_from(v).map(Amount)
+    }
+
+    fn type_name() -> String {
+        "Amount".to_owned()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Text
+    }
+}
+
+impl Nullable for Amount {
+    fn null() -> Value {
+        Value::String(None)
+    }
+}
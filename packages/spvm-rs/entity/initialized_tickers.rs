@@ -2,12 +2,23 @@
 
 use sea_orm::entity::prelude::*;
 
+use crate::Amount;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "initialized_tickers")]
 pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub chain_id: i64,
     #[sea_orm(primary_key, auto_increment = false, column_type = "Text")]
     pub ticker: String,
     pub is_initialized: bool,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub mint_authority: Option<String>,
+    pub decimals: i32,
+    #[sea_orm(column_type = "Text")]
+    pub name: String,
+    #[sea_orm(column_type = "Text", save_as = "decimal_text")]
+    pub total_supply: Amount,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
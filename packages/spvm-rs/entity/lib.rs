@@ -2,6 +2,14 @@
 
 pub mod prelude;
 
+mod amount;
+pub use amount::Amount;
+
+pub mod allowances;
+pub mod block_transactions;
+pub mod blocks;
 pub mod initialized_tickers;
 pub mod nonces;
+pub mod receipts;
 pub mod state;
+pub mod undo_journals;
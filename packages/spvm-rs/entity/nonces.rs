@@ -5,9 +5,11 @@ use sea_orm::entity::prelude::*;
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "nonces")]
 pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub chain_id: i64,
     #[sea_orm(primary_key, auto_increment = false, column_type = "Text")]
     pub owner_address: String,
-    pub nonce: i32,
+    pub nonce: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
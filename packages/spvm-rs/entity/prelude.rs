@@ -1,5 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+pub use super::allowances::Entity as Allowances;
+pub use super::block_transactions::Entity as BlockTransactions;
+pub use super::blocks::Entity as Blocks;
 pub use super::initialized_tickers::Entity as InitializedTickers;
 pub use super::nonces::Entity as Nonces;
+pub use super::receipts::Entity as Receipts;
 pub use super::state::Entity as State;
+pub use super::undo_journals::Entity as UndoJournals;
@@ -2,22 +2,27 @@
 
 use sea_orm::entity::prelude::*;
 
+use crate::Amount;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "state")]
 pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub chain_id: i64,
     #[sea_orm(primary_key, auto_increment = false, column_type = "Text")]
     pub ticker: String,
     #[sea_orm(primary_key, auto_increment = false, column_type = "Text")]
     pub owner_address: String,
-    pub amount: i32,
+    #[sea_orm(column_type = "Text", save_as = "decimal_text")]
+    pub amount: Amount,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
         belongs_to = "super::initialized_tickers::Entity",
-        from = "Column::Ticker",
-        to = "super::initialized_tickers::Column::Ticker",
+        from = "(Column::ChainId, Column::Ticker)",
+        to = "(super::initialized_tickers::Column::ChainId, super::initialized_tickers::Column::Ticker)",
         on_update = "NoAction",
         on_delete = "NoAction"
     )]
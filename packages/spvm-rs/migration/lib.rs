@@ -1,12 +1,36 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_tables;
+mod m20261018_000001_widen_state_amount;
+mod m20261018_000002_add_ticker_metadata;
+mod m20261018_000003_create_allowances;
+mod m20261018_000004_add_chain_id;
+mod m20261018_000005_create_blocks;
+mod m20261018_000006_create_receipts;
+mod m20261018_000007_create_undo_journals;
+mod m20261018_000008_expand_address_keys;
+mod m20261018_000009_widen_nonces;
+mod m20261018_000010_numeric_amounts;
+mod m20261018_000011_add_block_contract_hash;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_tables::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_tables::Migration),
+            Box::new(m20261018_000001_widen_state_amount::Migration),
+            Box::new(m20261018_000002_add_ticker_metadata::Migration),
+            Box::new(m20261018_000003_create_allowances::Migration),
+            Box::new(m20261018_000004_add_chain_id::Migration),
+            Box::new(m20261018_000005_create_blocks::Migration),
+            Box::new(m20261018_000006_create_receipts::Migration),
+            Box::new(m20261018_000007_create_undo_journals::Migration),
+            Box::new(m20261018_000008_expand_address_keys::Migration),
+            Box::new(m20261018_000009_widen_nonces::Migration),
+            Box::new(m20261018_000010_numeric_amounts::Migration),
+            Box::new(m20261018_000011_add_block_contract_hash::Migration),
+        ]
     }
 }
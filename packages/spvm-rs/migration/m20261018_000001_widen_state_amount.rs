@@ -0,0 +1,116 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Balances are uint256 values, which no SQL integer type can hold, so `state.amount` is moved
+// from a 0..=65535 integer to a text column holding the decimal representation.
+//
+// SQLite, which backs the services and tests, has no type for them: a NUMERIC column there stores
+// anything beyond 64 bits as a lossy REAL, while TEXT keeps every amount exact. Amounts are parsed
+// into `U256` and computed on in Rust, and always written in canonical form (no sign, no leading
+// zeros), so equality on the text works. Postgres moves them on to NUMERIC(78,0) in the
+// numeric_amounts migration.
+//
+// The column is rebuilt (add, copy, drop, rename) rather than altered in place because SQLite
+// cannot change the type of an existing column.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(State::Table)
+                    .add_column(
+                        ColumnDef::new(State::AmountText)
+                            .text()
+                            .not_null()
+                            .default("0"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(State::Table)
+                    .value(State::AmountText, Expr::cust("CAST(amount AS TEXT)"))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(State::Table)
+                    .drop_column(State::Amount)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(State::Table)
+                    .rename_column(State::AmountText, State::Amount)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(State::Table)
+                    .add_column(
+                        ColumnDef::new(State::AmountInteger)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(State::Table)
+                    .value(State::AmountInteger, Expr::cust("CAST(amount AS INTEGER)"))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(State::Table)
+                    .drop_column(State::Amount)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(State::Table)
+                    .rename_column(State::AmountInteger, State::Amount)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum State {
+    Table,
+    Amount,
+    AmountText,
+    AmountInteger,
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Tickers created before this migration have no known creator, so they get no mint authority
+// and can't be issued further. Their total supply is backfilled from the balances. Those are
+// decimal text since the previous migration, and are summed here rather than in SQL, which
+// would have to go through a 64-bit integer.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(InitializedTickers::Table)
+                    .add_column(ColumnDef::new(InitializedTickers::MintAuthority).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(InitializedTickers::Table)
+                    .add_column(
+                        ColumnDef::new(InitializedTickers::Decimals)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(InitializedTickers::Table)
+                    .add_column(
+                        ColumnDef::new(InitializedTickers::Name)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(InitializedTickers::Table)
+                    .add_column(
+                        ColumnDef::new(InitializedTickers::TotalSupply)
+                            .text()
+                            .not_null()
+                            .default("0"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(InitializedTickers::Table)
+                    .value(
+                        InitializedTickers::Name,
+                        Expr::col(InitializedTickers::Ticker),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        for (ticker, total_supply) in total_supplies(manager).await? {
+            manager
+                .exec_stmt(
+                    Query::update()
+                        .table(InitializedTickers::Table)
+                        .value(InitializedTickers::TotalSupply, total_supply.to_string())
+                        .and_where(Expr::col(InitializedTickers::Ticker).eq(ticker))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            InitializedTickers::TotalSupply,
+            InitializedTickers::Name,
+            InitializedTickers::Decimals,
+            InitializedTickers::MintAuthority,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(InitializedTickers::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+// Balances were uint16 until now, so no sum comes anywhere near overflowing a u128.
+async fn total_supplies(manager: &SchemaManager<'_>) -> Result<BTreeMap<String, u128>, DbErr> {
+    let db = manager.get_connection();
+    let select = Query::select()
+        .columns([State::Ticker, State::Amount])
+        .from(State::Table)
+        .to_owned();
+
+    let mut total_supplies = BTreeMap::new();
+    for row in db
+        .query_all(db.get_database_backend().build(&select))
+        .await?
+    {
+        let ticker: String = row.try_get("", "ticker")?;
+        let amount: String = row.try_get("", "amount")?;
+        let amount: u128 = amount.parse().map_err(|_| {
+            DbErr::Migration(format!("Invalid amount {} of ticker {}", amount, ticker))
+        })?;
+        *total_supplies.entry(ticker).or_default() += amount;
+    }
+
+    Ok(total_supplies)
+}
+
+#[derive(DeriveIden)]
+enum State {
+    Table,
+    Ticker,
+    Amount,
+}
+
+#[derive(DeriveIden)]
+enum InitializedTickers {
+    Table,
+    Ticker,
+    MintAuthority,
+    Decimals,
+    Name,
+    TotalSupply,
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Allowances::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Allowances::Ticker).string().not_null())
+                    .col(ColumnDef::new(Allowances::OwnerAddress).string().not_null())
+                    .col(
+                        ColumnDef::new(Allowances::SpenderAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Allowances::Amount)
+                            .text()
+                            .not_null()
+                            .default("0"),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk_allowances_ticker_owneraddress_spenderaddress")
+                            .col(Allowances::Ticker)
+                            .col(Allowances::OwnerAddress)
+                            .col(Allowances::SpenderAddress),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_allowances_ticker")
+                            .from(Allowances::Table, Allowances::Ticker)
+                            .to(InitializedTickers::Table, InitializedTickers::Ticker)
+                            .on_delete(ForeignKeyAction::NoAction)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Allowances::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Allowances {
+    Table,
+    Ticker,
+    OwnerAddress,
+    SpenderAddress,
+    Amount,
+}
+
+#[derive(DeriveIden)]
+enum InitializedTickers {
+    Table,
+    Ticker,
+}
@@ -0,0 +1,418 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Every appchain gets its own namespace in the SPVM tables, keyed by `chain_id`. The column is
+// part of the primary keys, which neither SQLite nor Postgres can change in place, so each table
+// is rebuilt: the old one is renamed, the new one created and the rows copied over.
+//
+// Rows written before this migration belong to the appchain in `APPCHAIN_ID`, the one the
+// enforcer runs with. Services that run several appchains don't set it, so it has to be given
+// for the migration if their database has rows already, e.g. `APPCHAIN_ID=1 cargo run`. Going
+// down only keeps the rows of that appchain.
+const TABLES: [&str; 4] = ["allowances", "state", "nonces", "initialized_tickers"];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rename_to_old(manager).await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(InitializedTickers::Table)
+                    .col(
+                        ColumnDef::new(InitializedTickers::ChainId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(InitializedTickers::Ticker).string().not_null())
+                    .col(
+                        ColumnDef::new(InitializedTickers::IsInitialized)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(InitializedTickers::MintAuthority).text())
+                    .col(
+                        ColumnDef::new(InitializedTickers::Decimals)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(InitializedTickers::Name)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(InitializedTickers::TotalSupply)
+                            .text()
+                            .not_null()
+                            .default("0"),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk_initialized_tickers_chainid_ticker")
+                            .col(InitializedTickers::ChainId)
+                            .col(InitializedTickers::Ticker),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(State::Table)
+                    .col(ColumnDef::new(State::ChainId).big_integer().not_null())
+                    .col(ColumnDef::new(State::Ticker).string().not_null())
+                    .col(ColumnDef::new(State::OwnerAddress).string().not_null())
+                    .col(ColumnDef::new(State::Amount).text().not_null().default("0"))
+                    .primary_key(
+                        Index::create()
+                            .name("pk_state_chainid_ticker_owneraddress")
+                            .col(State::ChainId)
+                            .col(State::Ticker)
+                            .col(State::OwnerAddress),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_state_chainid_ticker")
+                            .from(State::Table, (State::ChainId, State::Ticker))
+                            .to(
+                                InitializedTickers::Table,
+                                (InitializedTickers::ChainId, InitializedTickers::Ticker),
+                            )
+                            .on_delete(ForeignKeyAction::NoAction)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Nonces::Table)
+                    .col(ColumnDef::new(Nonces::ChainId).big_integer().not_null())
+                    .col(ColumnDef::new(Nonces::OwnerAddress).string().not_null())
+                    .col(ColumnDef::new(Nonces::Nonce).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk_nonces_chainid_owneraddress")
+                            .col(Nonces::ChainId)
+                            .col(Nonces::OwnerAddress),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Allowances::Table)
+                    .col(ColumnDef::new(Allowances::ChainId).big_integer().not_null())
+                    .col(ColumnDef::new(Allowances::Ticker).string().not_null())
+                    .col(ColumnDef::new(Allowances::OwnerAddress).string().not_null())
+                    .col(
+                        ColumnDef::new(Allowances::SpenderAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Allowances::Amount)
+                            .text()
+                            .not_null()
+                            .default("0"),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk_allowances_chainid_ticker_owneraddress_spenderaddress")
+                            .col(Allowances::ChainId)
+                            .col(Allowances::Ticker)
+                            .col(Allowances::OwnerAddress)
+                            .col(Allowances::SpenderAddress),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_allowances_chainid_ticker")
+                            .from(Allowances::Table, (Allowances::ChainId, Allowances::Ticker))
+                            .to(
+                                InitializedTickers::Table,
+                                (InitializedTickers::ChainId, InitializedTickers::Ticker),
+                            )
+                            .on_delete(ForeignKeyAction::NoAction)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        if has_old_rows(manager).await? {
+            let chain_id = existing_rows_chain_id()?;
+            // Tickers first, the other tables reference them
+            for table in TABLES.iter().rev() {
+                let columns = columns(table).join(", ");
+                db.execute_unprepared(&format!(
+                    "INSERT INTO {table} (chain_id, {columns}) \
+                     SELECT {chain_id}, {columns} FROM {table}_old"
+                ))
+                .await?;
+            }
+        }
+
+        drop_old(manager).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rename_to_old(manager).await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(InitializedTickers::Table)
+                    .col(
+                        ColumnDef::new(InitializedTickers::Ticker)
+                            .string()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(InitializedTickers::IsInitialized)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(InitializedTickers::MintAuthority).text())
+                    .col(
+                        ColumnDef::new(InitializedTickers::Decimals)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(InitializedTickers::Name)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(InitializedTickers::TotalSupply)
+                            .text()
+                            .not_null()
+                            .default("0"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(State::Table)
+                    .col(ColumnDef::new(State::Ticker).string().not_null())
+                    .col(ColumnDef::new(State::OwnerAddress).string().not_null())
+                    .col(ColumnDef::new(State::Amount).text().not_null().default("0"))
+                    .primary_key(
+                        Index::create()
+                            .name("pk_state_ticker_owneraddress")
+                            .col(State::Ticker)
+                            .col(State::OwnerAddress),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_state_ticker")
+                            .from(State::Table, State::Ticker)
+                            .to(InitializedTickers::Table, InitializedTickers::Ticker)
+                            .on_delete(ForeignKeyAction::NoAction)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Nonces::Table)
+                    .col(ColumnDef::new(Nonces::OwnerAddress).string().primary_key())
+                    .col(ColumnDef::new(Nonces::Nonce).integer().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Allowances::Table)
+                    .col(ColumnDef::new(Allowances::Ticker).string().not_null())
+                    .col(ColumnDef::new(Allowances::OwnerAddress).string().not_null())
+                    .col(
+                        ColumnDef::new(Allowances::SpenderAddress)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Allowances::Amount)
+                            .text()
+                            .not_null()
+                            .default("0"),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk_allowances_ticker_owneraddress_spenderaddress")
+                            .col(Allowances::Ticker)
+                            .col(Allowances::OwnerAddress)
+                            .col(Allowances::SpenderAddress),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_allowances_ticker")
+                            .from(Allowances::Table, Allowances::Ticker)
+                            .to(InitializedTickers::Table, InitializedTickers::Ticker)
+                            .on_delete(ForeignKeyAction::NoAction)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let db = manager.get_connection();
+        if has_old_rows(manager).await? {
+            let chain_id = existing_rows_chain_id()?;
+            for table in TABLES.iter().rev() {
+                let columns = columns(table).join(", ");
+                db.execute_unprepared(&format!(
+                    "INSERT INTO {table} ({columns}) SELECT {columns} FROM {table}_old \
+                     WHERE chain_id = {chain_id}"
+                ))
+                .await?;
+            }
+        }
+
+        drop_old(manager).await
+    }
+}
+
+// Columns shared by both versions of `table`
+fn columns(table: &str) -> &'static [&'static str] {
+    match table {
+        "initialized_tickers" => &[
+            "ticker",
+            "is_initialized",
+            "mint_authority",
+            "decimals",
+            "name",
+            "total_supply",
+        ],
+        "state" => &["ticker", "owner_address", "amount"],
+        "nonces" => &["owner_address", "nonce"],
+        "allowances" => &["ticker", "owner_address", "spender_address", "amount"],
+        _ => unreachable!(),
+    }
+}
+
+fn existing_rows_chain_id() -> Result<u64, DbErr> {
+    let chain_id = std::env::var("APPCHAIN_ID").map_err(|_| {
+        DbErr::Migration(
+            "APPCHAIN_ID must be set to the appchain of the existing SPVM state".into(),
+        )
+    })?;
+
+    match chain_id.parse() {
+        Ok(0) | Err(_) => Err(DbErr::Migration(format!(
+            "Invalid APPCHAIN_ID {}, must be a non-zero u64",
+            chain_id
+        ))),
+        Ok(chain_id) => Ok(chain_id),
+    }
+}
+
+async fn has_old_rows(manager: &SchemaManager<'_>) -> Result<bool, DbErr> {
+    let db = manager.get_connection();
+    for table in TABLES {
+        let select = Query::select()
+            .expr(Expr::val(1))
+            .from(Alias::new(format!("{}_old", table)))
+            .limit(1)
+            .to_owned();
+        if db
+            .query_one(db.get_database_backend().build(&select))
+            .await?
+            .is_some()
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+async fn rename_to_old(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    for table in TABLES {
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(Alias::new(table), Alias::new(format!("{}_old", table)))
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Referencing tables go first
+async fn drop_old(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    for table in TABLES {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(Alias::new(format!("{}_old", table)))
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[derive(DeriveIden)]
+enum InitializedTickers {
+    Table,
+    ChainId,
+    Ticker,
+    IsInitialized,
+    MintAuthority,
+    Decimals,
+    Name,
+    TotalSupply,
+}
+
+#[derive(DeriveIden)]
+enum State {
+    Table,
+    ChainId,
+    Ticker,
+    OwnerAddress,
+    Amount,
+}
+
+#[derive(DeriveIden)]
+enum Nonces {
+    Table,
+    ChainId,
+    OwnerAddress,
+    Nonce,
+}
+
+#[derive(DeriveIden)]
+enum Allowances {
+    Table,
+    ChainId,
+    Ticker,
+    OwnerAddress,
+    SpenderAddress,
+    Amount,
+}
@@ -0,0 +1,134 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Blocks are keyed by number within their appchain. Hashes, addresses and signatures are stored
+// as 0x-prefixed lowercase hex, and transactions as the hex of their ABI encoding.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Blocks::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Blocks::ChainId).big_integer().not_null())
+                    .col(ColumnDef::new(Blocks::Number).big_integer().not_null())
+                    .col(ColumnDef::new(Blocks::Hash).text().not_null())
+                    .col(ColumnDef::new(Blocks::ParentHash).text().not_null())
+                    .col(ColumnDef::new(Blocks::Proposer).text().not_null())
+                    .col(ColumnDef::new(Blocks::Signature).text().not_null())
+                    .col(ColumnDef::new(Blocks::StateRoot).text().not_null())
+                    .col(ColumnDef::new(Blocks::Timestamp).big_integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk_blocks_chainid_number")
+                            .col(Blocks::ChainId)
+                            .col(Blocks::Number),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_blocks_chainid_hash")
+                    .table(Blocks::Table)
+                    .col(Blocks::ChainId)
+                    .col(Blocks::Hash)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(BlockTransactions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(BlockTransactions::ChainId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(BlockTransactions::BlockNumber)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(BlockTransactions::Position)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(BlockTransactions::TxHash).text().not_null())
+                    .col(ColumnDef::new(BlockTransactions::Encoded).text().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk_block_transactions_chainid_blocknumber_position")
+                            .col(BlockTransactions::ChainId)
+                            .col(BlockTransactions::BlockNumber)
+                            .col(BlockTransactions::Position),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_block_transactions_chainid_blocknumber")
+                            .from(
+                                BlockTransactions::Table,
+                                (BlockTransactions::ChainId, BlockTransactions::BlockNumber),
+                            )
+                            .to(Blocks::Table, (Blocks::ChainId, Blocks::Number))
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_block_transactions_chainid_txhash")
+                    .table(BlockTransactions::Table)
+                    .col(BlockTransactions::ChainId)
+                    .col(BlockTransactions::TxHash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(BlockTransactions::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Blocks::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Blocks {
+    Table,
+    ChainId,
+    Number,
+    Hash,
+    ParentHash,
+    Proposer,
+    Signature,
+    StateRoot,
+    Timestamp,
+}
+
+#[derive(DeriveIden)]
+enum BlockTransactions {
+    Table,
+    ChainId,
+    BlockNumber,
+    Position,
+    TxHash,
+    Encoded,
+}
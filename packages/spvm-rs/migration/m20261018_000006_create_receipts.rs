@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// A transaction can have several receipts: a block that fails is rolled back, but the receipt of
+// its failing transaction is kept, and the transaction may succeed in a later block. Receipts
+// get their own id rather than being keyed by block and position.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Receipts::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Receipts::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Receipts::ChainId).big_integer().not_null())
+                    .col(ColumnDef::new(Receipts::TxHash).text().not_null())
+                    .col(ColumnDef::new(Receipts::Sender).text().not_null())
+                    .col(ColumnDef::new(Receipts::BlockNumber).big_integer().not_null())
+                    .col(ColumnDef::new(Receipts::Position).integer().not_null())
+                    .col(ColumnDef::new(Receipts::Success).boolean().not_null())
+                    .col(ColumnDef::new(Receipts::ErrorCode).text())
+                    .col(ColumnDef::new(Receipts::ErrorMessage).text())
+                    .col(
+                        ColumnDef::new(Receipts::BalanceChanges)
+                            .text()
+                            .not_null()
+                            .default("[]"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        for (name, column) in [
+            ("idx_receipts_chainid_txhash", Receipts::TxHash),
+            ("idx_receipts_chainid_sender", Receipts::Sender),
+            ("idx_receipts_chainid_blocknumber", Receipts::BlockNumber),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(name)
+                        .table(Receipts::Table)
+                        .col(Receipts::ChainId)
+                        .col(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Receipts::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Receipts {
+    Table,
+    Id,
+    ChainId,
+    TxHash,
+    Sender,
+    BlockNumber,
+    Position,
+    Success,
+    ErrorCode,
+    ErrorMessage,
+    BalanceChanges,
+}
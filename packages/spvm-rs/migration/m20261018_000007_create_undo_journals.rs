@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Every executed block gets an undo journal, so the state can be rolled back when the L1 blocks
+// it was derived from are reorged out. Blocks record the L1 block they were built on to detect
+// that; blocks stored before this migration have none and are assumed final.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UndoJournals::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UndoJournals::ChainId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UndoJournals::BlockNumber)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UndoJournals::Entries).text().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk_undo_journals_chainid_blocknumber")
+                            .col(UndoJournals::ChainId)
+                            .col(UndoJournals::BlockNumber),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blocks::Table)
+                    .add_column(ColumnDef::new(Blocks::L1BlockNumber).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blocks::Table)
+                    .add_column(ColumnDef::new(Blocks::L1BlockHash).text())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [Blocks::L1BlockHash, Blocks::L1BlockNumber] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Blocks::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .drop_table(Table::drop().table(UndoJournals::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UndoJournals {
+    Table,
+    ChainId,
+    BlockNumber,
+    Entries,
+}
+
+#[derive(DeriveIden)]
+enum Blocks {
+    Table,
+    L1BlockNumber,
+    L1BlockHash,
+}
@@ -0,0 +1,331 @@
+use std::collections::BTreeSet;
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Balances and nonces used to be keyed by the `Display` of the owner's address, which abbreviates
+// it to `0x1234…abcd`, while everything written since uses the full `{:#x}` form. The abbreviated
+// keys can't be looked up any more, and can't be expanded from the key alone, so each one is
+// rewritten to the single full address it matches among those the database knows of. Rows whose
+// key matches none or several of them, or whose rewrite would collide with a row already keyed by
+// the full address, are moved to `archived_address_keys` instead, so they can be recovered by hand
+// or from L1 without blocking the services from starting.
+const KEY_TABLES: [KeyTable; 2] = [
+    KeyTable {
+        name: "state",
+        ticker: true,
+        value: "amount",
+    },
+    KeyTable {
+        name: "nonces",
+        ticker: false,
+        value: "nonce",
+    },
+];
+
+struct KeyTable {
+    name: &'static str,
+    // Whether the rows are keyed by ticker too
+    ticker: bool,
+    value: &'static str,
+}
+
+// Columns holding full addresses the abbreviated keys are matched against
+const ADDRESS_COLUMNS: [(&str, &str); 7] = [
+    ("state", "owner_address"),
+    ("nonces", "owner_address"),
+    ("allowances", "owner_address"),
+    ("allowances", "spender_address"),
+    ("initialized_tickers", "mint_authority"),
+    ("receipts", "sender"),
+    ("blocks", "proposer"),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ArchivedAddressKeys::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ArchivedAddressKeys::SourceTable)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ArchivedAddressKeys::ChainId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ArchivedAddressKeys::Ticker).text())
+                    .col(
+                        ColumnDef::new(ArchivedAddressKeys::OwnerAddress)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ArchivedAddressKeys::Value).text().not_null())
+                    .col(
+                        ColumnDef::new(ArchivedAddressKeys::Reason)
+                            .text()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let mut known = BTreeSet::new();
+        for (table, column) in ADDRESS_COLUMNS {
+            known.extend(
+                select_addresses(manager, table, column)
+                    .await?
+                    .into_iter()
+                    .filter(|address| is_full_address(address)),
+            );
+        }
+
+        for table in &KEY_TABLES {
+            for key in select_addresses(manager, table.name, "owner_address").await? {
+                let Some((prefix, suffix)) = abbreviated_parts(&key) else {
+                    continue;
+                };
+
+                let mut matches = known
+                    .iter()
+                    .filter(|address| address[2..6] == *prefix && address[38..] == *suffix);
+                let address = match (matches.next(), matches.next()) {
+                    (Some(address), None) => Ok(address.as_str()),
+                    (None, _) => Err("no known address matches the key"),
+                    (Some(_), Some(_)) => Err("several known addresses match the key"),
+                };
+
+                for row in select_rows(manager, table, &key).await? {
+                    let reason = match address {
+                        Ok(address) if row_exists(manager, table, &row, address).await? => {
+                            "the full address already has a row"
+                        }
+                        Ok(address) => {
+                            manager
+                                .exec_stmt(
+                                    row_filter(
+                                        Query::update().table(Alias::new(table.name)),
+                                        &row,
+                                        &key,
+                                    )
+                                    .value(Alias::new("owner_address"), address)
+                                    .to_owned(),
+                                )
+                                .await?;
+                            continue;
+                        }
+                        Err(reason) => reason,
+                    };
+
+                    archive(manager, table, &row, reason).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The full keys are the only ones the executor reads, so they are kept going down.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(ArchivedAddressKeys::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+// Row of a key table, with its value as text
+struct Row {
+    chain_id: i64,
+    ticker: Option<String>,
+    owner_address: String,
+    value: String,
+}
+
+async fn select_rows(
+    manager: &SchemaManager<'_>,
+    table: &KeyTable,
+    key: &str,
+) -> Result<Vec<Row>, DbErr> {
+    let db = manager.get_connection();
+    let mut select = Query::select();
+    select
+        .column(Alias::new("chain_id"))
+        .expr_as(
+            Expr::col(Alias::new(table.value)).cast_as(Alias::new("text")),
+            Alias::new("value"),
+        )
+        .from(Alias::new(table.name))
+        .and_where(Expr::col(Alias::new("owner_address")).eq(key));
+    if table.ticker {
+        select.column(Alias::new("ticker"));
+    }
+
+    db.query_all(db.get_database_backend().build(&select))
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(Row {
+                chain_id: row.try_get("", "chain_id")?,
+                ticker: if table.ticker {
+                    row.try_get("", "ticker")?
+                } else {
+                    None
+                },
+                owner_address: key.to_owned(),
+                value: row.try_get("", "value")?,
+            })
+        })
+        .collect()
+}
+
+// Narrows `statement` down to the row keyed like `row`, but owned by `owner_address`
+fn row_filter<'a, T: ConditionalStatement>(
+    statement: &'a mut T,
+    row: &Row,
+    owner_address: &str,
+) -> &'a mut T {
+    statement
+        .and_where(Expr::col(Alias::new("chain_id")).eq(row.chain_id))
+        .and_where(Expr::col(Alias::new("owner_address")).eq(owner_address));
+    if let Some(ticker) = &row.ticker {
+        statement.and_where(Expr::col(Alias::new("ticker")).eq(ticker.as_str()));
+    }
+
+    statement
+}
+
+async fn row_exists(
+    manager: &SchemaManager<'_>,
+    table: &KeyTable,
+    row: &Row,
+    address: &str,
+) -> Result<bool, DbErr> {
+    let db = manager.get_connection();
+    let select = row_filter(
+        Query::select()
+            .column(Alias::new("owner_address"))
+            .from(Alias::new(table.name)),
+        row,
+        address,
+    )
+    .to_owned();
+
+    Ok(db
+        .query_one(db.get_database_backend().build(&select))
+        .await?
+        .is_some())
+}
+
+async fn archive(
+    manager: &SchemaManager<'_>,
+    table: &KeyTable,
+    row: &Row,
+    reason: &str,
+) -> Result<(), DbErr> {
+    eprintln!(
+        "Archiving {}.owner_address {} of chain {}{}: {}",
+        table.name,
+        row.owner_address,
+        row.chain_id,
+        row.ticker
+            .as_ref()
+            .map(|ticker| format!(" and ticker {}", ticker))
+            .unwrap_or_default(),
+        reason
+    );
+
+    manager
+        .exec_stmt(
+            Query::insert()
+                .into_table(ArchivedAddressKeys::Table)
+                .columns([
+                    ArchivedAddressKeys::SourceTable,
+                    ArchivedAddressKeys::ChainId,
+                    ArchivedAddressKeys::Ticker,
+                    ArchivedAddressKeys::OwnerAddress,
+                    ArchivedAddressKeys::Value,
+                    ArchivedAddressKeys::Reason,
+                ])
+                .values_panic([
+                    table.name.into(),
+                    row.chain_id.into(),
+                    row.ticker.clone().into(),
+                    row.owner_address.as_str().into(),
+                    row.value.as_str().into(),
+                    reason.into(),
+                ])
+                .to_owned(),
+        )
+        .await?;
+
+    manager
+        .exec_stmt(
+            row_filter(
+                Query::delete().from_table(Alias::new(table.name)),
+                row,
+                &row.owner_address,
+            )
+            .to_owned(),
+        )
+        .await
+}
+
+async fn select_addresses(
+    manager: &SchemaManager<'_>,
+    table: &str,
+    column: &str,
+) -> Result<Vec<String>, DbErr> {
+    let db = manager.get_connection();
+    let select = Query::select()
+        .distinct()
+        .column(Alias::new(column))
+        .from(Alias::new(table))
+        .and_where(Expr::col(Alias::new(column)).is_not_null())
+        .to_owned();
+
+    db.query_all(db.get_database_backend().build(&select))
+        .await?
+        .into_iter()
+        .map(|row| row.try_get::<String>("", column))
+        .collect()
+}
+
+fn is_full_address(address: &str) -> bool {
+    address.len() == 42
+        && address.starts_with("0x")
+        && address[2..]
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+// `0x1234…abcd` is split into `1234` and `abcd`
+fn abbreviated_parts(key: &str) -> Option<(&str, &str)> {
+    let (prefix, suffix) = key.strip_prefix("0x")?.split_once('…')?;
+    let hex = |part: &str| part.len() == 4 && part.bytes().all(|b| b.is_ascii_hexdigit());
+
+    (hex(prefix) && hex(suffix)).then_some((prefix, suffix))
+}
+
+#[derive(DeriveIden)]
+enum ArchivedAddressKeys {
+    Table,
+    SourceTable,
+    ChainId,
+    Ticker,
+    OwnerAddress,
+    Value,
+    Reason,
+}
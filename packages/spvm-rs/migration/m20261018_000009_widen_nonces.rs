@@ -0,0 +1,120 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Nonces are u32 values, which were stored in an INTEGER column as the bits of an i32, so those
+// past i32::MAX came out negative. `nonces.nonce` is moved to a BIGINT holding the nonce itself,
+// checked to be in range, and the negative ones are mapped back to the u32 they stand for.
+//
+// The column is rebuilt (add, copy, drop, rename) rather than altered in place because SQLite
+// cannot change the type of an existing column.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Nonces::Table)
+                    .add_column(
+                        ColumnDef::new(Nonces::NonceBigint)
+                            .big_integer()
+                            .not_null()
+                            .default(0)
+                            .check(Expr::col(Nonces::NonceBigint).between(0, i64::from(u32::MAX))),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Nonces::Table)
+                    .value(
+                        Nonces::NonceBigint,
+                        Expr::cust("CASE WHEN nonce < 0 THEN nonce + 4294967296 ELSE nonce END"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Nonces::Table)
+                    .drop_column(Nonces::Nonce)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Nonces::Table)
+                    .rename_column(Nonces::NonceBigint, Nonces::Nonce)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Nonces::Table)
+                    .add_column(
+                        ColumnDef::new(Nonces::NonceInteger)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Nonces::Table)
+                    .value(
+                        Nonces::NonceInteger,
+                        Expr::cust(
+                            "CASE WHEN nonce > 2147483647 THEN nonce - 4294967296 ELSE nonce END",
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Nonces::Table)
+                    .drop_column(Nonces::Nonce)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Nonces::Table)
+                    .rename_column(Nonces::NonceInteger, Nonces::Nonce)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Nonces {
+    Table,
+    Nonce,
+    NonceBigint,
+    NonceInteger,
+}
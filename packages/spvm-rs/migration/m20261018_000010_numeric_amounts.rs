@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DatabaseBackend;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// On Postgres, uint256 amounts are moved from decimal text to NUMERIC(78,0), which holds every
+// uint256 exactly and lets the database compare and sum them. SQLite has no such type, so its
+// columns stay TEXT.
+//
+// The entities keep reading and writing the amounts as decimal strings, casting them to text on
+// the way out and to `decimal_text` on the way in. On Postgres that is a domain over NUMERIC(78,0)
+// created here; SQLite doesn't know the name, but gives the cast TEXT affinity from it and so
+// leaves the strings as they are.
+const COLUMNS: [(&str, &str); 3] = [
+    ("state", "amount"),
+    ("allowances", "amount"),
+    ("initialized_tickers", "total_supply"),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DatabaseBackend::Postgres {
+            return Ok(());
+        }
+
+        let db = manager.get_connection();
+        db.execute_unprepared("CREATE DOMAIN decimal_text AS NUMERIC(78,0) CHECK (VALUE >= 0)")
+            .await?;
+        for (table, column) in COLUMNS {
+            db.execute_unprepared(&format!(
+                "ALTER TABLE {table}
+                     ALTER COLUMN {column} DROP DEFAULT,
+                     ALTER COLUMN {column} TYPE NUMERIC(78,0) USING {column}::NUMERIC(78,0),
+                     ALTER COLUMN {column} SET DEFAULT 0,
+                     ADD CHECK ({column} >= 0)"
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DatabaseBackend::Postgres {
+            return Ok(());
+        }
+
+        let db = manager.get_connection();
+        for (table, column) in COLUMNS {
+            db.execute_unprepared(&format!(
+                "ALTER TABLE {table}
+                     DROP CONSTRAINT {table}_{column}_check,
+                     ALTER COLUMN {column} DROP DEFAULT,
+                     ALTER COLUMN {column} TYPE TEXT USING {column}::TEXT,
+                     ALTER COLUMN {column} SET DEFAULT '0'"
+            ))
+            .await?;
+        }
+        db.execute_unprepared("DROP DOMAIN decimal_text").await?;
+
+        Ok(())
+    }
+}
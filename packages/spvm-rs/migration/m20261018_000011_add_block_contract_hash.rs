@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Blocks link to their parent by the hash the SPVM-1 contract gives them, which is indexed here
+// so parents can be looked up. It is derived from the parent hash and the transactions, which
+// SQL can't hash, so blocks stored before are left NULL until `index_contract_hashes` fills them.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blocks::Table)
+                    .add_column(ColumnDef::new(Blocks::ContractHash).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_blocks_chainid_contracthash")
+                    .table(Blocks::Table)
+                    .col(Blocks::ChainId)
+                    .col(Blocks::ContractHash)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_blocks_chainid_contracthash")
+                    .table(Blocks::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Blocks::Table)
+                    .drop_column(Blocks::ContractHash)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Blocks {
+    Table,
+    ChainId,
+    ContractHash,
+}
@@ -1,16 +1,97 @@
 CREATE TABLE initialized_tickers (
-	ticker TEXT PRIMARY KEY,
-	is_initialized BOOLEAN NOT NULL);
+	chain_id BIGINT NOT NULL, -- appchain the row belongs to
+	ticker TEXT NOT NULL,
+	is_initialized BOOLEAN NOT NULL,
+	mint_authority TEXT, -- address allowed to issue more supply, NULL for none
+	decimals INTEGER NOT NULL DEFAULT 0,
+	name TEXT NOT NULL DEFAULT '',
+	total_supply TEXT NOT NULL DEFAULT '0', -- uint256 as a decimal string, NUMERIC(78,0) on Postgres
+	PRIMARY KEY (chain_id, ticker));
 
 CREATE TABLE state (
+            chain_id BIGINT NOT NULL,
             ticker TEXT,
             owner_address TEXT,
-            amount INTEGER CHECK (amount >= 0 AND amount <= 65535),
-            PRIMARY KEY (ticker, owner_address),
-            FOREIGN KEY (ticker) REFERENCES initialized_tickers(ticker)
+            amount TEXT NOT NULL DEFAULT '0', -- uint256 balance as a decimal string, NUMERIC(78,0) on Postgres
+            PRIMARY KEY (chain_id, ticker, owner_address),
+            FOREIGN KEY (chain_id, ticker) REFERENCES initialized_tickers(chain_id, ticker)
         );
 
 CREATE TABLE nonces (
-            owner_address TEXT PRIMARY KEY,
-            nonce INTEGER NOT NULL CHECK (nonce >= 0 AND nonce <= 4294967295)
+            chain_id BIGINT NOT NULL,
+            owner_address TEXT,
+            nonce BIGINT NOT NULL CHECK (nonce >= 0 AND nonce <= 4294967295),
+            PRIMARY KEY (chain_id, owner_address)
+        );
+
+CREATE TABLE allowances (
+            chain_id BIGINT NOT NULL,
+            ticker TEXT,
+            owner_address TEXT,
+            spender_address TEXT,
+            amount TEXT NOT NULL DEFAULT '0', -- uint256 allowance as a decimal string, NUMERIC(78,0) on Postgres
+            PRIMARY KEY (chain_id, ticker, owner_address, spender_address),
+            FOREIGN KEY (chain_id, ticker) REFERENCES initialized_tickers(chain_id, ticker)
+        );
+
+CREATE TABLE blocks (
+            chain_id BIGINT NOT NULL,
+            number BIGINT NOT NULL,
+            hash TEXT NOT NULL, -- Block::hash, 0x-prefixed hex
+            parent_hash TEXT NOT NULL, -- contract_hash of the parent, zero for the first block
+            proposer TEXT NOT NULL,
+            signature TEXT NOT NULL, -- proposer's signature over the hash
+            state_root TEXT NOT NULL, -- SPVM state root after the block
+            timestamp BIGINT NOT NULL, -- unix time in seconds
+            l1_block_number BIGINT, -- L1 block the block was built on, NULL if unknown
+            l1_block_hash TEXT,
+            contract_hash TEXT, -- hash of the block in the SPVM-1 contract, which parent_hash refers to
+            PRIMARY KEY (chain_id, number),
+            UNIQUE (chain_id, hash),
+            UNIQUE (chain_id, contract_hash)
+        );
+
+CREATE TABLE block_transactions (
+            chain_id BIGINT NOT NULL,
+            block_number BIGINT NOT NULL,
+            position INTEGER NOT NULL, -- index of the transaction in the block
+            tx_hash TEXT NOT NULL,
+            encoded TEXT NOT NULL, -- hex of the ABI-encoded transaction
+            PRIMARY KEY (chain_id, block_number, position),
+            FOREIGN KEY (chain_id, block_number) REFERENCES blocks(chain_id, number) ON DELETE CASCADE
+        );
+
+CREATE INDEX idx_block_transactions_chainid_txhash ON block_transactions (chain_id, tx_hash);
+
+CREATE TABLE receipts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chain_id BIGINT NOT NULL,
+            tx_hash TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            block_number BIGINT NOT NULL,
+            position INTEGER NOT NULL, -- index of the transaction in the block
+            success BOOLEAN NOT NULL,
+            error_code TEXT, -- SpvmError::code of a failed transaction
+            error_message TEXT,
+            balance_changes TEXT NOT NULL DEFAULT '[]' -- JSON list of {ticker, holder, before, after}
+        );
+
+CREATE INDEX idx_receipts_chainid_txhash ON receipts (chain_id, tx_hash);
+CREATE INDEX idx_receipts_chainid_sender ON receipts (chain_id, sender);
+CREATE INDEX idx_receipts_chainid_blocknumber ON receipts (chain_id, block_number);
+
+CREATE TABLE undo_journals (
+            chain_id BIGINT NOT NULL,
+            block_number BIGINT NOT NULL,
+            entries TEXT NOT NULL, -- JSON list of the state values the block overwrote
+            PRIMARY KEY (chain_id, block_number)
+        );
+
+CREATE TABLE archived_address_keys ( -- rows whose abbreviated owner key couldn't be expanded
+            source_table TEXT NOT NULL, -- `state` or `nonces`
+            chain_id BIGINT NOT NULL,
+            ticker TEXT, -- NULL for nonces
+            owner_address TEXT NOT NULL, -- the abbreviated `0x1234…abcd` key
+            value TEXT NOT NULL, -- the amount or nonce as a decimal string
+            reason TEXT NOT NULL
         );
@@ -0,0 +1,204 @@
+use sea_orm::{ConnectionTrait, DatabaseTransaction, TransactionTrait};
+
+use crate::{SeaOrmBackend, SpvmError};
+
+/// Handle on the state of one appchain in a database shared by several appchains.
+///
+/// The SPVM tables key every row by appchain id, so one database can hold the state of any
+/// number of rollups. The executor and query APIs take a handle rather than a bare connection,
+/// which makes it impossible to read or write state without saying which appchain it is for.
+///
+/// Appchains only accept transactions bound to their id unless built with
+/// [`Appchain::accept_legacy_txs`].
+pub struct Appchain<'a, C> {
+    id: u64,
+    db: &'a C,
+    legacy_txs: bool,
+}
+
+// Derives would require `C: Clone`, connections are only borrowed
+impl<C> Clone for Appchain<'_, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for Appchain<'_, C> {}
+
+impl<'a, C: ConnectionTrait> Appchain<'a, C> {
+    pub fn new(id: u64, db: &'a C) -> Self {
+        Self {
+            id,
+            db,
+            legacy_txs: false,
+        }
+    }
+
+    /// Also accepts legacy transactions, which carry no chain id. Only meant for appchains
+    /// replaying blocks of the SPVM-1 contract, which knows no other format: a legacy
+    /// transaction is valid on every appchain that accepts them.
+    pub fn accept_legacy_txs(mut self) -> Self {
+        self.legacy_txs = true;
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn db(&self) -> &'a C {
+        self.db
+    }
+
+    pub fn accepts_legacy_txs(&self) -> bool {
+        self.legacy_txs
+    }
+
+    /// The same appchain through `db`, e.g. a transaction started with [`Appchain::begin`].
+    pub fn on<'b, D: ConnectionTrait>(&self, db: &'b D) -> Appchain<'b, D> {
+        Appchain {
+            id: self.id,
+            db,
+            legacy_txs: self.legacy_txs,
+        }
+    }
+
+    /// The appchain's state as seen by the executor.
+    pub fn state(&self) -> SeaOrmBackend<'a, C> {
+        let state = SeaOrmBackend::new(self.db, self.id);
+        if self.legacy_txs {
+            state.accept_legacy_txs()
+        } else {
+            state
+        }
+    }
+}
+
+impl<C: ConnectionTrait + TransactionTrait> Appchain<'_, C> {
+    /// Starts a database transaction. Wrap it in a handle with [`Appchain::on`] to make changes
+    /// to the appchain that are committed together.
+    pub async fn begin(&self) -> Result<DatabaseTransaction, SpvmError> {
+        Ok(self.db.begin().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{Address, U256},
+    };
+
+    #[async_std::test]
+    async fn execute_chains_are_isolated() {
+        let (wallet, connection) = setup().await;
+        let other_chain = CHAIN_ID + 1;
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        t1.execute_transaction(&SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        // Nothing of chain CHAIN_ID is visible from the other chain, so the same ticker can be
+        // minted there again
+        assert_eq!(
+            TransactionContent::get_balance(
+                "ABC",
+                wallet.address(),
+                &Appchain::new(other_chain, &connection).accept_legacy_txs()
+            )
+            .await
+            .unwrap(),
+            U256::zero()
+        );
+        assert!(
+            list_tickers(&Appchain::new(other_chain, &connection).accept_legacy_txs())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        t1.execute_transaction(
+            &SCHEME,
+            &Appchain::new(other_chain, &connection).accept_legacy_txs(),
+        )
+        .await
+        .unwrap();
+
+        let t2 = create_transfer_transaction("ABC", &wallet, Address::zero(), 40, 1);
+        t2.execute_transaction(
+            &SCHEME,
+            &Appchain::new(other_chain, &connection).accept_legacy_txs(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            TransactionContent::get_balance("ABC", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap(),
+            U256::from(100)
+        );
+        assert_eq!(
+            TransactionContent::get_balance(
+                "ABC",
+                wallet.address(),
+                &Appchain::new(other_chain, &connection).accept_legacy_txs()
+            )
+            .await
+            .unwrap(),
+            U256::from(60)
+        );
+    }
+
+    #[async_std::test]
+    async fn appchains_share_a_database() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let chain_a = Appchain::new(CHAIN_ID, &connection).accept_legacy_txs();
+        let chain_b = Appchain::new(CHAIN_ID + 1, &connection).accept_legacy_txs();
+
+        let mint = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let transfer = create_transfer_transaction("ABC", &wallet, wallet2.address(), 30, 1);
+        execute_transactions(&[mint.clone(), transfer], &SCHEME, &chain_a)
+            .await
+            .unwrap();
+
+        // The transfer only succeeds on the appchain where the ticker was minted
+        let transfer = create_transfer_transaction("ABC", &wallet, wallet2.address(), 30, 0);
+        let simulation = simulate_transactions(&[transfer], &SCHEME, &chain_b)
+            .await
+            .unwrap();
+        assert!(matches!(
+            simulation.failure,
+            Some(SimulationFailure {
+                index: 0,
+                error: SpvmError::TickerNotInitialized(_)
+            })
+        ));
+
+        execute_transactions(&[mint], &SCHEME, &chain_b)
+            .await
+            .unwrap();
+        assert_eq!(list_holders("ABC", 0, 10, &chain_a).await.unwrap().len(), 2);
+        assert_eq!(
+            get_balances(wallet.address(), &chain_b).await.unwrap(),
+            vec![TokenBalance {
+                ticker: "ABC".to_string(),
+                holder: wallet.address(),
+                amount: U256::from(100),
+            }]
+        );
+        assert_eq!(
+            chain_b.state().get_nonce(wallet.address()).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            chain_a.state().get_nonce(wallet.address()).await.unwrap(),
+            2
+        );
+    }
+}
@@ -0,0 +1,481 @@
+use ::entity::{allowances, initialized_tickers, nonces, state};
+use async_trait::async_trait;
+use ethers::types::{Address, U256};
+use sea_orm::{
+    entity::*, ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, Set,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::{SpvmError, TokenMetadata};
+
+/// Storage used by the SPVM executor.
+///
+/// The executor only needs balances, allowances, nonces and ticker metadata, so any store that
+/// can answer these can be used to run transactions: a SQL database through
+/// [`SeaOrmBackend`], or a plain in-memory map through [`InMemoryBackend`].
+#[async_trait]
+pub trait StateBackend: Send + Sync {
+    /// Id of the appchain whose state this is. Transactions bound to another chain are rejected.
+    fn chain_id(&self) -> u64;
+
+    /// Whether legacy transactions are accepted. They carry no chain id, so an appchain that
+    /// accepts them also accepts those signed for any other appchain that does.
+    fn accepts_legacy_txs(&self) -> bool {
+        false
+    }
+
+    async fn get_balance(&self, ticker: &str, holder: Address) -> Result<U256, SpvmError>;
+
+    async fn set_balance(
+        &mut self,
+        ticker: &str,
+        holder: Address,
+        balance: U256,
+    ) -> Result<(), SpvmError>;
+
+    /// Amount of `ticker` that `spender` may still transfer out of `owner`'s balance.
+    async fn get_allowance(
+        &self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+    ) -> Result<U256, SpvmError>;
+
+    async fn set_allowance(
+        &mut self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), SpvmError>;
+
+    async fn get_nonce(&self, owner: Address) -> Result<u32, SpvmError>;
+
+    async fn bump_nonce(&mut self, owner: Address) -> Result<(), SpvmError>;
+
+    async fn is_ticker_initialized(&self, ticker: &str) -> Result<bool, SpvmError>;
+
+    /// Initializes `ticker` with [`TokenMetadata::new`] unless it already has metadata.
+    async fn initialize_ticker(&mut self, ticker: &str) -> Result<(), SpvmError>;
+
+    /// Returns `None` if the ticker is not initialized.
+    async fn get_token_metadata(&self, ticker: &str) -> Result<Option<TokenMetadata>, SpvmError>;
+
+    /// Stores the metadata of `metadata.ticker`, initializing the ticker if needed.
+    async fn set_token_metadata(&mut self, metadata: &TokenMetadata) -> Result<(), SpvmError>;
+}
+
+/// State kept in memory, for simulations and tests that should not need a database.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryBackend {
+    chain_id: u64,
+    legacy_txs: bool,
+    balances: HashMap<(String, Address), U256>,
+    allowances: HashMap<(String, Address, Address), U256>,
+    nonces: HashMap<Address, u32>,
+    tokens: HashMap<String, TokenMetadata>,
+}
+
+impl InMemoryBackend {
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            ..Self::default()
+        }
+    }
+
+    /// Accepts legacy transactions, see [`StateBackend::accepts_legacy_txs`].
+    pub fn accept_legacy_txs(mut self) -> Self {
+        self.legacy_txs = true;
+        self
+    }
+}
+
+#[async_trait]
+impl StateBackend for InMemoryBackend {
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn accepts_legacy_txs(&self) -> bool {
+        self.legacy_txs
+    }
+
+    async fn get_balance(&self, ticker: &str, holder: Address) -> Result<U256, SpvmError> {
+        Ok(self
+            .balances
+            .get(&(ticker.to_string(), holder))
+            .copied()
+            .unwrap_or_default())
+    }
+
+    async fn set_balance(
+        &mut self,
+        ticker: &str,
+        holder: Address,
+        balance: U256,
+    ) -> Result<(), SpvmError> {
+        self.balances.insert((ticker.to_string(), holder), balance);
+        Ok(())
+    }
+
+    async fn get_allowance(
+        &self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+    ) -> Result<U256, SpvmError> {
+        Ok(self
+            .allowances
+            .get(&(ticker.to_string(), owner, spender))
+            .copied()
+            .unwrap_or_default())
+    }
+
+    async fn set_allowance(
+        &mut self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), SpvmError> {
+        self.allowances
+            .insert((ticker.to_string(), owner, spender), amount);
+        Ok(())
+    }
+
+    async fn get_nonce(&self, owner: Address) -> Result<u32, SpvmError> {
+        Ok(self.nonces.get(&owner).copied().unwrap_or(0))
+    }
+
+    async fn bump_nonce(&mut self, owner: Address) -> Result<(), SpvmError> {
+        let nonce = self.nonces.entry(owner).or_insert(0);
+        *nonce = nonce
+            .checked_add(1)
+            .ok_or(SpvmError::NonceOverflow(owner))?;
+        Ok(())
+    }
+
+    async fn is_ticker_initialized(&self, ticker: &str) -> Result<bool, SpvmError> {
+        Ok(self.tokens.contains_key(ticker))
+    }
+
+    async fn initialize_ticker(&mut self, ticker: &str) -> Result<(), SpvmError> {
+        self.tokens
+            .entry(ticker.to_string())
+            .or_insert_with(|| TokenMetadata::new(ticker));
+        Ok(())
+    }
+
+    async fn get_token_metadata(&self, ticker: &str) -> Result<Option<TokenMetadata>, SpvmError> {
+        Ok(self.tokens.get(ticker).cloned())
+    }
+
+    async fn set_token_metadata(&mut self, metadata: &TokenMetadata) -> Result<(), SpvmError> {
+        self.tokens
+            .insert(metadata.ticker.clone(), metadata.clone());
+        Ok(())
+    }
+}
+
+/// State of one appchain, stored in the `state`, `nonces`, `initialized_tickers` and
+/// `allowances` tables under its `chain_id`.
+///
+/// `C` can be a `DatabaseConnection` or a `DatabaseTransaction`; the executor wraps a
+/// transaction so that all writes of one SPVM transaction are committed together.
+pub struct SeaOrmBackend<'a, C: ConnectionTrait> {
+    db: &'a C,
+    chain_id: u64,
+    legacy_txs: bool,
+}
+
+impl<'a, C: ConnectionTrait> SeaOrmBackend<'a, C> {
+    pub fn new(db: &'a C, chain_id: u64) -> Self {
+        Self {
+            db,
+            chain_id,
+            legacy_txs: false,
+        }
+    }
+
+    /// Accepts legacy transactions, see [`StateBackend::accepts_legacy_txs`].
+    pub fn accept_legacy_txs(mut self) -> Self {
+        self.legacy_txs = true;
+        self
+    }
+}
+
+#[async_trait]
+impl<C: ConnectionTrait> StateBackend for SeaOrmBackend<'_, C> {
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn accepts_legacy_txs(&self) -> bool {
+        self.legacy_txs
+    }
+
+    async fn get_balance(&self, ticker: &str, holder: Address) -> Result<U256, SpvmError> {
+        let balance = state::Entity::find()
+            .filter(state::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(state::Column::Ticker.eq(ticker))
+            .filter(state::Column::OwnerAddress.eq(format!("{:#x}", holder)))
+            .one(self.db)
+            .await?;
+
+        match balance {
+            Some(record) => parse_amount(&record),
+            None => Ok(U256::zero()),
+        }
+    }
+
+    async fn set_balance(
+        &mut self,
+        ticker: &str,
+        holder: Address,
+        balance: U256,
+    ) -> Result<(), SpvmError> {
+        let state = state::Entity::find()
+            .filter(state::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(state::Column::Ticker.eq(ticker))
+            .filter(state::Column::OwnerAddress.eq(format!("{:#x}", holder)))
+            .one(self.db)
+            .await?;
+
+        match state {
+            Some(record) => {
+                let mut active_record = record.into_active_model();
+                active_record.amount = Set(balance.to_string().into());
+                active_record.update(self.db).await?;
+            }
+            None => {
+                let record = state::ActiveModel {
+                    chain_id: Set(chain_column(self.chain_id)),
+                    ticker: Set(ticker.to_string()),
+                    owner_address: Set(format!("{:#x}", holder)),
+                    amount: Set(balance.to_string().into()),
+                };
+                record.insert(self.db).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_allowance(
+        &self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+    ) -> Result<U256, SpvmError> {
+        let allowance = allowances::Entity::find()
+            .filter(allowances::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(allowances::Column::Ticker.eq(ticker))
+            .filter(allowances::Column::OwnerAddress.eq(format!("{:#x}", owner)))
+            .filter(allowances::Column::SpenderAddress.eq(format!("{:#x}", spender)))
+            .one(self.db)
+            .await?;
+
+        match allowance {
+            Some(record) => U256::from_dec_str(&record.amount).map_err(|e| {
+                SpvmError::Db(DbErr::Type(format!(
+                    "Invalid allowance {:?} stored for {}: {}",
+                    record.amount, ticker, e
+                )))
+            }),
+            None => Ok(U256::zero()),
+        }
+    }
+
+    async fn set_allowance(
+        &mut self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), SpvmError> {
+        let allowance = allowances::Entity::find()
+            .filter(allowances::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(allowances::Column::Ticker.eq(ticker))
+            .filter(allowances::Column::OwnerAddress.eq(format!("{:#x}", owner)))
+            .filter(allowances::Column::SpenderAddress.eq(format!("{:#x}", spender)))
+            .one(self.db)
+            .await?;
+
+        match allowance {
+            Some(record) => {
+                let mut active_record = record.into_active_model();
+                active_record.amount = Set(amount.to_string().into());
+                active_record.update(self.db).await?;
+            }
+            None => {
+                let record = allowances::ActiveModel {
+                    chain_id: Set(chain_column(self.chain_id)),
+                    ticker: Set(ticker.to_string()),
+                    owner_address: Set(format!("{:#x}", owner)),
+                    spender_address: Set(format!("{:#x}", spender)),
+                    amount: Set(amount.to_string().into()),
+                };
+                record.insert(self.db).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_nonce(&self, owner: Address) -> Result<u32, SpvmError> {
+        let nonce = nonces::Entity::find()
+            .filter(nonces::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(nonces::Column::OwnerAddress.eq(format!("{:#x}", owner)))
+            .one(self.db)
+            .await?;
+
+        Ok(nonce.map_or(0, |record| record.nonce as u32))
+    }
+
+    async fn bump_nonce(&mut self, owner: Address) -> Result<(), SpvmError> {
+        let nonce = nonces::Entity::find()
+            .filter(nonces::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(nonces::Column::OwnerAddress.eq(format!("{:#x}", owner)))
+            .one(self.db)
+            .await?;
+
+        match nonce {
+            Some(record) => {
+                let nonce = (record.nonce as u32)
+                    .checked_add(1)
+                    .ok_or(SpvmError::NonceOverflow(owner))?;
+                let mut active_record = record.into_active_model();
+                active_record.nonce = Set(i64::from(nonce));
+                active_record.update(self.db).await?;
+            }
+            None => {
+                let record = nonces::ActiveModel {
+                    chain_id: Set(chain_column(self.chain_id)),
+                    owner_address: Set(format!("{:#x}", owner)),
+                    nonce: Set(1),
+                };
+                record.insert(self.db).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn is_ticker_initialized(&self, ticker: &str) -> Result<bool, SpvmError> {
+        let initialized = initialized_tickers::Entity::find()
+            .filter(initialized_tickers::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(initialized_tickers::Column::Ticker.eq(ticker))
+            .one(self.db)
+            .await?;
+
+        Ok(initialized.is_some_and(|record| record.is_initialized))
+    }
+
+    async fn initialize_ticker(&mut self, ticker: &str) -> Result<(), SpvmError> {
+        let initialized = initialized_tickers::Entity::find()
+            .filter(initialized_tickers::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(initialized_tickers::Column::Ticker.eq(ticker))
+            .one(self.db)
+            .await?;
+
+        match initialized {
+            Some(record) => {
+                let mut active_record = record.into_active_model();
+                active_record.is_initialized = Set(true);
+                active_record.update(self.db).await?;
+            }
+            None => {
+                self.set_token_metadata(&TokenMetadata::new(ticker)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_token_metadata(&self, ticker: &str) -> Result<Option<TokenMetadata>, SpvmError> {
+        let record = initialized_tickers::Entity::find()
+            .filter(initialized_tickers::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(initialized_tickers::Column::Ticker.eq(ticker))
+            .filter(initialized_tickers::Column::IsInitialized.eq(true))
+            .one(self.db)
+            .await?;
+
+        record.map(parse_token_metadata).transpose()
+    }
+
+    async fn set_token_metadata(&mut self, metadata: &TokenMetadata) -> Result<(), SpvmError> {
+        let record = initialized_tickers::ActiveModel {
+            chain_id: Set(chain_column(self.chain_id)),
+            ticker: Set(metadata.ticker.clone()),
+            is_initialized: Set(true),
+            mint_authority: Set(metadata
+                .mint_authority
+                .map(|authority| format!("{:#x}", authority))),
+            decimals: Set(metadata.decimals as i32),
+            name: Set(metadata.name.clone()),
+            total_supply: Set(metadata.total_supply.to_string().into()),
+        };
+
+        let existing = initialized_tickers::Entity::find()
+            .filter(initialized_tickers::Column::ChainId.eq(chain_column(self.chain_id)))
+            .filter(initialized_tickers::Column::Ticker.eq(&metadata.ticker))
+            .one(self.db)
+            .await?;
+
+        match existing {
+            Some(_) => {
+                record.update(self.db).await?;
+            }
+            None => {
+                record.insert(self.db).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn parse_amount(record: &state::Model) -> Result<U256, SpvmError> {
+    U256::from_dec_str(&record.amount).map_err(|e| {
+        SpvmError::Db(DbErr::Type(format!(
+            "Invalid amount {:?} stored for {}: {}",
+            record.amount, record.ticker, e
+        )))
+    })
+}
+
+pub(crate) fn parse_token_metadata(
+    record: initialized_tickers::Model,
+) -> Result<TokenMetadata, SpvmError> {
+    let invalid = |column: &str, e: &dyn std::fmt::Display| {
+        SpvmError::Db(DbErr::Type(format!(
+            "Invalid {} stored for {}: {}",
+            column, record.ticker, e
+        )))
+    };
+
+    let mint_authority = match &record.mint_authority {
+        Some(authority) => {
+            Some(Address::from_str(authority).map_err(|e| invalid("mint_authority", &e))?)
+        }
+        None => None,
+    };
+    let decimals = u8::try_from(record.decimals).map_err(|e| invalid("decimals", &e))?;
+    let total_supply =
+        U256::from_dec_str(&record.total_supply).map_err(|e| invalid("total_supply", &e))?;
+
+    Ok(TokenMetadata {
+        ticker: record.ticker.clone(),
+        mint_authority,
+        decimals,
+        name: record.name.clone(),
+        total_supply,
+    })
+}
+
+// Chain ids are stored in BIGINT columns, which are signed. The cast is lossless both ways.
+pub(crate) fn chain_column(chain_id: u64) -> i64 {
+    chain_id as i64
+}
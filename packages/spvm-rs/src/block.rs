@@ -0,0 +1,555 @@
+//! Blocks of an appchain and their persistent store.
+//!
+//! A block commits to its parent, its transactions and the state root after executing them
+//! through [`Block::hash`], which is what the proposer signs. Blocks link to their parent by
+//! [`Block::contract_hash`] instead, the hash the SPVM-1 contract checks and stores, so the chain
+//! of stored blocks matches the contract's. Stored blocks and their transactions can be looked up
+//! by number or either hash, so a restarted node keeps its history.
+//! Each stored block can also record the L1 block it was built on, its [`L1Origin`], so that
+//! blocks built on L1 blocks that were reorged out can be found.
+
+use ::entity::{block_transactions, blocks};
+use ethers::{
+    core::{
+        abi::{encode, Token},
+        utils::keccak256,
+    },
+    types::{Address, Signature, TxHash, H256, U256},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel, NotSet,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::backend::chain_column;
+use crate::{contract_block_hash, Appchain, SpvmError, Transaction};
+use crate::{decode_transaction, encode_transaction, transaction_token};
+
+/// A block of transactions of one appchain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Block {
+    pub number: u32,
+    /// [`Block::contract_hash`] of the parent, zero for the first block.
+    pub parent_hash: H256,
+    pub transactions: Vec<Transaction>,
+    pub proposer: Address,
+    /// The proposer's signature over [`Block::hash`].
+    pub signature: Signature,
+    /// Root of the appchain's state after executing the block, see [`crate::StateTree`].
+    pub state_root: H256,
+    /// Unix time in seconds.
+    pub timestamp: u64,
+}
+
+/// A transaction of a stored block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockTransaction {
+    pub block_number: u32,
+    /// Index of the transaction in its block.
+    pub position: u32,
+    pub transaction: Transaction,
+}
+
+/// L1 block whose state a block was derived from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L1Origin {
+    pub number: u64,
+    pub hash: H256,
+}
+
+impl Default for Block {
+    fn default() -> Self {
+        Self {
+            number: 0,
+            parent_hash: H256::zero(),
+            transactions: Vec::new(),
+            proposer: Address::zero(),
+            signature: Signature {
+                r: U256::zero(),
+                s: U256::zero(),
+                v: 0,
+            },
+            state_root: H256::zero(),
+            timestamp: 0,
+        }
+    }
+}
+
+impl Block {
+    /// `keccak256(abi.encode(number, parentHash, keccak256(abi.encode(transactions)), proposer,
+    /// stateRoot, timestamp))`, with the transactions encoded as by [`encode_transaction`].
+    ///
+    /// Everything but the signature is committed to, so the hash identifies the block.
+    pub fn hash(&self) -> H256 {
+        let transactions = Token::Array(self.transactions.iter().map(transaction_token).collect());
+
+        H256(keccak256(encode(&[
+            Token::Uint(U256::from(self.number)),
+            Token::FixedBytes(self.parent_hash.as_bytes().to_vec()),
+            Token::FixedBytes(keccak256(encode(&[transactions])).to_vec()),
+            Token::Address(self.proposer),
+            Token::FixedBytes(self.state_root.as_bytes().to_vec()),
+            Token::Uint(U256::from(self.timestamp)),
+        ])))
+    }
+
+    /// Hash of the block in the SPVM-1 contract, see [`contract_block_hash`]. Unlike
+    /// [`Block::hash`], it doesn't commit to the state root.
+    pub fn contract_hash(&self) -> H256 {
+        contract_block_hash(self.parent_hash, &self.transactions)
+    }
+
+    /// Checks that the block was signed by its proposer.
+    pub fn verify_signature(&self) -> Result<(), SpvmError> {
+        Ok(self.signature.verify(self.hash(), self.proposer)?)
+    }
+}
+
+/// Stores `block` in `appchain`'s history, with its transactions, and returns its hash.
+///
+/// Fails if the appchain already has a block with the same number or either hash.
+pub async fn insert_block<C>(block: &Block, appchain: &Appchain<'_, C>) -> Result<H256, SpvmError>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    let hash = block.hash();
+    let txn = appchain.begin().await?;
+
+    blocks::ActiveModel {
+        chain_id: Set(chain_column(appchain.id())),
+        number: Set(block.number.into()),
+        hash: Set(format!("{:#x}", hash)),
+        parent_hash: Set(format!("{:#x}", block.parent_hash)),
+        proposer: Set(format!("{:#x}", block.proposer)),
+        signature: Set(format!("0x{}", block.signature)),
+        state_root: Set(format!("{:#x}", block.state_root)),
+        timestamp: Set(i64::try_from(block.timestamp).map_err(|_| {
+            SpvmError::Decode(format!("Block timestamp {} is too large", block.timestamp))
+        })?),
+        l1_block_number: NotSet,
+        l1_block_hash: NotSet,
+        contract_hash: Set(Some(format!("{:#x}", block.contract_hash()))),
+    }
+    .insert(&txn)
+    .await?;
+
+    for (position, tx) in block.transactions.iter().enumerate() {
+        block_transactions::ActiveModel {
+            chain_id: Set(chain_column(appchain.id())),
+            block_number: Set(block.number.into()),
+            position: Set(position as i32),
+            tx_hash: Set(format!("{:#x}", tx.tx_hash)),
+            encoded: Set(format!("0x{}", hex::encode(encode_transaction(tx)))),
+        }
+        .insert(&txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(hash)
+}
+
+/// Returns the block of `appchain` with the given number, if it is stored.
+pub async fn get_block<C: ConnectionTrait>(
+    number: u32,
+    appchain: &Appchain<'_, C>,
+) -> Result<Option<Block>, SpvmError> {
+    let record = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(blocks::Column::Number.eq(i64::from(number)))
+        .one(appchain.db())
+        .await?;
+
+    load_block(record, appchain).await
+}
+
+/// Returns the block of `appchain` with the given hash, if it is stored.
+pub async fn get_block_by_hash<C: ConnectionTrait>(
+    hash: H256,
+    appchain: &Appchain<'_, C>,
+) -> Result<Option<Block>, SpvmError> {
+    let record = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(blocks::Column::Hash.eq(format!("{:#x}", hash)))
+        .one(appchain.db())
+        .await?;
+
+    load_block(record, appchain).await
+}
+
+/// Returns the block of `appchain` with the given [`Block::contract_hash`], if it is stored, like
+/// the parent of a stored block.
+pub async fn get_block_by_contract_hash<C: ConnectionTrait>(
+    contract_hash: H256,
+    appchain: &Appchain<'_, C>,
+) -> Result<Option<Block>, SpvmError> {
+    let record = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(blocks::Column::ContractHash.eq(format!("{:#x}", contract_hash)))
+        .one(appchain.db())
+        .await?;
+
+    load_block(record, appchain).await
+}
+
+/// Records the contract hash of the blocks of `appchain` stored before it was, so that
+/// [`get_block_by_contract_hash`] finds them, and returns how many there were.
+pub async fn index_contract_hashes<C: ConnectionTrait>(
+    appchain: &Appchain<'_, C>,
+) -> Result<usize, SpvmError> {
+    let records = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(blocks::Column::ContractHash.is_null())
+        .all(appchain.db())
+        .await?;
+
+    let count = records.len();
+    for record in records {
+        let Some(block) = load_block(Some(record.clone()), appchain).await? else {
+            continue;
+        };
+        let mut active_record = record.into_active_model();
+        active_record.contract_hash = Set(Some(format!("{:#x}", block.contract_hash())));
+        active_record.update(appchain.db()).await?;
+    }
+
+    Ok(count)
+}
+
+/// Returns the stored block of `appchain` with the highest number.
+pub async fn get_latest_block<C: ConnectionTrait>(
+    appchain: &Appchain<'_, C>,
+) -> Result<Option<Block>, SpvmError> {
+    let record = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_column(appchain.id())))
+        .order_by_desc(blocks::Column::Number)
+        .one(appchain.db())
+        .await?;
+
+    load_block(record, appchain).await
+}
+
+/// Returns a transaction of a stored block of `appchain` by its hash.
+pub async fn get_transaction<C: ConnectionTrait>(
+    tx_hash: TxHash,
+    appchain: &Appchain<'_, C>,
+) -> Result<Option<BlockTransaction>, SpvmError> {
+    block_transactions::Entity::find()
+        .filter(block_transactions::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(block_transactions::Column::TxHash.eq(format!("{:#x}", tx_hash)))
+        .order_by_asc(block_transactions::Column::BlockNumber)
+        .one(appchain.db())
+        .await?
+        .map(|record| parse_block_transaction(&record))
+        .transpose()
+}
+
+/// Records that block `number` of `appchain` was built on the L1 block `origin`.
+pub async fn set_l1_origin<C: ConnectionTrait>(
+    number: u32,
+    origin: L1Origin,
+    appchain: &Appchain<'_, C>,
+) -> Result<(), SpvmError> {
+    let record = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(blocks::Column::Number.eq(i64::from(number)))
+        .one(appchain.db())
+        .await?
+        .ok_or_else(|| {
+            SpvmError::Db(DbErr::RecordNotFound(format!(
+                "Block {} of appchain {}",
+                number,
+                appchain.id()
+            )))
+        })?;
+
+    let mut active_record = record.into_active_model();
+    active_record.l1_block_number = Set(Some(i64::try_from(origin.number).map_err(|_| {
+        SpvmError::Decode(format!("L1 block number {} is too large", origin.number))
+    })?));
+    active_record.l1_block_hash = Set(Some(format!("{:#x}", origin.hash)));
+    active_record.update(appchain.db()).await?;
+
+    Ok(())
+}
+
+/// Returns the L1 block that block `number` of `appchain` was built on, if it was recorded.
+pub async fn get_l1_origin<C: ConnectionTrait>(
+    number: u32,
+    appchain: &Appchain<'_, C>,
+) -> Result<Option<L1Origin>, SpvmError> {
+    let record = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(blocks::Column::Number.eq(i64::from(number)))
+        .one(appchain.db())
+        .await?;
+
+    match record {
+        Some(blocks::Model {
+            l1_block_number: Some(l1_number),
+            l1_block_hash: Some(l1_hash),
+            ..
+        }) => Ok(Some(L1Origin {
+            number: parse_column(&l1_number, "blocks.l1_block_number")?,
+            hash: parse_column(&l1_hash, "blocks.l1_block_hash")?,
+        })),
+        _ => Ok(None),
+    }
+}
+
+async fn load_block<C: ConnectionTrait>(
+    record: Option<blocks::Model>,
+    appchain: &Appchain<'_, C>,
+) -> Result<Option<Block>, SpvmError> {
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let transactions = block_transactions::Entity::find()
+        .filter(block_transactions::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(block_transactions::Column::BlockNumber.eq(record.number))
+        .order_by_asc(block_transactions::Column::Position)
+        .all(appchain.db())
+        .await?
+        .iter()
+        .map(|tx| parse_block_transaction(tx).map(|tx| tx.transaction))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(Block {
+        number: parse_column(&record.number, "blocks.number")?,
+        parent_hash: parse_column(&record.parent_hash, "blocks.parent_hash")?,
+        transactions,
+        proposer: parse_column(&record.proposer, "blocks.proposer")?,
+        signature: parse_column(&record.signature, "blocks.signature")?,
+        state_root: parse_column(&record.state_root, "blocks.state_root")?,
+        timestamp: parse_column(&record.timestamp, "blocks.timestamp")?,
+    }))
+}
+
+fn parse_block_transaction(
+    record: &block_transactions::Model,
+) -> Result<BlockTransaction, SpvmError> {
+    let encoded = record.encoded.strip_prefix("0x").unwrap_or(&record.encoded);
+    let bytes = hex::decode(encoded)
+        .map_err(|e| invalid_column("block_transactions.encoded", &record.encoded, e))?;
+
+    Ok(BlockTransaction {
+        block_number: parse_column(&record.block_number, "block_transactions.block_number")?,
+        position: parse_column(&record.position, "block_transactions.position")?,
+        transaction: decode_transaction(&bytes)
+            .map_err(|e| invalid_column("block_transactions.encoded", &record.encoded, e))?,
+    })
+}
+
+// Parses a stored value, which is either a string or an integer of another width. `column` is
+// qualified by its table.
+pub(crate) fn parse_column<T, V>(value: &V, column: &str) -> Result<T, SpvmError>
+where
+    V: ToString,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = value.to_string();
+    value.parse().map_err(|e| invalid_column(column, &value, e))
+}
+
+pub(crate) fn invalid_column(column: &str, value: &str, e: impl std::fmt::Display) -> SpvmError {
+    SpvmError::Db(DbErr::Type(format!(
+        "Invalid {} {:?} stored: {}",
+        column, value, e
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{Address, H256},
+    };
+
+    #[test]
+    fn block_hash_is_canonical() {
+        let proposer = LocalWallet::new(&mut thread_rng());
+        let tx = create_mint_transaction("ABC", &proposer, proposer.address(), 100, 0);
+        let block = create_block(&proposer, 1, H256::random(), vec![tx.clone()]);
+        block.verify_signature().unwrap();
+
+        let mut resigned = block.clone();
+        resigned.signature = LocalWallet::new(&mut thread_rng())
+            .sign_hash(block.hash())
+            .unwrap();
+        assert_eq!(resigned.hash(), block.hash());
+        assert!(matches!(
+            resigned.verify_signature(),
+            Err(SpvmError::BadSignature(_))
+        ));
+
+        let changes: [fn(&mut Block); 6] = [
+            |b| b.number += 1,
+            |b| b.parent_hash = H256::random(),
+            |b| b.transactions.push(b.transactions[0].clone()),
+            |b| b.proposer = Address::random(),
+            |b| b.state_root = H256::random(),
+            |b| b.timestamp += 1,
+        ];
+        for change in changes {
+            let mut changed = block.clone();
+            change(&mut changed);
+            assert_ne!(changed.hash(), block.hash());
+            assert!(changed.verify_signature().is_err());
+        }
+    }
+
+    #[async_std::test]
+    async fn blocks_are_stored() {
+        let (wallet, connection) = setup().await;
+        let proposer = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet, proposer.address(), 10, 1);
+        let t3 = create_transfer_transaction("ABC", &wallet, proposer.address(), 10, 2);
+        let b1 = create_block(&proposer, 1, H256::zero(), vec![t1.clone(), t2.clone()]);
+        let b2 = create_block(&proposer, 2, b1.contract_hash(), vec![t3.clone()]);
+        let empty = create_block(&proposer, 3, b2.contract_hash(), vec![]);
+
+        assert_eq!(
+            get_latest_block(&appchain(&connection)).await.unwrap(),
+            None
+        );
+        for block in [&b1, &b2, &empty] {
+            assert_eq!(
+                insert_block(block, &appchain(&connection)).await.unwrap(),
+                block.hash()
+            );
+        }
+        assert!(matches!(
+            insert_block(&b1, &appchain(&connection)).await,
+            Err(SpvmError::Db(_))
+        ));
+
+        assert_eq!(
+            get_block(2, &appchain(&connection)).await.unwrap(),
+            Some(b2.clone())
+        );
+        assert_eq!(get_block(4, &appchain(&connection)).await.unwrap(), None);
+        assert_eq!(
+            get_block_by_hash(b1.hash(), &appchain(&connection))
+                .await
+                .unwrap(),
+            Some(b1.clone())
+        );
+        // Parents are found by the hash their children link to
+        assert_eq!(
+            get_block_by_contract_hash(empty.parent_hash, &appchain(&connection))
+                .await
+                .unwrap(),
+            Some(b2.clone())
+        );
+        assert_eq!(
+            get_latest_block(&appchain(&connection)).await.unwrap(),
+            Some(empty)
+        );
+
+        assert_eq!(
+            get_transaction(t2.tx_hash, &appchain(&connection))
+                .await
+                .unwrap(),
+            Some(BlockTransaction {
+                block_number: 1,
+                position: 1,
+                transaction: t2.clone(),
+            })
+        );
+        assert_eq!(
+            get_transaction(H256::random(), &appchain(&connection))
+                .await
+                .unwrap(),
+            None
+        );
+
+        // Other appchains have their own history
+        let other = Appchain::new(CHAIN_ID + 1, &connection);
+        assert_eq!(get_block_by_hash(b1.hash(), &other).await.unwrap(), None);
+        assert_eq!(get_transaction(t2.tx_hash, &other).await.unwrap(), None);
+        insert_block(&b1, &other).await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn contract_hashes_of_older_blocks_are_indexed() {
+        let (wallet, connection) = setup().await;
+        let b1 = create_block(&wallet, 1, H256::zero(), vec![]);
+        let b2 = create_block(&wallet, 2, b1.contract_hash(), vec![]);
+        for block in [&b1, &b2] {
+            insert_block(block, &appchain(&connection)).await.unwrap();
+        }
+
+        // As stored before contract hashes were recorded
+        blocks::Entity::update_many()
+            .col_expr(
+                blocks::Column::ContractHash,
+                sea_orm::sea_query::Expr::value(Option::<String>::None),
+            )
+            .exec(&connection)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_block_by_contract_hash(b2.parent_hash, &appchain(&connection))
+                .await
+                .unwrap(),
+            None
+        );
+
+        assert_eq!(
+            index_contract_hashes(&appchain(&connection)).await.unwrap(),
+            2
+        );
+        assert_eq!(
+            get_block_by_contract_hash(b2.parent_hash, &appchain(&connection))
+                .await
+                .unwrap(),
+            Some(b1)
+        );
+        assert_eq!(
+            index_contract_hashes(&appchain(&connection)).await.unwrap(),
+            0
+        );
+    }
+
+    #[async_std::test]
+    async fn l1_origins_are_stored() {
+        let (wallet, connection) = setup().await;
+        let block = create_block(&wallet, 1, H256::zero(), vec![]);
+        insert_block(&block, &appchain(&connection)).await.unwrap();
+        assert_eq!(
+            get_l1_origin(1, &appchain(&connection)).await.unwrap(),
+            None
+        );
+
+        let origin = L1Origin {
+            number: 42,
+            hash: H256::random(),
+        };
+        set_l1_origin(1, origin, &appchain(&connection))
+            .await
+            .unwrap();
+        assert_eq!(
+            get_l1_origin(1, &appchain(&connection)).await.unwrap(),
+            Some(origin)
+        );
+        assert_eq!(
+            get_block(1, &appchain(&connection)).await.unwrap(),
+            Some(block)
+        );
+        assert!(matches!(
+            set_l1_origin(2, origin, &appchain(&connection)).await,
+            Err(SpvmError::Db(DbErr::RecordNotFound(_)))
+        ));
+    }
+}
@@ -0,0 +1,1354 @@
+//! ABI codec for SPVM types.
+//!
+//! Layouts follow the structs of the SPVM-1 contract and of `PreconfirmationsTypes.sol`, so the
+//! bytes produced here are what `abi.encode` produces on chain, and hashes computed over them can
+//! be checked by the contracts. The one extension is the trailing `uint64 chainId` of v2
+//! transaction contents, see [`tx_content_param_type_for`].
+
+use ethers::{
+    core::abi::{decode, encode, ParamType, Token},
+    types::{Bytes, Signature, TxHash, U256},
+};
+
+use crate::{
+    ApproveTransactionParams, BurnTransactionParams, CreateTokenTransactionParams,
+    IssueTransactionParams, MintTransactionParams, MultiTransferTransactionParams,
+    PreconfirmationCommitment, PreconfirmationPayload, SpvmError, Transaction, TransactionContent,
+    TransactionParams, TransferFromTransactionParams, TransferTransactionParams, TX_FORMAT_V2,
+};
+
+pub fn encode_tx_params(tx_params: &TransactionParams) -> Bytes {
+    match tx_params {
+        TransactionParams::Mint(params) => {
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Address(params.owner),
+                Token::Uint(params.supply),
+            ]);
+            encode(&[tokens]).into()
+        }
+        TransactionParams::Transfer(params) => {
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Address(params.to),
+                Token::Uint(params.amount),
+            ]);
+            encode(&[tokens]).into()
+        }
+        TransactionParams::Burn(params) => {
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Uint(params.amount),
+            ]);
+            encode(&[tokens]).into()
+        }
+        TransactionParams::CreateToken(params) => {
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Address(params.owner),
+                Token::Uint(params.supply),
+                Token::Uint(U256::from(params.decimals)),
+                Token::String(params.name.clone()),
+            ]);
+            encode(&[tokens]).into()
+        }
+        TransactionParams::Issue(params) => {
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Address(params.to),
+                Token::Uint(params.amount),
+            ]);
+            encode(&[tokens]).into()
+        }
+        TransactionParams::Approve(params) => {
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Address(params.spender),
+                Token::Uint(params.amount),
+            ]);
+            encode(&[tokens]).into()
+        }
+        TransactionParams::TransferFrom(params) => {
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Address(params.owner),
+                Token::Address(params.to),
+                Token::Uint(params.amount),
+            ]);
+            encode(&[tokens]).into()
+        }
+        TransactionParams::MultiTransfer(params) => {
+            let transfers = params
+                .transfers
+                .iter()
+                .map(|(to, amount)| Token::Tuple(vec![Token::Address(*to), Token::Uint(*amount)]))
+                .collect();
+            let tokens = Token::Tuple(vec![
+                Token::String(params.token_ticker.clone()),
+                Token::Array(transfers),
+            ]);
+            encode(&[tokens]).into()
+        }
+    }
+}
+
+/// `TransactionContent` of SPVM-1, `PreconfTransactionContent` of the preconfirmation types.
+/// This is the layout of legacy transactions.
+pub fn tx_content_param_type() -> ParamType {
+    tx_content_param_type_for(0)
+}
+
+/// Layout of the content of a transaction of type `tx_type`. v2 transactions append
+/// `uint64 chainId` to the legacy layout, so the head of a legacy content is a prefix of the head
+/// of a v2 one.
+pub fn tx_content_param_type_for(tx_type: u8) -> ParamType {
+    let mut fields = vec![
+        ParamType::Address,
+        ParamType::Uint(8),
+        ParamType::Bytes,
+        ParamType::Uint(32),
+    ];
+    if tx_type & TX_FORMAT_V2 != 0 {
+        fields.push(ParamType::Uint(64));
+    }
+
+    ParamType::Tuple(fields)
+}
+
+/// `Transaction` of the preconfirmation types.
+pub fn transaction_param_type() -> ParamType {
+    transaction_param_type_for(0)
+}
+
+pub fn transaction_param_type_for(tx_type: u8) -> ParamType {
+    ParamType::Tuple(vec![
+        ParamType::FixedBytes(32),
+        tx_content_param_type_for(tx_type),
+        ParamType::Bytes,
+    ])
+}
+
+/// `SPVMTransaction` of SPVM-1. Its fields are ordered unlike the preconfirmation `Transaction`,
+/// and the contract only knows legacy contents.
+pub fn spvm_transaction_param_type() -> ParamType {
+    ParamType::Tuple(vec![
+        tx_content_param_type(),
+        ParamType::FixedBytes(32),
+        ParamType::Bytes,
+    ])
+}
+
+/// `PreconfirmationRequest` of the preconfirmation types.
+pub fn preconf_payload_param_type() -> ParamType {
+    preconf_payload_param_type_for(0, 0)
+}
+
+pub fn preconf_payload_param_type_for(tx_type: u8, tip_tx_type: u8) -> ParamType {
+    ParamType::Tuple(vec![
+        transaction_param_type_for(tx_type),
+        transaction_param_type_for(tip_tx_type),
+        ParamType::Address,
+    ])
+}
+
+/// `PreconfirmationCommitment` of the preconfirmation types.
+pub fn preconf_commitment_param_type() -> ParamType {
+    preconf_commitment_param_type_for(0, 0)
+}
+
+pub fn preconf_commitment_param_type_for(tx_type: u8, tip_tx_type: u8) -> ParamType {
+    ParamType::Tuple(vec![
+        preconf_payload_param_type_for(tx_type, tip_tx_type),
+        ParamType::Bytes,
+        ParamType::Address,
+        ParamType::Uint(256),
+    ])
+}
+
+pub fn tx_content_token(tx_content: &TransactionContent) -> Token {
+    let mut fields = vec![
+        Token::Address(tx_content.from),
+        Token::Uint(U256::from(tx_content.tx_type)),
+        Token::Bytes(encode_tx_params(&tx_content.tx_param).to_vec()),
+        Token::Uint(U256::from(tx_content.nonce)),
+    ];
+    if tx_content.tx_type & TX_FORMAT_V2 != 0 {
+        fields.push(Token::Uint(U256::from(tx_content.chain_id)));
+    }
+
+    Token::Tuple(fields)
+}
+
+pub fn transaction_token(tx: &Transaction) -> Token {
+    Token::Tuple(vec![
+        Token::FixedBytes(tx.tx_hash.as_bytes().to_vec()),
+        tx_content_token(&tx.tx_content),
+        Token::Bytes(tx.signature.to_vec()),
+    ])
+}
+
+pub fn spvm_transaction_token(tx: &Transaction) -> Token {
+    Token::Tuple(vec![
+        tx_content_token(&tx.tx_content),
+        Token::FixedBytes(tx.tx_hash.as_bytes().to_vec()),
+        Token::Bytes(tx.signature.to_vec()),
+    ])
+}
+
+pub fn preconf_payload_token(payload: &PreconfirmationPayload) -> Token {
+    Token::Tuple(vec![
+        transaction_token(&payload.transaction),
+        transaction_token(&payload.tip_tx),
+        Token::Address(payload.preconfer_contract),
+    ])
+}
+
+pub fn preconf_commitment_token(commitment: &PreconfirmationCommitment) -> Token {
+    Token::Tuple(vec![
+        preconf_payload_token(&commitment.preconfirmation_request),
+        Token::Bytes(commitment.commitment.to_vec()),
+        Token::Address(commitment.signer),
+        Token::Uint(commitment.block_number),
+    ])
+}
+
+/// The bytes hashed into `Transaction::tx_hash`.
+pub fn encode_tx_content(tx_content: &TransactionContent) -> Bytes {
+    encode(&[tx_content_token(tx_content)]).into()
+}
+
+pub fn encode_transaction(tx: &Transaction) -> Bytes {
+    encode(&[transaction_token(tx)]).into()
+}
+
+/// `abi.encode(SPVMTransaction[])`, as hashed into SPVM-1 block hashes and returned by
+/// `getTransactionsInBlock`.
+pub fn encode_spvm_transactions(txs: &[Transaction]) -> Bytes {
+    encode(&[Token::Array(
+        txs.iter().map(spvm_transaction_token).collect(),
+    )])
+    .into()
+}
+
+/// The bytes hashed and signed by enforcers into a preconfirmation commitment.
+pub fn encode_preconf_payload(payload: &PreconfirmationPayload) -> Bytes {
+    encode(&[preconf_payload_token(payload)]).into()
+}
+
+pub fn encode_preconf_commitment(commitment: &PreconfirmationCommitment) -> Bytes {
+    encode(&[preconf_commitment_token(commitment)]).into()
+}
+
+/// Decodes hex-encoded `TransactionContent`, with or without a `0x` prefix.
+///
+/// The input usually comes straight from users, so anything malformed or out of range for its
+/// field is a [`SpvmError::Decode`] rather than a panic or a silently truncated value.
+pub fn decode_tx_content(data: &str) -> Result<TransactionContent, SpvmError> {
+    let data = data.strip_prefix("0x").unwrap_or(data);
+    let bytes =
+        hex::decode(data).map_err(|e| SpvmError::Decode(format!("Invalid hex input: {}", e)))?;
+
+    let legacy = decode(&[tx_content_param_type()], &bytes)?;
+    let tx_type = content_tx_type(&legacy[0])?;
+
+    let mut tokens = decode(&[tx_content_param_type_for(tx_type)], &bytes)?;
+    let tx_content = tx_content_from_token(tokens.remove(0))?;
+    check_canonical(
+        &encode_tx_content(&tx_content),
+        &bytes,
+        "TransactionContent",
+    )?;
+
+    Ok(tx_content)
+}
+
+pub fn decode_transaction(data: &[u8]) -> Result<Transaction, SpvmError> {
+    let legacy = decode(&[transaction_param_type()], data)?;
+    let tx_type = transaction_tx_type(&legacy[0])?;
+
+    let mut tokens = decode(&[transaction_param_type_for(tx_type)], data)?;
+    let tx = transaction_from_token(tokens.remove(0))?;
+    check_canonical(&encode_transaction(&tx), data, "Transaction")?;
+
+    Ok(tx)
+}
+
+/// Decodes `abi.encode(SPVMTransaction[])`, see [`encode_spvm_transactions`].
+pub fn decode_spvm_transactions(data: &[u8]) -> Result<Vec<Transaction>, SpvmError> {
+    let txs = match decode(
+        &[ParamType::Array(Box::new(spvm_transaction_param_type()))],
+        data,
+    )?
+    .remove(0)
+    {
+        Token::Array(tokens) => tokens
+            .into_iter()
+            .map(spvm_transaction_from_token)
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(SpvmError::Decode("Expected Array type".into())),
+    };
+    check_canonical(&encode_spvm_transactions(&txs), data, "SPVMTransaction[]")?;
+
+    Ok(txs)
+}
+
+pub fn decode_preconf_payload(data: &[u8]) -> Result<PreconfirmationPayload, SpvmError> {
+    let legacy = decode(&[preconf_payload_param_type()], data)?;
+    let tx_type = transaction_tx_type(tuple_field(&legacy[0], 0)?)?;
+    let tip_tx_type = transaction_tx_type(tuple_field(&legacy[0], 1)?)?;
+
+    let mut tokens = decode(
+        &[preconf_payload_param_type_for(tx_type, tip_tx_type)],
+        data,
+    )?;
+    let payload = preconf_payload_from_token(tokens.remove(0))?;
+    check_canonical(
+        &encode_preconf_payload(&payload),
+        data,
+        "PreconfirmationRequest",
+    )?;
+
+    Ok(payload)
+}
+
+pub fn decode_preconf_commitment(data: &[u8]) -> Result<PreconfirmationCommitment, SpvmError> {
+    let legacy = decode(&[preconf_commitment_param_type()], data)?;
+    let payload = tuple_field(&legacy[0], 0)?;
+    let tx_type = transaction_tx_type(tuple_field(payload, 0)?)?;
+    let tip_tx_type = transaction_tx_type(tuple_field(payload, 1)?)?;
+
+    let mut tokens = decode(
+        &[preconf_commitment_param_type_for(tx_type, tip_tx_type)],
+        data,
+    )?;
+    let commitment = preconf_commitment_from_token(tokens.remove(0))?;
+    check_canonical(
+        &encode_preconf_commitment(&commitment),
+        data,
+        "PreconfirmationCommitment",
+    )?;
+
+    Ok(commitment)
+}
+
+// Decoding with the legacy layout is only used to read the transaction types, which select the
+// exact layout. It reads the right values for v2 contents too since their head starts with the
+// legacy one.
+fn tuple_field(token: &Token, index: usize) -> Result<&Token, SpvmError> {
+    match token {
+        Token::Tuple(fields) if index < fields.len() => Ok(&fields[index]),
+        _ => Err(SpvmError::Decode("Expected Tuple type".into())),
+    }
+}
+
+fn content_tx_type(token: &Token) -> Result<u8, SpvmError> {
+    match tuple_field(token, 1)? {
+        Token::Uint(u) => check_uint_range(*u, "tx_type"),
+        _ => Err(SpvmError::Decode("Expected Uint type for 'tx_type'".into())),
+    }
+}
+
+fn transaction_tx_type(token: &Token) -> Result<u8, SpvmError> {
+    content_tx_type(tuple_field(token, 1)?)
+}
+
+// The decoder ignores trailing bytes and, when the layout was picked from a field it read, can
+// misread a value laid out for the other format. Re-encoding catches both.
+fn check_canonical(encoded: &[u8], data: &[u8], name: &str) -> Result<(), SpvmError> {
+    if encoded != data {
+        return Err(SpvmError::Decode(format!(
+            "Input is not a canonical encoding of {}",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+fn tuple_fields(token: Token, name: &str, len: usize) -> Result<Vec<Token>, SpvmError> {
+    match token {
+        Token::Tuple(fields) if fields.len() == len => Ok(fields),
+        _ => Err(SpvmError::Decode(format!(
+            "Expected a tuple of {} fields for {}",
+            len, name
+        ))),
+    }
+}
+
+fn signature_from_token(token: &Token, name: &str) -> Result<Signature, SpvmError> {
+    match token {
+        Token::Bytes(b) => Signature::try_from(b.as_slice())
+            .map_err(|e| SpvmError::Decode(format!("Invalid '{}': {}", name, e))),
+        _ => Err(SpvmError::Decode(format!(
+            "Expected Bytes type for '{}'",
+            name
+        ))),
+    }
+}
+
+fn transaction_from_token(token: Token) -> Result<Transaction, SpvmError> {
+    let mut fields = tuple_fields(token, "Transaction", 3)?.into_iter();
+
+    let tx_hash = match fields.next() {
+        Some(Token::FixedBytes(b)) if b.len() == 32 => TxHash::from_slice(&b),
+        _ => return Err(SpvmError::Decode("Expected bytes32 for 'tx_hash'".into())),
+    };
+    let tx_content = tx_content_from_token(fields.next().unwrap())?;
+    let signature = signature_from_token(&fields.next().unwrap(), "signature")?;
+
+    Ok(Transaction {
+        tx_content,
+        tx_hash,
+        signature,
+    })
+}
+
+fn spvm_transaction_from_token(token: Token) -> Result<Transaction, SpvmError> {
+    let mut fields = tuple_fields(token, "SPVMTransaction", 3)?;
+    fields.swap(0, 1);
+
+    transaction_from_token(Token::Tuple(fields))
+}
+
+fn preconf_payload_from_token(token: Token) -> Result<PreconfirmationPayload, SpvmError> {
+    let mut fields = tuple_fields(token, "PreconfirmationRequest", 3)?.into_iter();
+
+    let transaction = transaction_from_token(fields.next().unwrap())?;
+    let tip_tx = transaction_from_token(fields.next().unwrap())?;
+    let preconfer_contract = match fields.next() {
+        Some(Token::Address(addr)) => addr,
+        _ => {
+            return Err(SpvmError::Decode(
+                "Expected Address type for 'preconf_contract'".into(),
+            ))
+        }
+    };
+
+    Ok(PreconfirmationPayload {
+        transaction,
+        tip_tx,
+        preconfer_contract,
+    })
+}
+
+fn preconf_commitment_from_token(token: Token) -> Result<PreconfirmationCommitment, SpvmError> {
+    let mut fields = tuple_fields(token, "PreconfirmationCommitment", 4)?.into_iter();
+
+    let preconfirmation_request = preconf_payload_from_token(fields.next().unwrap())?;
+    let commitment = signature_from_token(&fields.next().unwrap(), "commitment")?;
+    let signer = match fields.next() {
+        Some(Token::Address(addr)) => addr,
+        _ => {
+            return Err(SpvmError::Decode(
+                "Expected Address type for 'signer'".into(),
+            ))
+        }
+    };
+    let block_number = match fields.next() {
+        Some(Token::Uint(u)) => u,
+        _ => {
+            return Err(SpvmError::Decode(
+                "Expected Uint type for 'block_number'".into(),
+            ))
+        }
+    };
+
+    Ok(PreconfirmationCommitment {
+        preconfirmation_request,
+        commitment,
+        signer,
+        block_number,
+    })
+}
+
+fn tx_content_from_token(token: Token) -> Result<TransactionContent, SpvmError> {
+    let tokens = match token {
+        Token::Tuple(t) => t,
+        _ => return Err(SpvmError::Decode("Expected Tuple type".into())),
+    };
+
+    if tokens.len() != 4 && tokens.len() != 5 {
+        return Err(SpvmError::Decode("Incorrect number of tokens".into()));
+    }
+
+    let from = match &tokens[0] {
+        Token::Address(addr) => *addr,
+        _ => return Err(SpvmError::Decode("Expected Address type for 'from'".into())),
+    };
+
+    let tx_type = match &tokens[1] {
+        Token::Uint(u) => check_uint_range(*u, "tx_type")?,
+        _ => return Err(SpvmError::Decode("Expected Uint type for 'tx_type'".into())),
+    };
+
+    // Only v2 contents carry a chain id
+    let v2 = tx_type & TX_FORMAT_V2 != 0;
+    if tokens.len() != if v2 { 5 } else { 4 } {
+        return Err(SpvmError::Decode(format!(
+            "Incorrect number of tokens for tx_type {}",
+            tx_type
+        )));
+    }
+
+    let tx_param_bytes = match &tokens[2] {
+        Token::Bytes(b) => b,
+        _ => {
+            return Err(SpvmError::Decode(
+                "Expected Bytes type for 'tx_param'".into(),
+            ))
+        }
+    };
+
+    let tx_params = decode_tx_params(tx_type, tx_param_bytes)?;
+
+    let nonce = match &tokens[3] {
+        Token::Uint(u) => check_uint_range(*u, "nonce")?,
+        _ => return Err(SpvmError::Decode("Expected Uint type for 'nonce'".into())),
+    };
+
+    let chain_id = match tokens.get(4) {
+        Some(Token::Uint(u)) => check_uint_range(*u, "chain_id")?,
+        Some(_) => {
+            return Err(SpvmError::Decode(
+                "Expected Uint type for 'chain_id'".into(),
+            ))
+        }
+        None => 0,
+    };
+
+    Ok(TransactionContent {
+        from,
+        tx_type,
+        tx_param: tx_params,
+        nonce,
+        chain_id,
+    })
+}
+
+/// Decodes the `tx_param` bytes of a transaction of type `tx_type`.
+pub fn decode_tx_params(tx_type: u8, data: &[u8]) -> Result<TransactionParams, SpvmError> {
+    let tx_param_tokens = decode(&tx_param_types(tx_type)?, data)?;
+    let tx_params = decode_transaction_params(tx_type, tx_param_tokens)?;
+
+    // The decoder skips whatever it does not read, so parameters laid out for another tx_type,
+    // trailing bytes or dirty padding would otherwise go through.
+    if encode_tx_params(&tx_params)[..] != data[..] {
+        return Err(SpvmError::Decode(format!(
+            "'tx_param' is not a canonical encoding for tx_type {}",
+            tx_type
+        )));
+    }
+
+    Ok(tx_params)
+}
+
+/// ABI layout of the `tx_param` bytes of a transaction of type `tx_type`.
+pub fn tx_param_types(tx_type: u8) -> Result<Vec<ParamType>, SpvmError> {
+    let amount_type = if tx_type & TX_FORMAT_V2 == 0 {
+        ParamType::Uint(16)
+    } else {
+        ParamType::Uint(256)
+    };
+
+    let params = match tx_type & !TX_FORMAT_V2 {
+        0 | 1 | 4 | 5 => vec![ParamType::String, ParamType::Address, amount_type],
+        2 => vec![ParamType::String, amount_type],
+        3 => vec![
+            ParamType::String,
+            ParamType::Address,
+            amount_type,
+            ParamType::Uint(8),
+            ParamType::String,
+        ],
+        6 => vec![
+            ParamType::String,
+            ParamType::Address,
+            ParamType::Address,
+            amount_type,
+        ],
+        7 => vec![
+            ParamType::String,
+            ParamType::Array(Box::new(ParamType::Tuple(vec![
+                ParamType::Address,
+                amount_type,
+            ]))),
+        ],
+        _ => return Err(SpvmError::UnsupportedTxType(tx_type)),
+    };
+
+    Ok(vec![ParamType::Tuple(params)])
+}
+
+pub fn decode_transaction_params(
+    tx_type: u8,
+    params: Vec<Token>,
+) -> Result<TransactionParams, SpvmError> {
+    let params = match params.first() {
+        Some(Token::Tuple(t)) => t.clone(),
+        _ => return Err(SpvmError::Decode("Expected Tuple type".into())),
+    };
+
+    match tx_type & !TX_FORMAT_V2 {
+        0 => {
+            if params.len() != 3 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for Mint".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let owner = match &params[1] {
+                Token::Address(addr) => *addr,
+                _ => return Err(SpvmError::Decode("Expected address for owner".into())),
+            };
+            let supply = match &params[2] {
+                Token::Uint(u) => check_amount_range(tx_type, *u, "supply")?,
+                _ => return Err(SpvmError::Decode("Expected uint for supply".into())),
+            };
+            Ok(TransactionParams::Mint(MintTransactionParams {
+                token_ticker,
+                owner,
+                supply,
+            }))
+        }
+        1 => {
+            if params.len() != 3 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for Transfer".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let to = match &params[1] {
+                Token::Address(addr) => *addr,
+                _ => return Err(SpvmError::Decode("Expected address for to".into())),
+            };
+            let amount = match &params[2] {
+                Token::Uint(u) => check_amount_range(tx_type, *u, "amount")?,
+                _ => return Err(SpvmError::Decode("Expected uint for amount".into())),
+            };
+            Ok(TransactionParams::Transfer(TransferTransactionParams {
+                token_ticker,
+                to,
+                amount,
+            }))
+        }
+        2 => {
+            if params.len() != 2 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for Burn".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let amount = match &params[1] {
+                Token::Uint(u) => check_amount_range(tx_type, *u, "amount")?,
+                _ => return Err(SpvmError::Decode("Expected uint for amount".into())),
+            };
+            Ok(TransactionParams::Burn(BurnTransactionParams {
+                token_ticker,
+                amount,
+            }))
+        }
+        3 => {
+            if params.len() != 5 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for CreateToken".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let owner = match &params[1] {
+                Token::Address(addr) => *addr,
+                _ => return Err(SpvmError::Decode("Expected address for owner".into())),
+            };
+            let supply = match &params[2] {
+                Token::Uint(u) => check_amount_range(tx_type, *u, "supply")?,
+                _ => return Err(SpvmError::Decode("Expected uint for supply".into())),
+            };
+            let decimals = match &params[3] {
+                Token::Uint(u) => check_uint_range(*u, "decimals")?,
+                _ => return Err(SpvmError::Decode("Expected uint for decimals".into())),
+            };
+            let name = match &params[4] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for name".into())),
+            };
+            Ok(TransactionParams::CreateToken(
+                CreateTokenTransactionParams {
+                    token_ticker,
+                    owner,
+                    supply,
+                    decimals,
+                    name,
+                },
+            ))
+        }
+        4 => {
+            if params.len() != 3 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for Issue".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let to = match &params[1] {
+                Token::Address(addr) => *addr,
+                _ => return Err(SpvmError::Decode("Expected address for to".into())),
+            };
+            let amount = match &params[2] {
+                Token::Uint(u) => check_amount_range(tx_type, *u, "amount")?,
+                _ => return Err(SpvmError::Decode("Expected uint for amount".into())),
+            };
+            Ok(TransactionParams::Issue(IssueTransactionParams {
+                token_ticker,
+                to,
+                amount,
+            }))
+        }
+        5 => {
+            if params.len() != 3 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for Approve".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let spender = match &params[1] {
+                Token::Address(addr) => *addr,
+                _ => return Err(SpvmError::Decode("Expected address for spender".into())),
+            };
+            let amount = match &params[2] {
+                Token::Uint(u) => check_amount_range(tx_type, *u, "amount")?,
+                _ => return Err(SpvmError::Decode("Expected uint for amount".into())),
+            };
+            Ok(TransactionParams::Approve(ApproveTransactionParams {
+                token_ticker,
+                spender,
+                amount,
+            }))
+        }
+        6 => {
+            if params.len() != 4 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for TransferFrom".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let owner = match &params[1] {
+                Token::Address(addr) => *addr,
+                _ => return Err(SpvmError::Decode("Expected address for owner".into())),
+            };
+            let to = match &params[2] {
+                Token::Address(addr) => *addr,
+                _ => return Err(SpvmError::Decode("Expected address for to".into())),
+            };
+            let amount = match &params[3] {
+                Token::Uint(u) => check_amount_range(tx_type, *u, "amount")?,
+                _ => return Err(SpvmError::Decode("Expected uint for amount".into())),
+            };
+            Ok(TransactionParams::TransferFrom(
+                TransferFromTransactionParams {
+                    token_ticker,
+                    owner,
+                    to,
+                    amount,
+                },
+            ))
+        }
+        7 => {
+            if params.len() != 2 {
+                return Err(SpvmError::Decode(
+                    "Invalid number of parameters for MultiTransfer".into(),
+                ));
+            }
+            let token_ticker = match &params[0] {
+                Token::String(s) => s.clone(),
+                _ => return Err(SpvmError::Decode("Expected string for token_ticker".into())),
+            };
+            let entries = match &params[1] {
+                Token::Array(entries) => entries,
+                _ => return Err(SpvmError::Decode("Expected array for transfers".into())),
+            };
+            let mut transfers = Vec::with_capacity(entries.len());
+            for entry in entries {
+                match entry {
+                    Token::Tuple(t) => match t.as_slice() {
+                        [Token::Address(to), Token::Uint(amount)] => {
+                            transfers.push((*to, check_amount_range(tx_type, *amount, "amount")?))
+                        }
+                        _ => {
+                            return Err(SpvmError::Decode(
+                                "Expected (address, uint) for transfer".into(),
+                            ))
+                        }
+                    },
+                    _ => return Err(SpvmError::Decode("Expected tuple for transfer".into())),
+                }
+            }
+            Ok(TransactionParams::MultiTransfer(
+                MultiTransferTransactionParams {
+                    token_ticker,
+                    transfers,
+                },
+            ))
+        }
+        _ => Err(SpvmError::UnsupportedTxType(tx_type)),
+    }
+}
+
+// Legacy transactions carry uint16 amounts; anything larger needs the v2 format.
+fn check_amount_range(tx_type: u8, amount: U256, name: &str) -> Result<U256, SpvmError> {
+    if tx_type & TX_FORMAT_V2 == 0 && amount > U256::from(u16::MAX) {
+        return Err(SpvmError::Decode(format!(
+            "{} {} does not fit in uint16, use the v2 transaction format",
+            name, amount
+        )));
+    }
+
+    Ok(amount)
+}
+
+// The ABI decoder returns every uint as a U256 whatever its declared width.
+fn check_uint_range<T: TryFrom<U256>>(value: U256, name: &str) -> Result<T, SpvmError> {
+    T::try_from(value).map_err(|_| {
+        SpvmError::Decode(format!(
+            "{} {} is out of range for uint{}",
+            name,
+            value,
+            std::mem::size_of::<T>() * 8
+        ))
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        abi::Abi,
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{Address, U256},
+        utils::keccak256,
+    };
+    use proptest::prelude::*;
+
+    #[test]
+    fn decode_fail_legacy_amount_beyond_u16() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let tx_content = TransactionContent {
+            from: wallet.address(),
+            tx_type: 1,
+            tx_param: TransactionParams::Transfer(TransferTransactionParams {
+                token_ticker: "ABC".to_string(),
+                to: wallet.address(),
+                amount: U256::from(u16::MAX) + 1,
+            }),
+            nonce: 0,
+            chain_id: 0,
+        };
+
+        assert!(matches!(
+            decode_tx_content(&hex::encode(encode_tx_content(&tx_content))),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn decode_tx_params_by_tx_type() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let burn = TransactionParams::Burn(BurnTransactionParams {
+            token_ticker: "ABC".to_string(),
+            amount: U256::from(10),
+        });
+        let transfer = TransactionParams::Transfer(TransferTransactionParams {
+            token_ticker: "ABC".to_string(),
+            to: wallet.address(),
+            amount: U256::from(u16::MAX) + 1,
+        });
+
+        assert_eq!(decode_tx_params(2, &encode_tx_params(&burn)).unwrap(), burn);
+        assert_eq!(
+            decode_tx_params(TX_FORMAT_V2 | 1, &encode_tx_params(&transfer)).unwrap(),
+            transfer
+        );
+
+        // A legacy layout can't carry the amount, and a burn isn't laid out like a transfer
+        assert!(matches!(
+            decode_tx_params(1, &encode_tx_params(&transfer)),
+            Err(SpvmError::Decode(_))
+        ));
+        assert!(matches!(
+            decode_tx_params(1, &encode_tx_params(&burn)),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    fn encode_raw_tx_content(tx_type: U256, tx_param: &TransactionParams, nonce: U256) -> String {
+        hex::encode(ethers::core::abi::encode(&[
+            ethers::core::abi::Token::Tuple(vec![
+                ethers::core::abi::Token::Address(Address::zero()),
+                ethers::core::abi::Token::Uint(tx_type),
+                ethers::core::abi::Token::Bytes(encode_tx_params(tx_param).to_vec()),
+                ethers::core::abi::Token::Uint(nonce),
+            ]),
+        ]))
+    }
+
+    fn burn_params() -> TransactionParams {
+        TransactionParams::Burn(BurnTransactionParams {
+            token_ticker: "ABC".to_string(),
+            amount: U256::from(5),
+        })
+    }
+
+    #[test]
+    fn decode_tx_content_by_tx_type() {
+        let decoded = decode_tx_content(&encode_raw_tx_content(
+            U256::from(2),
+            &burn_params(),
+            U256::one(),
+        ))
+        .unwrap();
+        assert_eq!(decoded.tx_type, 2);
+        assert_eq!(decoded.tx_param, burn_params());
+        assert_eq!(decoded.nonce, 1);
+
+        // A burn's parameters don't make a valid transfer
+        assert!(matches!(
+            decode_tx_content(&encode_raw_tx_content(
+                U256::one(),
+                &burn_params(),
+                U256::one()
+            )),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn decode_tx_content_accepts_0x_prefix() {
+        let encoded = encode_raw_tx_content(U256::from(2), &burn_params(), U256::zero());
+        assert_eq!(
+            decode_tx_content(&format!("0x{}", encoded)).unwrap(),
+            decode_tx_content(&encoded).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_fail_invalid_hex() {
+        assert!(matches!(
+            decode_tx_content("0xnothex"),
+            Err(SpvmError::Decode(_))
+        ));
+        assert!(matches!(
+            decode_tx_content("abc"),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn decode_fail_truncated_input() {
+        let encoded = encode_raw_tx_content(U256::from(2), &burn_params(), U256::zero());
+        assert!(matches!(
+            decode_tx_content(&encoded[..encoded.len() - 64]),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn decode_fail_tx_type_out_of_range() {
+        // 0x102 used to be truncated to a burn
+        assert!(matches!(
+            decode_tx_content(&encode_raw_tx_content(
+                U256::from(0x102),
+                &burn_params(),
+                U256::zero()
+            )),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn decode_fail_unsupported_tx_type() {
+        assert!(matches!(
+            decode_tx_content(&encode_raw_tx_content(
+                U256::from(0x7f),
+                &burn_params(),
+                U256::zero()
+            )),
+            Err(SpvmError::UnsupportedTxType(0x7f))
+        ));
+    }
+
+    #[test]
+    fn decode_fail_nonce_out_of_range() {
+        assert!(matches!(
+            decode_tx_content(&encode_raw_tx_content(
+                U256::from(2),
+                &burn_params(),
+                U256::from(u32::MAX) + 1
+            )),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn decode_fail_v2_without_chain_id() {
+        // Legacy layout, so the chain id is missing
+        assert!(matches!(
+            decode_tx_content(&encode_raw_tx_content(
+                U256::from(TX_FORMAT_V2 | 2),
+                &burn_params(),
+                U256::zero()
+            )),
+            Err(SpvmError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn codec_v2_tx_content_carries_chain_id() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let tx = create_transaction(&wallet, TX_FORMAT_V2 | 2, burn_params(), 0);
+        assert_eq!(tx.tx_content.chain_id, CHAIN_ID);
+
+        let decoded = decode_tx_content(&hex::encode(encode_tx_content(&tx.tx_content))).unwrap();
+        assert_eq!(decoded, tx.tx_content);
+        assert_eq!(decode_transaction(&encode_transaction(&tx)).unwrap(), tx);
+    }
+
+    fn arb_address() -> impl Strategy<Value = Address> {
+        any::<[u8; 20]>().prop_map(Address::from)
+    }
+
+    pub(crate) fn arb_amount(v2: bool) -> BoxedStrategy<U256> {
+        if v2 {
+            any::<[u8; 32]>()
+                .prop_map(|b| U256::from_big_endian(&b))
+                .boxed()
+        } else {
+            any::<u16>().prop_map(U256::from).boxed()
+        }
+    }
+
+    fn arb_tx_params(v2: bool) -> impl Strategy<Value = (u8, TransactionParams)> {
+        let ticker = "[A-Z]{1,8}";
+        prop_oneof![
+            (ticker, arb_address(), arb_amount(v2)).prop_map(|(token_ticker, owner, supply)| (
+                0,
+                TransactionParams::Mint(MintTransactionParams {
+                    token_ticker,
+                    owner,
+                    supply,
+                })
+            )),
+            (ticker, arb_address(), arb_amount(v2)).prop_map(|(token_ticker, to, amount)| (
+                1,
+                TransactionParams::Transfer(TransferTransactionParams {
+                    token_ticker,
+                    to,
+                    amount,
+                })
+            )),
+            (ticker, arb_amount(v2)).prop_map(|(token_ticker, amount)| (
+                2,
+                TransactionParams::Burn(BurnTransactionParams {
+                    token_ticker,
+                    amount,
+                })
+            )),
+            (
+                ticker,
+                arb_address(),
+                arb_amount(v2),
+                any::<u8>(),
+                ".{0,16}"
+            )
+                .prop_map(|(token_ticker, owner, supply, decimals, name)| (
+                    3,
+                    TransactionParams::CreateToken(CreateTokenTransactionParams {
+                        token_ticker,
+                        owner,
+                        supply,
+                        decimals,
+                        name,
+                    })
+                )),
+            (ticker, arb_address(), arb_amount(v2)).prop_map(|(token_ticker, to, amount)| (
+                4,
+                TransactionParams::Issue(IssueTransactionParams {
+                    token_ticker,
+                    to,
+                    amount,
+                })
+            )),
+            (ticker, arb_address(), arb_amount(v2)).prop_map(|(token_ticker, spender, amount)| (
+                5,
+                TransactionParams::Approve(ApproveTransactionParams {
+                    token_ticker,
+                    spender,
+                    amount,
+                })
+            )),
+            (ticker, arb_address(), arb_address(), arb_amount(v2)).prop_map(
+                |(token_ticker, owner, to, amount)| (
+                    6,
+                    TransactionParams::TransferFrom(TransferFromTransactionParams {
+                        token_ticker,
+                        owner,
+                        to,
+                        amount,
+                    })
+                )
+            ),
+            (
+                ticker,
+                proptest::collection::vec((arb_address(), arb_amount(v2)), 0..4)
+            )
+                .prop_map(|(token_ticker, transfers)| (
+                    7,
+                    TransactionParams::MultiTransfer(MultiTransferTransactionParams {
+                        token_ticker,
+                        transfers,
+                    })
+                )),
+        ]
+    }
+
+    pub(crate) fn arb_tx_content() -> impl Strategy<Value = TransactionContent> {
+        any::<bool>()
+            .prop_flat_map(|v2| {
+                (
+                    Just(v2),
+                    arb_address(),
+                    arb_tx_params(v2),
+                    any::<u32>(),
+                    any::<u64>(),
+                )
+            })
+            .prop_map(
+                |(v2, from, (tx_type, tx_param), nonce, chain_id)| TransactionContent {
+                    from,
+                    tx_type: if v2 { tx_type | TX_FORMAT_V2 } else { tx_type },
+                    tx_param,
+                    nonce,
+                    // Legacy contents have no chain id on the wire
+                    chain_id: if v2 { chain_id } else { 0 },
+                },
+            )
+    }
+
+    fn arb_signature() -> impl Strategy<Value = Signature> {
+        (any::<[u8; 32]>(), any::<[u8; 32]>(), 27u64..=28).prop_map(|(r, s, v)| Signature {
+            r: U256::from_big_endian(&r),
+            s: U256::from_big_endian(&s),
+            v,
+        })
+    }
+
+    fn arb_transaction() -> impl Strategy<Value = Transaction> {
+        (arb_tx_content(), any::<[u8; 32]>(), arb_signature()).prop_map(
+            |(tx_content, tx_hash, signature)| Transaction {
+                tx_content,
+                tx_hash: TxHash::from(tx_hash),
+                signature,
+            },
+        )
+    }
+
+    fn arb_preconf_payload() -> impl Strategy<Value = PreconfirmationPayload> {
+        (arb_transaction(), arb_transaction(), arb_address()).prop_map(
+            |(transaction, tip_tx, preconfer_contract)| PreconfirmationPayload {
+                transaction,
+                tip_tx,
+                preconfer_contract,
+            },
+        )
+    }
+
+    fn arb_preconf_commitment() -> impl Strategy<Value = PreconfirmationCommitment> {
+        (
+            arb_preconf_payload(),
+            arb_signature(),
+            arb_address(),
+            arb_amount(true),
+        )
+            .prop_map(
+                |(preconfirmation_request, commitment, signer, block_number)| {
+                    PreconfirmationCommitment {
+                        preconfirmation_request,
+                        commitment,
+                        signer,
+                        block_number,
+                    }
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn codec_round_trip_tx_content(tx_content in arb_tx_content()) {
+            let encoded = hex::encode(encode_tx_content(&tx_content));
+            prop_assert_eq!(decode_tx_content(&encoded).unwrap(), tx_content);
+        }
+
+        #[test]
+        fn codec_round_trip_transaction(tx in arb_transaction()) {
+            prop_assert_eq!(decode_transaction(&encode_transaction(&tx)).unwrap(), tx);
+        }
+
+        #[test]
+        fn codec_round_trip_spvm_transactions(
+            txs in proptest::collection::vec(arb_transaction(), 0..4)
+        ) {
+            let decoded = decode_spvm_transactions(&encode_spvm_transactions(&txs));
+            // SPVM-1 has no layout for v2 contents
+            if txs.iter().all(|tx| tx.tx_content.tx_type & TX_FORMAT_V2 == 0) {
+                prop_assert_eq!(decoded.unwrap(), txs);
+            } else {
+                prop_assert!(decoded.is_err());
+            }
+        }
+
+        #[test]
+        fn codec_round_trip_preconf_payload(payload in arb_preconf_payload()) {
+            let encoded = encode_preconf_payload(&payload);
+            prop_assert_eq!(decode_preconf_payload(&encoded).unwrap(), payload);
+        }
+
+        #[test]
+        fn codec_round_trip_preconf_commitment(commitment in arb_preconf_commitment()) {
+            let encoded = encode_preconf_commitment(&commitment);
+            prop_assert_eq!(decode_preconf_commitment(&encoded).unwrap(), commitment);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn decode_tx_content_never_panics(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let _ = decode_tx_content(&hex::encode(data));
+        }
+
+        #[test]
+        fn decode_tx_content_never_panics_on_text(data in "(0x)?[0-9a-fA-F]{0,640}|\\PC*") {
+            let _ = decode_tx_content(&data);
+        }
+
+        // Whatever decodes is canonical: it encodes back to the exact input
+        #[test]
+        fn decoders_never_panic(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+            check_decoders(&data)?;
+        }
+
+        #[test]
+        fn decoders_never_panic_on_corrupted_encodings(
+            tx in arb_transaction(),
+            corruptions in proptest::collection::vec(
+                (any::<prop::sample::Index>(), any::<u8>()),
+                1..4,
+            ),
+            truncate in proptest::option::of(any::<prop::sample::Index>()),
+        ) {
+            let mut data = encode_transaction(&tx).to_vec();
+            for (index, byte) in corruptions {
+                let i = index.index(data.len());
+                data[i] = byte;
+            }
+            if let Some(len) = truncate {
+                data.truncate(len.index(data.len()));
+            }
+
+            check_decoders(&data)?;
+            check_decoders(&data[encode_tx_content(&tx.tx_content).len().min(data.len())..])?;
+        }
+    }
+
+    fn check_decoders(data: &[u8]) -> Result<(), TestCaseError> {
+        if let Ok(tx_content) = decode_tx_content(&hex::encode(data)) {
+            prop_assert_eq!(&encode_tx_content(&tx_content)[..], data);
+        }
+        if let Ok(tx) = decode_transaction(data) {
+            prop_assert_eq!(&encode_transaction(&tx)[..], data);
+        }
+        if let Ok(txs) = decode_spvm_transactions(data) {
+            prop_assert_eq!(&encode_spvm_transactions(&txs)[..], data);
+        }
+        if let Ok(payload) = decode_preconf_payload(data) {
+            prop_assert_eq!(&encode_preconf_payload(&payload)[..], data);
+        }
+        if let Ok(commitment) = decode_preconf_commitment(data) {
+            prop_assert_eq!(&encode_preconf_commitment(&commitment)[..], data);
+        }
+
+        Ok(())
+    }
+
+    // The enforcer ships the compiled Slashing contract, whose ABI is generated from
+    // PreconfirmationsTypes.sol.
+    fn slashing_abi() -> Abi {
+        let artifact: serde_json::Value = serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../apps/enforcer/contracts/Slashing.json"
+        )))
+        .unwrap();
+        serde_json::from_value(artifact["abi"].clone()).unwrap()
+    }
+
+    #[test]
+    fn codec_layouts_match_slashing_abi() {
+        let abi = slashing_abi();
+
+        let slash = abi.function("slash").unwrap();
+        assert_eq!(slash.inputs[0].kind, preconf_commitment_param_type());
+
+        let submit = abi.function("submitValidityConditions").unwrap();
+        assert_eq!(
+            submit.inputs[0].kind,
+            ParamType::Array(Box::new(transaction_param_type()))
+        );
+    }
+
+    #[test]
+    fn codec_encodes_preconf_commitment_like_the_contract_abi() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let tx = create_transfer_transaction("ABC", &wallet, wallet.address(), 10, 0);
+        let tip_tx = create_transfer_transaction("ABC", &wallet, wallet.address(), 1, 1);
+        let payload = PreconfirmationPayload {
+            transaction: tx,
+            tip_tx,
+            preconfer_contract: wallet.address(),
+        };
+        let commitment = PreconfirmationCommitment {
+            commitment: wallet
+                .sign_hash(TxHash::from(keccak256(encode_preconf_payload(&payload))))
+                .unwrap(),
+            preconfirmation_request: payload,
+            signer: wallet.address(),
+            block_number: U256::from(42),
+        };
+
+        let function = slashing_abi().function("slash").unwrap().clone();
+        let calldata = function
+            .encode_input(&[preconf_commitment_token(&commitment)])
+            .unwrap();
+
+        assert_eq!(calldata[..4], function.short_signature());
+        assert_eq!(calldata[4..], encode_preconf_commitment(&commitment)[..]);
+    }
+}
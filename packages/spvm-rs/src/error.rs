@@ -0,0 +1,161 @@
+use ethers::types::{Address, SignatureError, U256};
+use sea_orm::DbErr;
+use std::fmt;
+
+/// Errors returned by the SPVM executor and codec.
+///
+/// Every variant has a stable [`SpvmError::code`] so services can expose it to clients
+/// without depending on the wording of the message.
+#[derive(Debug)]
+pub enum SpvmError {
+    InvalidNonce { expected: u32, got: u32 },
+    NonceOverflow(Address),
+    WrongChain { expected: u64, got: u64 },
+    LegacyTxRejected(u64),
+    InsufficientBalance { have: U256, need: U256 },
+    InsufficientAllowance { have: U256, need: U256 },
+    BalanceOverflow,
+    SupplyUnderflow(String),
+    TickerNotInitialized(String),
+    TickerAlreadyInitialized(String),
+    NotMintAuthority { ticker: String, sender: Address },
+    UnsupportedTxType(u8),
+    TxTypeMismatch(u8),
+    AmountOutOfRange(U256),
+    HashMismatch,
+    BadSignature(SignatureError),
+    Decode(String),
+    InvalidSnapshot(String),
+    MissingUndoJournal(u32),
+    InvalidBlock(String),
+    Db(DbErr),
+}
+
+impl SpvmError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SpvmError::InvalidNonce { .. } => "INVALID_NONCE",
+            SpvmError::NonceOverflow(_) => "NONCE_OVERFLOW",
+            SpvmError::WrongChain { .. } => "WRONG_CHAIN",
+            SpvmError::LegacyTxRejected(_) => "LEGACY_TX_REJECTED",
+            SpvmError::InsufficientBalance { .. } => "INSUFFICIENT_BALANCE",
+            SpvmError::InsufficientAllowance { .. } => "INSUFFICIENT_ALLOWANCE",
+            SpvmError::BalanceOverflow => "BALANCE_OVERFLOW",
+            SpvmError::SupplyUnderflow(_) => "SUPPLY_UNDERFLOW",
+            SpvmError::TickerNotInitialized(_) => "TICKER_NOT_INITIALIZED",
+            SpvmError::TickerAlreadyInitialized(_) => "TICKER_ALREADY_INITIALIZED",
+            SpvmError::NotMintAuthority { .. } => "NOT_MINT_AUTHORITY",
+            SpvmError::UnsupportedTxType(_) => "UNSUPPORTED_TX_TYPE",
+            SpvmError::TxTypeMismatch(_) => "TX_TYPE_MISMATCH",
+            SpvmError::AmountOutOfRange(_) => "AMOUNT_OUT_OF_RANGE",
+            SpvmError::HashMismatch => "HASH_MISMATCH",
+            SpvmError::BadSignature(_) => "BAD_SIGNATURE",
+            SpvmError::Decode(_) => "DECODE_ERROR",
+            SpvmError::InvalidSnapshot(_) => "INVALID_SNAPSHOT",
+            SpvmError::MissingUndoJournal(_) => "MISSING_UNDO_JOURNAL",
+            SpvmError::InvalidBlock(_) => "INVALID_BLOCK",
+            SpvmError::Db(_) => "DB_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for SpvmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpvmError::InvalidNonce { expected, got } => {
+                write!(f, "Invalid nonce: expected {}, got {}", expected, got)
+            }
+            SpvmError::NonceOverflow(owner) => {
+                write!(
+                    f,
+                    "Nonce of {:#x} can't be incremented past {}",
+                    owner,
+                    u32::MAX
+                )
+            }
+            SpvmError::WrongChain { expected, got } => {
+                write!(f, "Wrong chain id: expected {}, got {}", expected, got)
+            }
+            SpvmError::LegacyTxRejected(chain_id) => write!(
+                f,
+                "Appchain {} doesn't accept legacy transactions, use the v2 transaction format",
+                chain_id
+            ),
+            SpvmError::InsufficientBalance { have, need } => {
+                write!(f, "Insufficient balance: have {}, need {}", have, need)
+            }
+            SpvmError::InsufficientAllowance { have, need } => {
+                write!(f, "Insufficient allowance: have {}, need {}", have, need)
+            }
+            SpvmError::BalanceOverflow => write!(f, "Balance overflow"),
+            SpvmError::SupplyUnderflow(ticker) => {
+                write!(f, "Total supply of {} is smaller than its balances", ticker)
+            }
+            SpvmError::TickerNotInitialized(ticker) => {
+                write!(f, "Token {} not initialized", ticker)
+            }
+            SpvmError::TickerAlreadyInitialized(ticker) => {
+                write!(f, "Token {} already initialized", ticker)
+            }
+            SpvmError::NotMintAuthority { ticker, sender } => {
+                write!(f, "{:#x} is not the mint authority of {}", sender, ticker)
+            }
+            SpvmError::UnsupportedTxType(tx_type) => {
+                write!(f, "Unsupported transaction type {}", tx_type)
+            }
+            SpvmError::TxTypeMismatch(tx_type) => {
+                write!(
+                    f,
+                    "Transaction type {} does not match its parameters",
+                    tx_type
+                )
+            }
+            SpvmError::AmountOutOfRange(amount) => write!(
+                f,
+                "Amount {} does not fit in uint16, use the v2 transaction format",
+                amount
+            ),
+            SpvmError::HashMismatch => write!(f, "Transaction hash mismatch"),
+            SpvmError::BadSignature(e) => write!(f, "Invalid signature: {}", e),
+            SpvmError::Decode(msg) => write!(f, "Decoding error: {}", msg),
+            SpvmError::InvalidSnapshot(msg) => write!(f, "Invalid snapshot: {}", msg),
+            SpvmError::MissingUndoJournal(number) => {
+                write!(
+                    f,
+                    "Block {} has no undo journal and can't be rolled back",
+                    number
+                )
+            }
+            SpvmError::InvalidBlock(msg) => write!(f, "Invalid block: {}", msg),
+            SpvmError::Db(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SpvmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpvmError::BadSignature(e) => Some(e),
+            SpvmError::Db(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DbErr> for SpvmError {
+    fn from(e: DbErr) -> Self {
+        SpvmError::Db(e)
+    }
+}
+
+impl From<SignatureError> for SpvmError {
+    fn from(e: SignatureError) -> Self {
+        SpvmError::BadSignature(e)
+    }
+}
+
+impl From<ethers::core::abi::Error> for SpvmError {
+    fn from(e: ethers::core::abi::Error) -> Self {
+        SpvmError::Decode(e.to_string())
+    }
+}
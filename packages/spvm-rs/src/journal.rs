@@ -0,0 +1,486 @@
+//! Undo journals, to roll the state of an appchain back to an earlier block.
+//!
+//! [`crate::execute_block`] records the value every balance, allowance, nonce and ticker had
+//! before the block overwrote it. [`rollback_to`] writes these values back, newest block first,
+//! and forgets the undone blocks, so the state can be re-derived when the L1 blocks it was built
+//! on are reorged out.
+
+use ::entity::{
+    allowances, block_transactions, blocks, initialized_tickers, nonces, receipts, state,
+    undo_journals,
+};
+use async_trait::async_trait;
+use ethers::types::{Address, U256};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::chain_column;
+use crate::block::{invalid_column, parse_column};
+use crate::{Appchain, SpvmError, StateBackend, TokenMetadata};
+
+/// A value of the state as it was before a block overwrote it. Zero balances, allowances and
+/// nonces and missing metadata stand for values that were not stored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum UndoEntry {
+    Balance {
+        ticker: String,
+        holder: Address,
+        before: U256,
+    },
+    Allowance {
+        ticker: String,
+        owner: Address,
+        spender: Address,
+        before: U256,
+    },
+    Nonce {
+        owner: Address,
+        before: u32,
+    },
+    Token {
+        ticker: String,
+        before: Option<TokenMetadata>,
+    },
+}
+
+// Records the previous value of everything written to the wrapped state. Undoing the entries in
+// reverse order restores the state as it was before the first write.
+pub(crate) struct Journaled<B: StateBackend> {
+    state: B,
+    pub(crate) entries: Vec<UndoEntry>,
+}
+
+impl<B: StateBackend> Journaled<B> {
+    pub(crate) fn new(state: B) -> Self {
+        Self {
+            state,
+            entries: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl<B: StateBackend> StateBackend for Journaled<B> {
+    fn chain_id(&self) -> u64 {
+        self.state.chain_id()
+    }
+
+    fn accepts_legacy_txs(&self) -> bool {
+        self.state.accepts_legacy_txs()
+    }
+
+    async fn get_balance(&self, ticker: &str, holder: Address) -> Result<U256, SpvmError> {
+        self.state.get_balance(ticker, holder).await
+    }
+
+    async fn set_balance(
+        &mut self,
+        ticker: &str,
+        holder: Address,
+        balance: U256,
+    ) -> Result<(), SpvmError> {
+        self.entries.push(UndoEntry::Balance {
+            ticker: ticker.to_string(),
+            holder,
+            before: self.state.get_balance(ticker, holder).await?,
+        });
+        self.state.set_balance(ticker, holder, balance).await
+    }
+
+    async fn get_allowance(
+        &self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+    ) -> Result<U256, SpvmError> {
+        self.state.get_allowance(ticker, owner, spender).await
+    }
+
+    async fn set_allowance(
+        &mut self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), SpvmError> {
+        self.entries.push(UndoEntry::Allowance {
+            ticker: ticker.to_string(),
+            owner,
+            spender,
+            before: self.state.get_allowance(ticker, owner, spender).await?,
+        });
+        self.state
+            .set_allowance(ticker, owner, spender, amount)
+            .await
+    }
+
+    async fn get_nonce(&self, owner: Address) -> Result<u32, SpvmError> {
+        self.state.get_nonce(owner).await
+    }
+
+    async fn bump_nonce(&mut self, owner: Address) -> Result<(), SpvmError> {
+        self.entries.push(UndoEntry::Nonce {
+            owner,
+            before: self.state.get_nonce(owner).await?,
+        });
+        self.state.bump_nonce(owner).await
+    }
+
+    async fn is_ticker_initialized(&self, ticker: &str) -> Result<bool, SpvmError> {
+        self.state.is_ticker_initialized(ticker).await
+    }
+
+    async fn initialize_ticker(&mut self, ticker: &str) -> Result<(), SpvmError> {
+        self.entries.push(UndoEntry::Token {
+            ticker: ticker.to_string(),
+            before: self.state.get_token_metadata(ticker).await?,
+        });
+        self.state.initialize_ticker(ticker).await
+    }
+
+    async fn get_token_metadata(&self, ticker: &str) -> Result<Option<TokenMetadata>, SpvmError> {
+        self.state.get_token_metadata(ticker).await
+    }
+
+    async fn set_token_metadata(&mut self, metadata: &TokenMetadata) -> Result<(), SpvmError> {
+        self.entries.push(UndoEntry::Token {
+            ticker: metadata.ticker.clone(),
+            before: self.state.get_token_metadata(&metadata.ticker).await?,
+        });
+        self.state.set_token_metadata(metadata).await
+    }
+}
+
+// Fails if block `block_number` already has a journal: its number was executed before and the
+// state must be rolled back first.
+pub(crate) async fn insert_undo_journal<C: ConnectionTrait>(
+    block_number: u32,
+    entries: &[UndoEntry],
+    appchain: &Appchain<'_, C>,
+) -> Result<(), SpvmError> {
+    let entries = serde_json::to_string(entries)
+        .map_err(|e| SpvmError::Decode(format!("Unserializable undo journal: {}", e)))?;
+
+    undo_journals::ActiveModel {
+        chain_id: Set(chain_column(appchain.id())),
+        block_number: Set(block_number.into()),
+        entries: Set(entries),
+    }
+    .insert(appchain.db())
+    .await?;
+
+    Ok(())
+}
+
+/// Rolls the state of `appchain` back to how it was after block `block_number`, or before its
+/// first block if `None`, and returns the numbers of the undone blocks, oldest first.
+///
+/// Every block executed after it is undone, whether it was stored or not. The undone blocks,
+/// their transactions and the receipts of their successful transactions are deleted; failure
+/// receipts stay on record. Fails without changing anything if a stored block to undo has no
+/// journal, like a block imported from a snapshot.
+pub async fn rollback_to<C>(
+    block_number: Option<u32>,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<u32>, SpvmError>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    let after = block_number.map_or(-1, i64::from);
+    let chain_id = chain_column(appchain.id());
+    let txn = appchain.begin().await?;
+    let batch = appchain.on(&txn);
+
+    let journals = undo_journals::Entity::find()
+        .filter(undo_journals::Column::ChainId.eq(chain_id))
+        .filter(undo_journals::Column::BlockNumber.gt(after))
+        .order_by_desc(undo_journals::Column::BlockNumber)
+        .all(&txn)
+        .await?;
+
+    let stored: Vec<i64> = blocks::Entity::find()
+        .select_only()
+        .column(blocks::Column::Number)
+        .filter(blocks::Column::ChainId.eq(chain_id))
+        .filter(blocks::Column::Number.gt(after))
+        .into_tuple()
+        .all(&txn)
+        .await?;
+    if let Some(number) = stored
+        .iter()
+        .find(|number| !journals.iter().any(|j| j.block_number == **number))
+    {
+        return Err(SpvmError::MissingUndoJournal(parse_column(
+            number,
+            "blocks.number",
+        )?));
+    }
+
+    let mut undone = Vec::with_capacity(journals.len());
+    for journal in &journals {
+        let entries: Vec<UndoEntry> = serde_json::from_str(&journal.entries)
+            .map_err(|e| invalid_column("undo_journals.entries", &journal.entries, e))?;
+        for entry in entries.iter().rev() {
+            undo(entry, &batch).await?;
+        }
+        undone.push(parse_column(
+            &journal.block_number,
+            "undo_journals.block_number",
+        )?);
+    }
+    undone.reverse();
+
+    undo_journals::Entity::delete_many()
+        .filter(undo_journals::Column::ChainId.eq(chain_id))
+        .filter(undo_journals::Column::BlockNumber.gt(after))
+        .exec(&txn)
+        .await?;
+    receipts::Entity::delete_many()
+        .filter(receipts::Column::ChainId.eq(chain_id))
+        .filter(receipts::Column::BlockNumber.gt(after))
+        .filter(receipts::Column::Success.eq(true))
+        .exec(&txn)
+        .await?;
+    // SQLite only enforces the cascade with foreign keys enabled
+    block_transactions::Entity::delete_many()
+        .filter(block_transactions::Column::ChainId.eq(chain_id))
+        .filter(block_transactions::Column::BlockNumber.gt(after))
+        .exec(&txn)
+        .await?;
+    blocks::Entity::delete_many()
+        .filter(blocks::Column::ChainId.eq(chain_id))
+        .filter(blocks::Column::Number.gt(after))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok(undone)
+}
+
+// Values that were not stored are deleted rather than written back as zero, so the rolled back
+// state has the same rows as before.
+async fn undo<C: ConnectionTrait>(
+    entry: &UndoEntry,
+    appchain: &Appchain<'_, C>,
+) -> Result<(), SpvmError> {
+    let chain_id = chain_column(appchain.id());
+    match entry {
+        UndoEntry::Balance {
+            ticker,
+            holder,
+            before,
+        } if before.is_zero() => {
+            state::Entity::delete_many()
+                .filter(state::Column::ChainId.eq(chain_id))
+                .filter(state::Column::Ticker.eq(ticker))
+                .filter(state::Column::OwnerAddress.eq(format!("{:#x}", holder)))
+                .exec(appchain.db())
+                .await?;
+        }
+        UndoEntry::Balance {
+            ticker,
+            holder,
+            before,
+        } => {
+            appchain
+                .state()
+                .set_balance(ticker, *holder, *before)
+                .await?;
+        }
+        UndoEntry::Allowance {
+            ticker,
+            owner,
+            spender,
+            before,
+        } if before.is_zero() => {
+            allowances::Entity::delete_many()
+                .filter(allowances::Column::ChainId.eq(chain_id))
+                .filter(allowances::Column::Ticker.eq(ticker))
+                .filter(allowances::Column::OwnerAddress.eq(format!("{:#x}", owner)))
+                .filter(allowances::Column::SpenderAddress.eq(format!("{:#x}", spender)))
+                .exec(appchain.db())
+                .await?;
+        }
+        UndoEntry::Allowance {
+            ticker,
+            owner,
+            spender,
+            before,
+        } => {
+            appchain
+                .state()
+                .set_allowance(ticker, *owner, *spender, *before)
+                .await?;
+        }
+        UndoEntry::Nonce { owner, before } => {
+            let record = nonces::Entity::find()
+                .filter(nonces::Column::ChainId.eq(chain_id))
+                .filter(nonces::Column::OwnerAddress.eq(format!("{:#x}", owner)))
+                .one(appchain.db())
+                .await?;
+            match record {
+                Some(record) if *before == 0 => {
+                    nonces::Entity::delete(record.into_active_model())
+                        .exec(appchain.db())
+                        .await?;
+                }
+                Some(record) => {
+                    let mut active_record = record.into_active_model();
+                    active_record.nonce = Set(i64::from(*before));
+                    active_record.update(appchain.db()).await?;
+                }
+                None if *before == 0 => {}
+                None => {
+                    nonces::ActiveModel {
+                        chain_id: Set(chain_id),
+                        owner_address: Set(format!("{:#x}", owner)),
+                        nonce: Set(i64::from(*before)),
+                    }
+                    .insert(appchain.db())
+                    .await?;
+                }
+            }
+        }
+        UndoEntry::Token {
+            ticker,
+            before: None,
+        } => {
+            initialized_tickers::Entity::delete_many()
+                .filter(initialized_tickers::Column::ChainId.eq(chain_id))
+                .filter(initialized_tickers::Column::Ticker.eq(ticker))
+                .exec(appchain.db())
+                .await?;
+        }
+        UndoEntry::Token {
+            before: Some(metadata),
+            ..
+        } => {
+            appchain.state().set_token_metadata(metadata).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::tests::populate;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::H256,
+    };
+
+    #[async_std::test]
+    async fn rollback_undoes_blocks() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let b1_txs = vec![
+            create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0),
+            create_transfer_transaction("ABC", &wallet, wallet2.address(), 30, 1),
+        ];
+        execute_block(1, &b1_txs, &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+        let mut b1 = create_block(&wallet, 1, H256::zero(), b1_txs.clone());
+        b1.state_root = state_root(&appchain(&connection)).await.unwrap();
+        insert_block(&b1, &appchain(&connection)).await.unwrap();
+        let after_b1 = export_snapshot(&appchain(&connection)).await.unwrap();
+
+        // Overwrites every kind of value, and empties a balance
+        let t3 = create_approve_transaction("ABC", &wallet, wallet2.address(), 20, 2);
+        let b2_txs = vec![
+            t3.clone(),
+            create_transfer_transaction("ABC", &wallet, wallet2.address(), 70, 3),
+            create_mint_transaction("XYZ", &wallet, wallet.address(), 5, 4),
+        ];
+        execute_block(2, &b2_txs, &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+        let mut b2 = create_block(&wallet, 2, b1.contract_hash(), b2_txs);
+        b2.state_root = state_root(&appchain(&connection)).await.unwrap();
+        insert_block(&b2, &appchain(&connection)).await.unwrap();
+
+        // Executed but never stored
+        let t6 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 10, 0);
+        execute_block(3, &[t6], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rollback_to(Some(1), &appchain(&connection)).await.unwrap(),
+            vec![2, 3]
+        );
+        assert_eq!(
+            export_snapshot(&appchain(&connection)).await.unwrap(),
+            after_b1
+        );
+        assert!(get_block_receipts(2, &appchain(&connection))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            get_transaction(t3.tx_hash, &appchain(&connection))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            rollback_to(Some(1), &appchain(&connection)).await.unwrap(),
+            Vec::<u32>::new()
+        );
+
+        assert_eq!(
+            rollback_to(None, &appchain(&connection)).await.unwrap(),
+            vec![1]
+        );
+        assert_eq!(
+            state_root(&appchain(&connection)).await.unwrap(),
+            H256::zero()
+        );
+        assert!(list_tickers(&appchain(&connection))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            get_latest_block(&appchain(&connection)).await.unwrap(),
+            None
+        );
+
+        // The same block can be executed again
+        execute_block(1, &b1_txs, &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+        assert_eq!(
+            state_root(&appchain(&connection)).await.unwrap(),
+            b1.state_root
+        );
+    }
+
+    #[async_std::test]
+    async fn rollback_needs_undo_journals() {
+        let (wallet, connection) = setup().await;
+        let snapshot = populate(&wallet, &connection).await;
+        assert!(matches!(
+            execute_block(1, &[], &SCHEME, &appchain(&connection)).await,
+            Err(SpvmError::Db(_))
+        ));
+
+        // Imported blocks were executed elsewhere
+        let (_, fresh) = setup().await;
+        import_snapshot(&snapshot, &appchain(&fresh)).await.unwrap();
+        assert!(matches!(
+            rollback_to(None, &appchain(&fresh)).await,
+            Err(SpvmError::MissingUndoJournal(1))
+        ));
+        assert_eq!(export_snapshot(&appchain(&fresh)).await.unwrap(), snapshot);
+    }
+}
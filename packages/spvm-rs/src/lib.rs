@@ -1,51 +1,226 @@
-use ::entity::{initialized_tickers, nonces, state};
-use ethers::{
-    core::abi::{decode, encode, ParamType, Token},
-    core::utils::keccak256,
-    types::{Address, Bytes, Signature, TxHash, U256},
-};
-use sea_orm::{
-    entity::*, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
-};
+use ethers::types::{Address, Signature, TxHash, U256};
+use sea_orm::{ConnectionTrait, TransactionTrait};
 use serde::{Deserialize, Serialize};
 use std::default::Default;
-use std::error::Error;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+mod appchain;
+mod backend;
+mod block;
+mod codec;
+mod error;
+mod journal;
+mod merkle;
+mod query;
+mod receipt;
+mod signing;
+mod simulate;
+mod snapshot;
+mod sync;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
+pub use appchain::Appchain;
+pub use backend::{InMemoryBackend, SeaOrmBackend, StateBackend};
+pub use block::{
+    get_block, get_block_by_contract_hash, get_block_by_hash, get_l1_origin, get_latest_block,
+    get_transaction, index_contract_hashes, insert_block, set_l1_origin, Block, BlockTransaction,
+    L1Origin,
+};
+pub use codec::*;
+pub use error::SpvmError;
+pub use journal::{rollback_to, UndoEntry};
+pub use merkle::{balance_key, nonce_key, state_root, BalanceProof, StateTree};
+pub use query::{get_balances, get_total_supply, list_holders, list_tickers, TokenBalance};
+pub use receipt::{
+    execute_block, get_block_receipts, get_receipts, get_receipts_by_sender, Receipt, ReceiptStatus,
+};
+pub use signing::*;
+pub use simulate::{
+    simulate_transactions, simulate_transactions_on, BalanceChange, NonceChange, Simulation,
+    SimulationFailure,
+};
+pub use snapshot::{
+    export_snapshot, import_snapshot, AccountNonce, Snapshot, TokenAllowance, SNAPSHOT_VERSION,
+};
+pub use sync::{contract_block_hash, sync_block, ContractBlock, CONTRACT_SIGNATURE_SCHEME};
+
+/// Flag set in `TransactionContent::tx_type` by transactions using the v2 format, whose amounts
+/// are full uint256 values and which are bound to one appchain by `chain_id`. Transactions
+/// without it use the legacy format understood by the SPVM-1 contract, where amounts must fit in
+/// a uint16 and there is no chain id.
+pub const TX_FORMAT_V2: u8 = 0x80;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct TransactionContent {
     pub from: Address,
-    pub tx_type: u8, // Only the first 2 bits used
+    pub tx_type: u8, // Low bits select the transaction kind, see TX_FORMAT_V2 for the top bit
     pub tx_param: TransactionParams,
     pub nonce: u32,
+    /// Appchain the transaction is for. Only v2 transactions carry one; legacy transactions
+    /// must leave it at 0 and are valid on any appchain, as on SPVM-1.
+    #[serde(default)]
+    pub chain_id: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TransactionParams {
     Mint(MintTransactionParams),
     Transfer(TransferTransactionParams),
+    Burn(BurnTransactionParams),
+    CreateToken(CreateTokenTransactionParams),
+    Issue(IssueTransactionParams),
+    Approve(ApproveTransactionParams),
+    TransferFrom(TransferFromTransactionParams),
+    MultiTransfer(MultiTransferTransactionParams),
+}
+
+impl TransactionParams {
+    /// The `tx_type` selecting these parameters, without the [`TX_FORMAT_V2`] flag.
+    pub fn tx_type(&self) -> u8 {
+        match self {
+            TransactionParams::Mint(_) => 0,
+            TransactionParams::Transfer(_) => 1,
+            TransactionParams::Burn(_) => 2,
+            TransactionParams::CreateToken(_) => 3,
+            TransactionParams::Issue(_) => 4,
+            TransactionParams::Approve(_) => 5,
+            TransactionParams::TransferFrom(_) => 6,
+            TransactionParams::MultiTransfer(_) => 7,
+        }
+    }
+
+    /// Every amount the parameters carry, supplies included.
+    pub fn amounts(&self) -> Vec<U256> {
+        match self {
+            TransactionParams::Mint(params) => vec![params.supply],
+            TransactionParams::Transfer(params) => vec![params.amount],
+            TransactionParams::Burn(params) => vec![params.amount],
+            TransactionParams::CreateToken(params) => vec![params.supply],
+            TransactionParams::Issue(params) => vec![params.amount],
+            TransactionParams::Approve(params) => vec![params.amount],
+            TransactionParams::TransferFrom(params) => vec![params.amount],
+            TransactionParams::MultiTransfer(params) => {
+                params.transfers.iter().map(|(_, amount)| *amount).collect()
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct MintTransactionParams {
     pub token_ticker: String,
     pub owner: Address,
-    pub supply: u16,
+    pub supply: U256,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct TransferTransactionParams {
     pub token_ticker: String,
     pub to: Address,
-    pub amount: u16,
+    pub amount: U256,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BurnTransactionParams {
+    pub token_ticker: String,
+    pub amount: U256,
+}
+
+/// Creates a ticker with metadata. The sender becomes its mint authority.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CreateTokenTransactionParams {
+    pub token_ticker: String,
+    pub owner: Address,
+    pub supply: U256,
+    pub decimals: u8,
+    pub name: String,
+}
+
+/// Mints more supply of an existing ticker. Only its mint authority can send it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct IssueTransactionParams {
+    pub token_ticker: String,
+    pub to: Address,
+    pub amount: U256,
+}
+
+/// Allows `spender` to transfer up to `amount` of the sender's tokens. Replaces any previous
+/// allowance of the same spender.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ApproveTransactionParams {
+    pub token_ticker: String,
+    pub spender: Address,
+    pub amount: U256,
+}
+
+/// Transfers tokens of `owner`, who approved the sender as a spender beforehand.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TransferFromTransactionParams {
+    pub token_ticker: String,
+    pub owner: Address,
+    pub to: Address,
+    pub amount: U256,
+}
+
+/// Transfers one ticker to several recipients, in order. The sender needs enough balance for
+/// the sum of all amounts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MultiTransferTransactionParams {
+    pub token_ticker: String,
+    pub transfers: Vec<(Address, U256)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub ticker: String,
+    /// Address allowed to issue more supply. Tickers created before mint authorities existed
+    /// have none.
+    pub mint_authority: Option<Address>,
+    pub decimals: u8,
+    pub name: String,
+    /// Supply created by mints and issues, minus burns. Always the sum of the balances of the
+    /// ticker, and the only record of it.
+    pub total_supply: U256,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl TokenMetadata {
+    /// Metadata of a ticker initialized without any: no mint authority, no decimals and the
+    /// ticker as its name.
+    pub fn new(ticker: &str) -> Self {
+        Self {
+            ticker: ticker.to_string(),
+            mint_authority: None,
+            decimals: 0,
+            name: ticker.to_string(),
+            total_supply: U256::zero(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Transaction {
     pub tx_content: TransactionContent,
     pub tx_hash: TxHash,
     pub signature: Signature,
 }
 
+/// A preconfirmation request, `PreconfirmationRequest` in `PreconfirmationsTypes.sol`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreconfirmationPayload {
+    pub transaction: Transaction,
+    pub tip_tx: Transaction,
+    pub preconfer_contract: Address,
+}
+
+/// An enforcer's signature over [`encode_preconf_payload`] of the request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreconfirmationCommitment {
+    pub preconfirmation_request: PreconfirmationPayload,
+    pub commitment: Signature,
+    pub signer: Address,
+    pub block_number: U256,
+}
+
 impl Default for TransactionParams {
     fn default() -> Self {
         Self::Mint(MintTransactionParams::default())
@@ -67,203 +242,345 @@ impl Default for Transaction {
 }
 
 impl TransactionContent {
-    pub async fn execute_raw_transaction(
+    /// Validates and applies the transaction to the state of `appchain` inside a single
+    /// database transaction, so either every state change (balances, ticker initialization,
+    /// nonce) is committed or none is.
+    pub async fn execute_raw_transaction<C>(
         &self,
-        db: &DatabaseConnection,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.check_validity(db).await?;
+        appchain: &Appchain<'_, C>,
+    ) -> Result<(), SpvmError>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        let txn = appchain.begin().await?;
+        self.execute_on(&mut appchain.on(&txn).state()).await?;
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    /// Validates and applies the transaction to an arbitrary [`StateBackend`].
+    pub async fn execute_on<B: StateBackend>(&self, state: &mut B) -> Result<(), SpvmError> {
+        self.check_validity_on(state).await?;
         match &self.tx_param {
             TransactionParams::Mint(params) => {
-                Self::set_balance(&params.token_ticker, params.owner, params.supply, db).await?;
+                let metadata = TokenMetadata {
+                    mint_authority: Some(self.from),
+                    total_supply: params.supply,
+                    ..TokenMetadata::new(&params.token_ticker)
+                };
+                state.set_token_metadata(&metadata).await?;
+                state
+                    .set_balance(&params.token_ticker, params.owner, params.supply)
+                    .await?;
             }
             TransactionParams::Transfer(params) => {
-                let sender_balance = Self::get_balance(&params.token_ticker, self.from, db).await?;
-                println!("Sender balance: {:?}", sender_balance);
-                let receiver_balance =
-                    Self::get_balance(&params.token_ticker, params.to, db).await?;
-                Self::set_balance(
+                transfer_balance(
+                    state,
                     &params.token_ticker,
                     self.from,
-                    sender_balance - params.amount,
-                    db,
-                )
-                .await?;
-                Self::set_balance(
-                    &params.token_ticker,
                     params.to,
-                    receiver_balance + params.amount,
-                    db,
+                    params.amount,
                 )
                 .await?;
             }
-        }
-
-        let nonce = nonces::Entity::find()
-            .filter(nonces::Column::OwnerAddress.eq(self.from.to_string()))
-            .one(db)
-            .await?;
+            TransactionParams::Burn(params) => {
+                let balance = state.get_balance(&params.token_ticker, self.from).await?;
+                let new_balance =
+                    balance
+                        .checked_sub(params.amount)
+                        .ok_or(SpvmError::InsufficientBalance {
+                            have: balance,
+                            need: params.amount,
+                        })?;
+                state
+                    .set_balance(&params.token_ticker, self.from, new_balance)
+                    .await?;
 
-        match nonce {
-            Some(record) => {
-                let mut active_record = record.clone().into_active_model();
-                active_record.nonce = Set(record.nonce + 1);
-                active_record.update(db).await?;
+                let mut metadata = token_metadata(state, &params.token_ticker).await?;
+                metadata.total_supply = metadata
+                    .total_supply
+                    .checked_sub(params.amount)
+                    .ok_or_else(|| SpvmError::SupplyUnderflow(params.token_ticker.clone()))?;
+                state.set_token_metadata(&metadata).await?;
             }
-            None => {
-                let record = nonces::ActiveModel {
-                    owner_address: Set(self.from.to_string()),
-                    nonce: Set(1),
+            TransactionParams::CreateToken(params) => {
+                let metadata = TokenMetadata {
+                    ticker: params.token_ticker.clone(),
+                    mint_authority: Some(self.from),
+                    decimals: params.decimals,
+                    name: params.name.clone(),
+                    total_supply: params.supply,
                 };
-                record.insert(db).await?;
+                state.set_token_metadata(&metadata).await?;
+                state
+                    .set_balance(&params.token_ticker, params.owner, params.supply)
+                    .await?;
+            }
+            TransactionParams::Issue(params) => {
+                let mut metadata = token_metadata(state, &params.token_ticker).await?;
+                metadata.total_supply = metadata
+                    .total_supply
+                    .checked_add(params.amount)
+                    .ok_or(SpvmError::BalanceOverflow)?;
+                let new_balance = state
+                    .get_balance(&params.token_ticker, params.to)
+                    .await?
+                    .checked_add(params.amount)
+                    .ok_or(SpvmError::BalanceOverflow)?;
+
+                state.set_token_metadata(&metadata).await?;
+                state
+                    .set_balance(&params.token_ticker, params.to, new_balance)
+                    .await?;
+            }
+            TransactionParams::Approve(params) => {
+                state
+                    .set_allowance(
+                        &params.token_ticker,
+                        self.from,
+                        params.spender,
+                        params.amount,
+                    )
+                    .await?;
+            }
+            TransactionParams::TransferFrom(params) => {
+                let allowance = state
+                    .get_allowance(&params.token_ticker, params.owner, self.from)
+                    .await?;
+                let new_allowance = allowance.checked_sub(params.amount).ok_or(
+                    SpvmError::InsufficientAllowance {
+                        have: allowance,
+                        need: params.amount,
+                    },
+                )?;
+
+                transfer_balance(
+                    state,
+                    &params.token_ticker,
+                    params.owner,
+                    params.to,
+                    params.amount,
+                )
+                .await?;
+                state
+                    .set_allowance(&params.token_ticker, params.owner, self.from, new_allowance)
+                    .await?;
+            }
+            TransactionParams::MultiTransfer(params) => {
+                for (to, amount) in &params.transfers {
+                    transfer_balance(state, &params.token_ticker, self.from, *to, *amount).await?;
+                }
             }
         }
 
-        Ok(())
+        state.bump_nonce(self.from).await
     }
 
-    pub async fn check_validity(
+    pub async fn check_validity<C: ConnectionTrait>(
         &self,
-        db: &DatabaseConnection,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        let nonce = nonces::Entity::find()
-            .filter(nonces::Column::OwnerAddress.eq(self.from.to_string()))
-            .one(db)
-            .await?;
-
-        match nonce {
-            Some(record) => {
-                if record.nonce != self.nonce as i32 {
-                    return Err("Invalid nonce".into());
-                }
+        appchain: &Appchain<'_, C>,
+    ) -> Result<bool, SpvmError> {
+        self.check_validity_on(&appchain.state()).await
+    }
+
+    pub async fn check_validity_on<B: StateBackend>(&self, state: &B) -> Result<bool, SpvmError> {
+        let expected_chain = if self.tx_type & TX_FORMAT_V2 == 0 {
+            if !state.accepts_legacy_txs() {
+                return Err(SpvmError::LegacyTxRejected(state.chain_id()));
             }
-            None => {
-                if self.nonce != 0 {
-                    return Err("Invalid nonce".into());
-                }
+            0
+        } else {
+            state.chain_id()
+        };
+        if self.chain_id != expected_chain {
+            return Err(SpvmError::WrongChain {
+                expected: expected_chain,
+                got: self.chain_id,
+            });
+        }
+
+        // Several kinds share a parameter layout, so the signed hash only commits to the kind
+        // through `tx_type`. Contents built from JSON could otherwise pair one with another's
+        // parameters, or carry amounts a legacy transaction can't encode.
+        if self.tx_type & !TX_FORMAT_V2 != self.tx_param.tx_type() {
+            return Err(SpvmError::TxTypeMismatch(self.tx_type));
+        }
+        if self.tx_type & TX_FORMAT_V2 == 0 {
+            if let Some(amount) = self
+                .tx_param
+                .amounts()
+                .into_iter()
+                .find(|amount| *amount > U256::from(u16::MAX))
+            {
+                return Err(SpvmError::AmountOutOfRange(amount));
             }
         }
 
+        let expected = state.get_nonce(self.from).await?;
+        if expected != self.nonce {
+            return Err(SpvmError::InvalidNonce {
+                expected,
+                got: self.nonce,
+            });
+        }
+
         match &self.tx_param {
             TransactionParams::Mint(params) => {
-                let initialized = initialized_tickers::Entity::find()
-                    .filter(initialized_tickers::Column::Ticker.eq(&params.token_ticker))
-                    .one(db)
-                    .await?;
-
-                match initialized {
-                    Some(record) => {
-                        if record.is_initialized {
-                            Err("Token already initialized".into())
-                        } else {
-                            Ok(true)
-                        }
-                    }
-                    None => Ok(true),
+                if state.is_ticker_initialized(&params.token_ticker).await? {
+                    return Err(SpvmError::TickerAlreadyInitialized(
+                        params.token_ticker.clone(),
+                    ));
                 }
             }
             TransactionParams::Transfer(params) => {
-                let initialized = initialized_tickers::Entity::find()
-                    .filter(initialized_tickers::Column::Ticker.eq(&params.token_ticker))
-                    .one(db)
-                    .await?;
+                if !state.is_ticker_initialized(&params.token_ticker).await? {
+                    return Err(SpvmError::TickerNotInitialized(params.token_ticker.clone()));
+                }
 
-                println!("Token ticker {:?}", params.token_ticker);
-                match initialized {
-                    Some(record) => {
-                        if !record.is_initialized {
-                            return Err("Token not initialized (Some)".into());
-                        }
-                    }
-                    None => return Err("Token not initialized (None)".into()),
+                let have = state.get_balance(&params.token_ticker, self.from).await?;
+                if have < params.amount {
+                    return Err(SpvmError::InsufficientBalance {
+                        have,
+                        need: params.amount,
+                    });
+                }
+            }
+            TransactionParams::Burn(params) => {
+                if !state.is_ticker_initialized(&params.token_ticker).await? {
+                    return Err(SpvmError::TickerNotInitialized(params.token_ticker.clone()));
+                }
+
+                let have = state.get_balance(&params.token_ticker, self.from).await?;
+                if have < params.amount {
+                    return Err(SpvmError::InsufficientBalance {
+                        have,
+                        need: params.amount,
+                    });
+                }
+            }
+            TransactionParams::CreateToken(params) => {
+                if state.is_ticker_initialized(&params.token_ticker).await? {
+                    return Err(SpvmError::TickerAlreadyInitialized(
+                        params.token_ticker.clone(),
+                    ));
+                }
+            }
+            TransactionParams::Issue(params) => {
+                let metadata = token_metadata(state, &params.token_ticker).await?;
+                if metadata.mint_authority != Some(self.from) {
+                    return Err(SpvmError::NotMintAuthority {
+                        ticker: params.token_ticker.clone(),
+                        sender: self.from,
+                    });
+                }
+            }
+            TransactionParams::Approve(params) => {
+                if !state.is_ticker_initialized(&params.token_ticker).await? {
+                    return Err(SpvmError::TickerNotInitialized(params.token_ticker.clone()));
+                }
+            }
+            TransactionParams::TransferFrom(params) => {
+                if !state.is_ticker_initialized(&params.token_ticker).await? {
+                    return Err(SpvmError::TickerNotInitialized(params.token_ticker.clone()));
                 }
 
-                let balance = state::Entity::find()
-                    .filter(state::Column::Ticker.eq(&params.token_ticker))
-                    .filter(state::Column::OwnerAddress.eq(format!("{:#x}", self.from)))
-                    .one(db)
+                let allowance = state
+                    .get_allowance(&params.token_ticker, params.owner, self.from)
                     .await?;
+                if allowance < params.amount {
+                    return Err(SpvmError::InsufficientAllowance {
+                        have: allowance,
+                        need: params.amount,
+                    });
+                }
 
-                println!("Params amount {:?}", params.amount);
-                match balance {
-                    Some(record) => {
-                        println!("Record amount {:?}", record.amount);
-                        if record.amount < params.amount as i32 {
-                            return Err("Insufficient balance".into());
-                        }
-                    }
-                    None => return Err("Insufficient balance".into()),
+                let have = state
+                    .get_balance(&params.token_ticker, params.owner)
+                    .await?;
+                if have < params.amount {
+                    return Err(SpvmError::InsufficientBalance {
+                        have,
+                        need: params.amount,
+                    });
+                }
+            }
+            TransactionParams::MultiTransfer(params) => {
+                if !state.is_ticker_initialized(&params.token_ticker).await? {
+                    return Err(SpvmError::TickerNotInitialized(params.token_ticker.clone()));
                 }
 
-                Ok(true)
+                // Check the whole batch up front so it can't fail halfway through
+                let need = params
+                    .transfers
+                    .iter()
+                    .try_fold(U256::zero(), |total, (_, amount)| {
+                        total.checked_add(*amount)
+                    })
+                    .ok_or(SpvmError::BalanceOverflow)?;
+                let have = state.get_balance(&params.token_ticker, self.from).await?;
+                if have < need {
+                    return Err(SpvmError::InsufficientBalance { have, need });
+                }
             }
         }
+
+        Ok(true)
     }
 
-    pub async fn set_balance(
+    pub async fn set_balance<C: ConnectionTrait>(
         ticker: &str,
         holder_address: Address,
-        balance: u16,
-        db: &DatabaseConnection,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        balance: U256,
+        appchain: &Appchain<'_, C>,
+    ) -> Result<(), SpvmError> {
+        let mut state = appchain.state();
         // Initialise ticker if not present or if set to False
-        let initialized = initialized_tickers::Entity::find()
-            .filter(initialized_tickers::Column::Ticker.eq(ticker))
-            .one(db)
-            .await?;
-
-        match initialized {
-            Some(record) => {
-                let mut active_record = record.into_active_model();
-                active_record.is_initialized = Set(true);
-                active_record.update(db).await?;
-            }
-            None => {
-                let record = initialized_tickers::ActiveModel {
-                    ticker: Set(ticker.to_string()),
-                    is_initialized: Set(true),
-                };
-                record.insert(db).await?;
-            }
-        }
+        state.initialize_ticker(ticker).await?;
+
+        // The supply follows the balance so it stays the sum of all balances
+        let mut metadata = token_metadata(&state, ticker).await?;
+        let before = state.get_balance(ticker, holder_address).await?;
+        metadata.total_supply = metadata
+            .total_supply
+            .checked_sub(before)
+            .ok_or_else(|| SpvmError::SupplyUnderflow(ticker.to_string()))?
+            .checked_add(balance)
+            .ok_or(SpvmError::BalanceOverflow)?;
+
+        state.set_token_metadata(&metadata).await?;
+        state.set_balance(ticker, holder_address, balance).await
+    }
 
-        // Update balance
-        let state = state::Entity::find()
-            .filter(state::Column::Ticker.eq(ticker))
-            .filter(state::Column::OwnerAddress.eq(holder_address.to_string()))
-            .one(db)
-            .await?;
-
-        match state {
-            Some(record) => {
-                let mut active_record = record.into_active_model();
-                active_record.amount = Set(balance as i32);
-                active_record.update(db).await?;
-            }
-            None => {
-                let record = state::ActiveModel {
-                    ticker: Set(ticker.to_string()),
-                    owner_address: Set(holder_address.to_string()),
-                    amount: Set(balance as i32),
-                };
-                record.insert(db).await?;
-            }
-        }
+    pub async fn get_balance<C: ConnectionTrait>(
+        ticker: &str,
+        holder_address: Address,
+        appchain: &Appchain<'_, C>,
+    ) -> Result<U256, SpvmError> {
+        appchain.state().get_balance(ticker, holder_address).await
+    }
 
-        Ok(())
+    pub async fn get_allowance<C: ConnectionTrait>(
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+        appchain: &Appchain<'_, C>,
+    ) -> Result<U256, SpvmError> {
+        appchain.state().get_allowance(ticker, owner, spender).await
     }
 
-    pub async fn get_balance(
+    pub async fn get_token_metadata<C: ConnectionTrait>(
         ticker: &str,
-        holder_address: Address,
-        db: &DatabaseConnection,
-    ) -> Result<u16, Box<dyn std::error::Error>> {
-        let balance = state::Entity::find()
-            .filter(state::Column::Ticker.eq(ticker))
-            .filter(state::Column::OwnerAddress.eq(holder_address.to_string()))
-            .one(db)
-            .await?;
+        appchain: &Appchain<'_, C>,
+    ) -> Result<Option<TokenMetadata>, SpvmError> {
+        appchain.state().get_token_metadata(ticker).await
+    }
 
-        Ok(balance.map_or(0, |record| record.amount as u16))
+    pub async fn get_total_supply<C: ConnectionTrait>(
+        ticker: &str,
+        appchain: &Appchain<'_, C>,
+    ) -> Result<U256, SpvmError> {
+        query::get_total_supply(ticker, appchain).await
     }
 }
 
@@ -272,272 +589,116 @@ impl Transaction {
         self.signature.verify(self.tx_hash, self.tx_content.from)
     }
 
-    pub async fn execute_transaction(
-        &self,
-        db: &DatabaseConnection,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let message = encode_tx_content(&self.tx_content);
-        let hash = keccak256(message);
-
-        if self.tx_hash == TxHash::from_slice(&hash) {
-            self.validate_signature()?;
-            // let connection = Connection::open(db)?;
-            self.tx_content.execute_raw_transaction(db).await?;
-            Ok(())
-        } else {
-            Err("Transaction hash mismatch".into())
+    /// Checks that `tx_hash` is the hash of the content under `scheme` and that the sender
+    /// signed it.
+    pub fn validate_hash_and_signature(&self, scheme: &SignatureScheme) -> Result<(), SpvmError> {
+        if self.tx_hash != scheme.tx_hash(&self.tx_content) {
+            return Err(SpvmError::HashMismatch);
         }
-    }
-}
+        self.validate_signature()?;
 
-pub fn encode_tx_params(tx_params: &TransactionParams) -> Bytes {
-    match tx_params {
-        TransactionParams::Mint(params) => {
-            let tokens = Token::Tuple(vec![
-                Token::String(params.token_ticker.clone()),
-                Token::Address(params.owner),
-                Token::Uint(U256::from(params.supply)),
-            ]);
-            encode(&[tokens]).into()
-        }
-        TransactionParams::Transfer(params) => {
-            let tokens = Token::Tuple(vec![
-                Token::String(params.token_ticker.clone()),
-                Token::Address(params.to),
-                Token::Uint(U256::from(params.amount)),
-            ]);
-            encode(&[tokens]).into()
-        }
+        Ok(())
     }
-}
-
-pub fn encode_tx_content(tx_content: &TransactionContent) -> Bytes {
-    let encoded_tx_param = encode_tx_params(&tx_content.tx_param);
-
-    let tokens = Token::Tuple(vec![
-        Token::Address(tx_content.from),
-        Token::Uint(U256::from(tx_content.tx_type)),
-        Token::Bytes(encoded_tx_param.to_vec()),
-        Token::Uint(U256::from(tx_content.nonce)),
-    ]);
 
-    encode(&[tokens]).into()
-}
-
-pub fn decode_tx_content(data: &str) -> Result<TransactionContent, Box<dyn Error>> {
-    let tokens = decode(
-        &[ParamType::Tuple(vec![
-            ParamType::Address,
-            ParamType::Uint(8),
-            ParamType::Bytes,
-            ParamType::Uint(32),
-        ])],
-        &hex::decode(data).unwrap(),
-    )?;
-
-    let tokens = match &tokens[0] {
-        Token::Tuple(t) => t.clone(),
-        _ => return Err("Expected Tuple type".into()),
-    };
-
-    if tokens.len() != 4 {
-        return Err("Incorrect number of tokens".into());
+    pub async fn execute_transaction<C>(
+        &self,
+        scheme: &SignatureScheme,
+        appchain: &Appchain<'_, C>,
+    ) -> Result<(), SpvmError>
+    where
+        C: ConnectionTrait + TransactionTrait,
+    {
+        self.validate_hash_and_signature(scheme)?;
+        self.tx_content.execute_raw_transaction(appchain).await
     }
 
-    let from = match &tokens[0] {
-        Token::Address(addr) => *addr,
-        _ => return Err("Expected Address type for 'from'".into()),
-    };
-
-    let tx_type = match &tokens[1] {
-        Token::Uint(u) => u.low_u32() as u8,
-
-        _ => return Err("Expected Uint type for 'tx_type'".into()),
-    };
-
-    let tx_param_tokens = match &tokens[2] {
-        Token::Bytes(b) => decode(
-            &[ParamType::Tuple(vec![
-                ParamType::String,
-                ParamType::Address,
-                ParamType::Uint(16),
-            ])],
-            b,
-        )?,
-        _ => return Err("Expected Bytes type for 'tx_param'".into()),
-    };
-
-    let tx_params = decode_transaction_params(tx_type, tx_param_tokens)?;
+    /// Checks the hash and signature, then applies the transaction to `state`.
+    pub async fn execute_on<B: StateBackend>(
+        &self,
+        scheme: &SignatureScheme,
+        state: &mut B,
+    ) -> Result<(), SpvmError> {
+        self.validate_hash_and_signature(scheme)?;
+        self.tx_content.execute_on(state).await
+    }
+}
 
-    let nonce = match &tokens[3] {
-        Token::Uint(u) => u.low_u32(),
-        _ => return Err("Expected Uint type for 'nonce'".into()),
+// Moves `amount` of `ticker` from `from` to `to`.
+async fn transfer_balance<B: StateBackend>(
+    state: &mut B,
+    ticker: &str,
+    from: Address,
+    to: Address,
+    amount: U256,
+) -> Result<(), SpvmError> {
+    let sender_balance = state.get_balance(ticker, from).await?;
+    let new_sender_balance =
+        sender_balance
+            .checked_sub(amount)
+            .ok_or(SpvmError::InsufficientBalance {
+                have: sender_balance,
+                need: amount,
+            })?;
+    // Credit on top of the debited balance so a self-transfer is a no-op
+    let receiver_balance = if to == from {
+        new_sender_balance
+    } else {
+        state.get_balance(ticker, to).await?
     };
+    let new_receiver_balance = receiver_balance
+        .checked_add(amount)
+        .ok_or(SpvmError::BalanceOverflow)?;
 
-    Ok(TransactionContent {
-        from,
-        tx_type,
-        tx_param: tx_params,
-        nonce,
-    })
+    state.set_balance(ticker, from, new_sender_balance).await?;
+    state.set_balance(ticker, to, new_receiver_balance).await
 }
 
-pub fn decode_transaction_params(
-    tx_type: u8,
-    params: Vec<Token>,
-) -> Result<TransactionParams, Box<dyn Error>> {
-    let params = match &params[0] {
-        Token::Tuple(t) => t.clone(),
-        _ => return Err("Expected Tuple type".into()),
-    };
+// Metadata of a ticker that transactions require to be initialized.
+async fn token_metadata<B: StateBackend>(
+    state: &B,
+    ticker: &str,
+) -> Result<TokenMetadata, SpvmError> {
+    state
+        .get_token_metadata(ticker)
+        .await?
+        .ok_or_else(|| SpvmError::TickerNotInitialized(ticker.to_string()))
+}
 
-    match tx_type {
-        0 => {
-            if params.len() != 3 {
-                return Err("Invalid number of parameters for Mint".into());
-            }
-            let token_ticker = match &params[0] {
-                Token::String(s) => s.clone(),
-                _ => return Err("Expected string for token_ticker".into()),
-            };
-            let owner = match &params[1] {
-                Token::Address(addr) => *addr,
-                _ => return Err("Expected address for owner".into()),
-            };
-            let supply = match &params[2] {
-                Token::Uint(u) => u.as_u32() as u16,
-                _ => return Err("Expected uint for supply".into()),
-            };
-            Ok(TransactionParams::Mint(MintTransactionParams {
-                token_ticker,
-                owner,
-                supply,
-            }))
-        }
-        1 => {
-            if params.len() != 3 {
-                return Err("Invalid number of parameters for Transfer".into());
-            }
-            let token_ticker = match &params[0] {
-                Token::String(s) => s.clone(),
-                _ => return Err("Expected string for token_ticker".into()),
-            };
-            let to = match &params[1] {
-                Token::Address(addr) => *addr,
-                _ => return Err("Expected address for to".into()),
-            };
-            let amount = match &params[2] {
-                Token::Uint(u) => u.as_u32() as u16,
-                _ => return Err("Expected uint for amount".into()),
-            };
-            Ok(TransactionParams::Transfer(TransferTransactionParams {
-                token_ticker,
-                to,
-                amount,
-            }))
-        }
-        _ => Err("Unsupported transaction type".into()),
+/// Executes `txs` in order on `appchain` inside one database transaction. If any transaction
+/// fails, the whole batch is rolled back and the error of the first failing transaction is
+/// returned.
+pub async fn execute_transactions<C>(
+    txs: &[Transaction],
+    scheme: &SignatureScheme,
+    appchain: &Appchain<'_, C>,
+) -> Result<(), SpvmError>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    let txn = appchain.begin().await?;
+    let batch = appchain.on(&txn);
+    for tx in txs {
+        tx.execute_transaction(scheme, &batch).await?;
     }
+    txn.commit().await?;
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use std::env;
-
+    use super::test_utils::*;
     use super::Transaction;
     use super::*;
-    use sea_orm::{entity::prelude::*, Database, DbBackend, Schema};
+    use sea_orm::{entity::prelude::*, Set};
 
+    use crate::codec::tests::arb_amount;
     use ethers::{
         core::rand::thread_rng,
         signers::{LocalWallet, Signer},
+        types::H256,
     };
-
-    async fn setup() -> (LocalWallet, DatabaseConnection) {
-        let wallet0 = LocalWallet::new(&mut thread_rng());
-
-        let db_path = env::var("DB").unwrap();
-        let db = Database::connect(db_path).await.unwrap();
-        setup_schema(&db).await;
-
-        (wallet0, db)
-    }
-
-    async fn setup_schema(db: &DbConn) {
-        let schema = Schema::new(DbBackend::Sqlite);
-
-        let stmt0 = schema.create_table_from_entity(state::Entity);
-        let stmt1 = schema.create_table_from_entity(nonces::Entity);
-        let stmt2 = schema.create_table_from_entity(initialized_tickers::Entity);
-
-        let _result = db.execute(db.get_database_backend().build(&stmt0)).await;
-
-        let _result = db.execute(db.get_database_backend().build(&stmt1)).await;
-
-        let _result = db.execute(db.get_database_backend().build(&stmt2)).await;
-    }
-
-    fn create_transaction(
-        signer: &LocalWallet,
-        tx_type: u8,
-        tx_param: TransactionParams,
-        nonce: u32,
-    ) -> Transaction {
-        let tx_content = TransactionContent {
-            from: signer.address(),
-            tx_type,
-            tx_param,
-            nonce,
-        };
-
-        let message = encode_tx_content(&tx_content);
-        let tx_hash = TxHash::from_slice(&keccak256(message));
-        let signature = signer.sign_hash(tx_hash).unwrap();
-
-        Transaction {
-            tx_content,
-            tx_hash,
-            signature,
-        }
-    }
-
-    fn create_mint_transaction(
-        ticker: &str,
-        from: &LocalWallet,
-        owner: Address,
-        supply: u16,
-        nonce: u32,
-    ) -> Transaction {
-        create_transaction(
-            from,
-            0,
-            TransactionParams::Mint(MintTransactionParams {
-                token_ticker: ticker.to_string(),
-                owner,
-                supply,
-            }),
-            nonce,
-        )
-    }
-
-    fn create_transfer_transaction(
-        ticker: &str,
-        from: &LocalWallet,
-        to: Address,
-        amount: u16,
-        nonce: u32,
-    ) -> Transaction {
-        create_transaction(
-            from,
-            1,
-            TransactionParams::Transfer(TransferTransactionParams {
-                token_ticker: ticker.to_string(),
-                to,
-                amount,
-            }),
-            nonce,
-        )
-    }
+    use proptest::prelude::*;
 
     #[async_std::test]
     async fn get_balance() {
@@ -545,10 +706,10 @@ mod tests {
         let address = wallet.address();
         let ticker = "ABC";
 
-        let result = TransactionContent::get_balance(ticker, address, &connection)
+        let result = TransactionContent::get_balance(ticker, address, &appchain(&connection))
             .await
             .unwrap();
-        assert_eq!(result, 0);
+        assert_eq!(result, U256::from(0));
     }
 
     #[async_std::test]
@@ -557,27 +718,41 @@ mod tests {
         let address = wallet.address();
         let ticker = "ABC";
 
-        assert!(
-            TransactionContent::set_balance(ticker, address, 100, &connection)
-                .await
-                .is_ok()
-        );
+        assert!(TransactionContent::set_balance(
+            ticker,
+            address,
+            U256::from(100),
+            &appchain(&connection)
+        )
+        .await
+        .is_ok());
 
-        let result = TransactionContent::get_balance(ticker, address, &connection)
+        let result = TransactionContent::get_balance(ticker, address, &appchain(&connection))
             .await
             .unwrap();
-        assert_eq!(result, 100);
+        assert_eq!(result, U256::from(100));
+        let result = TransactionContent::get_total_supply(ticker, &appchain(&connection))
+            .await
+            .unwrap();
+        assert_eq!(result, U256::from(100));
 
-        assert!(
-            TransactionContent::set_balance(ticker, address, 0, &connection)
-                .await
-                .is_ok()
-        );
+        assert!(TransactionContent::set_balance(
+            ticker,
+            address,
+            U256::zero(),
+            &appchain(&connection)
+        )
+        .await
+        .is_ok());
 
-        let result = TransactionContent::get_balance(ticker, address, &connection)
+        let result = TransactionContent::get_balance(ticker, address, &appchain(&connection))
+            .await
+            .unwrap();
+        assert_eq!(result, U256::from(0));
+        let result = TransactionContent::get_total_supply(ticker, &appchain(&connection))
             .await
             .unwrap();
-        assert_eq!(result, 0);
+        assert_eq!(result, U256::from(0));
     }
 
     #[async_std::test]
@@ -589,37 +764,41 @@ mod tests {
 
         assert!(t1
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_ok());
 
-        let result = TransactionContent::get_balance("ABC", wallet2.address(), &connection)
-            .await
-            .unwrap();
-        assert_eq!(result, 100);
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(100));
 
-        let result = TransactionContent::get_balance("ABC", wallet.address(), &connection)
-            .await
-            .unwrap();
-        assert_eq!(result, 0);
+        let result =
+            TransactionContent::get_balance("ABC", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(0));
 
         let t2 = create_mint_transaction("DEF", &wallet, wallet.address(), 100, 1);
 
         assert!(t2
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_ok());
 
-        let result = TransactionContent::get_balance("DEF", wallet.address(), &connection)
-            .await
-            .unwrap();
-        assert_eq!(result, 100);
+        let result =
+            TransactionContent::get_balance("DEF", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(100));
 
-        let result = TransactionContent::get_balance("DEF", wallet2.address(), &connection)
-            .await
-            .unwrap();
-        assert_eq!(result, 0);
+        let result =
+            TransactionContent::get_balance("DEF", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(0));
     }
 
     #[async_std::test]
@@ -633,24 +812,26 @@ mod tests {
 
         assert!(t1
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_ok());
         assert!(t2
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_ok());
 
-        let result = TransactionContent::get_balance("ABC", wallet.address(), &connection)
-            .await
-            .unwrap();
-        assert_eq!(result, 50);
+        let result =
+            TransactionContent::get_balance("ABC", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(50));
 
-        let result = TransactionContent::get_balance("ABC", wallet2.address(), &connection)
-            .await
-            .unwrap();
-        assert_eq!(result, 50);
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(50));
     }
 
     #[async_std::test]
@@ -664,14 +845,15 @@ mod tests {
 
         assert!(t1
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_ok());
-        assert!(t2
-            .tx_content
-            .execute_raw_transaction(&connection)
-            .await
-            .is_err());
+        assert!(matches!(
+            t2.tx_content
+                .execute_raw_transaction(&appchain(&connection))
+                .await,
+            Err(SpvmError::TickerAlreadyInitialized(_))
+        ));
     }
 
     #[async_std::test]
@@ -681,11 +863,15 @@ mod tests {
 
         let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 1);
 
-        assert!(t1
-            .tx_content
-            .execute_raw_transaction(&connection)
-            .await
-            .is_err());
+        assert!(matches!(
+            t1.tx_content
+                .execute_raw_transaction(&appchain(&connection))
+                .await,
+            Err(SpvmError::InvalidNonce {
+                expected: 0,
+                got: 1
+            })
+        ));
     }
 
     #[async_std::test]
@@ -699,14 +885,14 @@ mod tests {
 
         assert!(t1
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_ok());
-        assert!(t2
-            .tx_content
-            .execute_raw_transaction(&connection)
-            .await
-            .is_err());
+        assert!(matches!(
+            t2.tx_content.execute_raw_transaction(&appchain(&connection)).await,
+            Err(SpvmError::InsufficientBalance { have, need })
+                if have == U256::from(100) && need == U256::from(200)
+        ));
     }
 
     #[async_std::test]
@@ -716,11 +902,12 @@ mod tests {
 
         let t1 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 50, 0);
 
-        assert!(t1
-            .tx_content
-            .execute_raw_transaction(&connection)
-            .await
-            .is_err());
+        assert!(matches!(
+            t1.tx_content
+                .execute_raw_transaction(&appchain(&connection))
+                .await,
+            Err(SpvmError::TickerNotInitialized(_))
+        ));
     }
 
     #[async_std::test]
@@ -734,12 +921,12 @@ mod tests {
 
         assert!(t1
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_ok());
         assert!(t2
             .tx_content
-            .execute_raw_transaction(&connection)
+            .execute_raw_transaction(&appchain(&connection))
             .await
             .is_err());
     }
@@ -753,7 +940,11 @@ mod tests {
 
         t1.tx_hash = TxHash::from_slice(&[0; 32]);
 
-        assert!(t1.execute_transaction(&connection).await.is_err());
+        assert!(matches!(
+            t1.execute_transaction(&SCHEME, &appchain(&connection))
+                .await,
+            Err(SpvmError::HashMismatch)
+        ));
     }
 
     #[async_std::test]
@@ -765,7 +956,11 @@ mod tests {
 
         t1.signature = wallet2.sign_hash(t1.tx_hash).unwrap();
 
-        assert!(t1.execute_transaction(&connection).await.is_err());
+        assert!(matches!(
+            t1.execute_transaction(&SCHEME, &appchain(&connection))
+                .await,
+            Err(SpvmError::BadSignature(_))
+        ));
     }
 
     #[async_std::test]
@@ -777,9 +972,111 @@ mod tests {
 
         t1.signature = wallet.sign_hash(TxHash::from_slice(&[0; 32])).unwrap();
 
-        assert!(t1.execute_transaction(&connection).await.is_err());
-    }
-
+        assert!(t1
+            .execute_transaction(&SCHEME, &appchain(&connection))
+            .await
+            .is_err());
+    }
+
+    #[async_std::test]
+    async fn execute_transactions_batch() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 50, 0);
+
+        execute_transactions(&[t1, t2], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(50));
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(50));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_transactions_batch_rolls_back() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 200, 0);
+
+        assert!(
+            execute_transactions(&[t1, t2], &SCHEME, &appchain(&connection))
+                .await
+                .is_err()
+        );
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(0));
+
+        // The mint was rolled back with the rest of the batch, so its nonce is still free
+        let t3 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        assert!(t3
+            .execute_transaction(&SCHEME, &appchain(&connection))
+            .await
+            .is_ok());
+    }
+
+    #[async_std::test]
+    async fn execute_in_memory() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 50, 0);
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        t2.execute_on(&SCHEME, &mut state).await.unwrap();
+
+        assert_eq!(
+            state.get_balance("ABC", wallet.address()).await.unwrap(),
+            U256::from(50)
+        );
+        assert_eq!(
+            state.get_balance("ABC", wallet2.address()).await.unwrap(),
+            U256::from(50)
+        );
+        assert_eq!(state.get_nonce(wallet.address()).await.unwrap(), 1);
+        assert_eq!(state.get_nonce(wallet2.address()).await.unwrap(), 1);
+    }
+
+    #[async_std::test]
+    async fn execute_fail_in_memory_leaves_state_unchanged() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 200, 0);
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        assert!(matches!(
+            t2.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::InsufficientBalance { .. })
+        ));
+
+        assert_eq!(
+            state.get_balance("ABC", wallet2.address()).await.unwrap(),
+            U256::from(100)
+        );
+        assert_eq!(state.get_nonce(wallet2.address()).await.unwrap(), 0);
+    }
+
     #[async_std::test]
     async fn execute_transaction() {
         let (wallet, connection) = setup().await;
@@ -787,11 +1084,1113 @@ mod tests {
 
         let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
 
-        t1.execute_transaction(&connection).await.unwrap();
+        t1.execute_transaction(&SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(100));
+    }
+
+    #[async_std::test]
+    async fn execute_v2_amounts_beyond_u16() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+        let supply = U256::from(10).pow(U256::from(30));
+
+        let t1 = create_transaction(
+            &wallet,
+            TX_FORMAT_V2,
+            TransactionParams::Mint(MintTransactionParams {
+                token_ticker: "ABC".to_string(),
+                owner: wallet2.address(),
+                supply,
+            }),
+            0,
+        );
+        let t2 = create_transaction(
+            &wallet2,
+            TX_FORMAT_V2 | 1,
+            TransactionParams::Transfer(TransferTransactionParams {
+                token_ticker: "ABC".to_string(),
+                to: wallet.address(),
+                amount: supply - 1,
+            }),
+            0,
+        );
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        t2.execute_on(&SCHEME, &mut state).await.unwrap();
+
+        assert_eq!(
+            state.get_balance("ABC", wallet.address()).await.unwrap(),
+            supply - 1
+        );
+        assert_eq!(
+            state.get_balance("ABC", wallet2.address()).await.unwrap(),
+            U256::one()
+        );
+
+        let decoded = decode_tx_content(&hex::encode(encode_tx_content(&t2.tx_content))).unwrap();
+        match decoded.tx_param {
+            TransactionParams::Transfer(params) => assert_eq!(params.amount, supply - 1),
+            _ => panic!("expected a transfer"),
+        }
+    }
+
+    #[async_std::test]
+    async fn execute_v2_amounts_persisted() {
+        let (wallet, connection) = setup().await;
+        let balance = U256::MAX - 1;
+
+        TransactionContent::set_balance("ABC", wallet.address(), balance, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, balance);
+        assert_eq!(
+            get_total_supply("ABC", &appchain(&connection))
+                .await
+                .unwrap(),
+            balance
+        );
+    }
+
+    #[async_std::test]
+    async fn execute_fail_transfer_balance_overflow() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        state.initialize_ticker("ABC").await.unwrap();
+        state
+            .set_balance("ABC", wallet.address(), U256::MAX)
+            .await
+            .unwrap();
+        state
+            .set_balance("ABC", wallet2.address(), U256::one())
+            .await
+            .unwrap();
+
+        let t1 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 1, 0);
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::BalanceOverflow)
+        ));
+        assert_eq!(
+            state.get_balance("ABC", wallet2.address()).await.unwrap(),
+            U256::one()
+        );
+    }
+
+    #[async_std::test]
+    async fn execute_fail_tx_type_mismatch() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+
+        // Transfer parameters have the layout of mints, issues and approvals
+        let params = TransactionParams::Transfer(TransferTransactionParams {
+            token_ticker: "ABC".to_string(),
+            to: wallet2.address(),
+            amount: U256::from(10),
+        });
+        for tx_type in [0, 4, 5, TX_FORMAT_V2 | 4] {
+            let t2 = create_transaction(&wallet, tx_type, params.clone(), 1);
+
+            assert!(matches!(
+                t2.execute_on(&SCHEME, &mut state).await,
+                Err(SpvmError::TxTypeMismatch(got)) if got == tx_type
+            ));
+        }
+        assert_eq!(
+            state.get_balance("ABC", wallet.address()).await.unwrap(),
+            U256::from(100)
+        );
+        assert_eq!(state.get_nonce(wallet.address()).await.unwrap(), 1);
+    }
+
+    #[async_std::test]
+    async fn execute_fail_legacy_amount_out_of_range() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+        let amount = U256::from(u16::MAX) + 1;
+
+        state.initialize_ticker("ABC").await.unwrap();
+        state
+            .set_balance("ABC", wallet.address(), U256::MAX)
+            .await
+            .unwrap();
+
+        let params = TransactionParams::Transfer(TransferTransactionParams {
+            token_ticker: "ABC".to_string(),
+            to: wallet2.address(),
+            amount,
+        });
+        let t1 = create_transaction(&wallet, 1, params.clone(), 0);
+        let t2 = create_multi_transfer_transaction(
+            "ABC",
+            &wallet,
+            &[(wallet2.address(), 1), (wallet2.address(), amount.as_u64())],
+            0,
+        );
+        for tx in [t1, t2] {
+            assert!(matches!(
+                tx.execute_on(&SCHEME, &mut state).await,
+                Err(SpvmError::AmountOutOfRange(got)) if got == amount
+            ));
+        }
+        assert_eq!(
+            state.get_balance("ABC", wallet2.address()).await.unwrap(),
+            U256::zero()
+        );
+
+        // The same amount is fine in the v2 format
+        let t3 = create_transaction(&wallet, TX_FORMAT_V2 | 1, params, 0);
+        t3.execute_on(&SCHEME, &mut state).await.unwrap();
+        assert_eq!(
+            state.get_balance("ABC", wallet2.address()).await.unwrap(),
+            amount
+        );
+    }
+
+    #[async_std::test]
+    async fn execute_fail_nonce_overflow() {
+        let (wallet, connection) = setup().await;
+        entity::nonces::ActiveModel {
+            chain_id: Set(CHAIN_ID as i64),
+            owner_address: Set(format!("{:#x}", wallet.address())),
+            nonce: Set(i64::from(u32::MAX)),
+        }
+        .insert(&connection)
+        .await
+        .unwrap();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, u32::MAX);
+
+        let simulation =
+            simulate_transactions(std::slice::from_ref(&t1), &SCHEME, &appchain(&connection))
+                .await
+                .unwrap();
+        assert!(matches!(
+            simulation.failure,
+            Some(SimulationFailure {
+                index: 0,
+                error: SpvmError::NonceOverflow(owner),
+            }) if owner == wallet.address()
+        ));
+
+        assert!(matches!(
+            t1.execute_transaction(&SCHEME, &appchain(&connection))
+                .await,
+            Err(SpvmError::NonceOverflow(_))
+        ));
+        assert_eq!(
+            appchain(&connection)
+                .state()
+                .get_nonce(wallet.address())
+                .await
+                .unwrap(),
+            u32::MAX
+        );
+        assert!(list_tickers(&appchain(&connection))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn execute_fail_v2_wrong_chain() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = InMemoryBackend::new(CHAIN_ID + 1);
+
+        let t1 = create_transaction(
+            &wallet,
+            TX_FORMAT_V2,
+            TransactionParams::Mint(MintTransactionParams {
+                token_ticker: "ABC".to_string(),
+                owner: wallet.address(),
+                supply: U256::from(100),
+            }),
+            0,
+        );
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::WrongChain { expected, got }) if expected == CHAIN_ID + 1 && got == CHAIN_ID
+        ));
+        assert_eq!(state.get_nonce(wallet.address()).await.unwrap(), 0);
+    }
+
+    #[async_std::test]
+    async fn execute_fail_legacy_with_chain_id() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let mut t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        t1.tx_content.chain_id = CHAIN_ID;
+        resign(&mut t1, &wallet, &SCHEME);
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::WrongChain { expected: 0, .. })
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_legacy_not_accepted() {
+        let (wallet, connection) = setup().await;
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+
+        let strict = Appchain::new(CHAIN_ID, &connection);
+        assert!(matches!(
+            t1.execute_transaction(&SCHEME, &strict).await,
+            Err(SpvmError::LegacyTxRejected(CHAIN_ID))
+        ));
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut InMemoryBackend::new(CHAIN_ID))
+                .await,
+            Err(SpvmError::LegacyTxRejected(CHAIN_ID))
+        ));
+        assert_eq!(strict.state().get_nonce(wallet.address()).await.unwrap(), 0);
+
+        // Transactions bound to the appchain are accepted either way
+        let mut t2 = t1.clone();
+        t2.tx_content.tx_type |= TX_FORMAT_V2;
+        t2.tx_content.chain_id = CHAIN_ID;
+        resign(&mut t2, &wallet, &SCHEME);
+        t2.execute_transaction(&SCHEME, &strict).await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn execute_burn() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_burn_transaction("ABC", &wallet2, 40, 0);
+
+        execute_transactions(&[t1, t2.clone()], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(60));
+        let result = TransactionContent::get_total_supply("ABC", &appchain(&connection))
+            .await
+            .unwrap();
+        assert_eq!(result, U256::from(60));
+
+        let decoded = decode_tx_content(&hex::encode(encode_tx_content(&t2.tx_content))).unwrap();
+        match decoded.tx_param {
+            TransactionParams::Burn(params) => {
+                assert_eq!(params.token_ticker, "ABC");
+                assert_eq!(params.amount, U256::from(40));
+            }
+            _ => panic!("expected a burn"),
+        }
+    }
+
+    #[async_std::test]
+    async fn execute_fail_burn_insufficient_balance() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_burn_transaction("ABC", &wallet2, 101, 0);
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        assert!(matches!(
+            t2.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::InsufficientBalance { .. })
+        ));
+        assert_eq!(
+            state
+                .get_token_metadata("ABC")
+                .await
+                .unwrap()
+                .unwrap()
+                .total_supply,
+            U256::from(100)
+        );
+    }
+
+    #[async_std::test]
+    async fn execute_fail_burn_supply_underflow() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        // A balance the supply doesn't account for
+        state.initialize_ticker("ABC").await.unwrap();
+        state
+            .set_balance("ABC", wallet.address(), U256::from(100))
+            .await
+            .unwrap();
+
+        let t1 = create_burn_transaction("ABC", &wallet, 40, 0);
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::SupplyUnderflow(ticker)) if ticker == "ABC"
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_burn_token_not_initialized() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_burn_transaction("ABC", &wallet, 1, 0);
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::TickerNotInitialized(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_create_token_and_issue() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_transaction(
+            &wallet,
+            3,
+            TransactionParams::CreateToken(CreateTokenTransactionParams {
+                token_ticker: "ABC".to_string(),
+                owner: wallet2.address(),
+                supply: U256::from(100),
+                decimals: 6,
+                name: "Alphabet".to_string(),
+            }),
+            0,
+        );
+        let t2 = create_issue_transaction("ABC", &wallet, wallet2.address(), 50, 1);
+        let t3 = create_burn_transaction("ABC", &wallet2, 30, 0);
+
+        let decoded = decode_tx_content(&hex::encode(encode_tx_content(&t1.tx_content))).unwrap();
+        assert!(matches!(
+            decoded.tx_param,
+            TransactionParams::CreateToken(CreateTokenTransactionParams { decimals: 6, .. })
+        ));
+
+        execute_transactions(&[t1, t2, t3], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let metadata = TransactionContent::get_token_metadata("ABC", &appchain(&connection))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            metadata,
+            TokenMetadata {
+                ticker: "ABC".to_string(),
+                mint_authority: Some(wallet.address()),
+                decimals: 6,
+                name: "Alphabet".to_string(),
+                total_supply: U256::from(120),
+            }
+        );
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(120));
+    }
+
+    #[async_std::test]
+    async fn execute_mint_sets_mint_authority() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_issue_transaction("ABC", &wallet, wallet.address(), 1, 1);
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        t2.execute_on(&SCHEME, &mut state).await.unwrap();
+
+        let metadata = state.get_token_metadata("ABC").await.unwrap().unwrap();
+        assert_eq!(metadata.mint_authority, Some(wallet.address()));
+        assert_eq!(metadata.name, "ABC");
+        assert_eq!(metadata.total_supply, U256::from(101));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_issue_not_mint_authority() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_issue_transaction("ABC", &wallet2, wallet2.address(), 100, 0);
+
+        t1.execute_transaction(&SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+        assert!(matches!(
+            t2.execute_transaction(&SCHEME, &appchain(&connection)).await,
+            Err(SpvmError::NotMintAuthority { sender, .. }) if sender == wallet2.address()
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_issue_without_mint_authority() {
+        let (wallet, connection) = setup().await;
+
+        // Tickers initialized outside of a mint have no authority
+        TransactionContent::set_balance(
+            "ABC",
+            wallet.address(),
+            U256::from(100),
+            &appchain(&connection),
+        )
+        .await
+        .unwrap();
+
+        let t1 = create_issue_transaction("ABC", &wallet, wallet.address(), 100, 0);
+
+        assert!(matches!(
+            t1.execute_transaction(&SCHEME, &appchain(&connection))
+                .await,
+            Err(SpvmError::NotMintAuthority { .. })
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_issue_token_not_initialized() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_issue_transaction("ABC", &wallet, wallet.address(), 1, 0);
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::TickerNotInitialized(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_approve_and_transfer_from() {
+        let (wallet, connection) = setup().await;
+        let spender = LocalWallet::new(&mut thread_rng());
+        let receiver = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_approve_transaction("ABC", &wallet, spender.address(), 60, 1);
+        let t3 = create_transfer_from_transaction(
+            "ABC",
+            &spender,
+            wallet.address(),
+            receiver.address(),
+            40,
+            0,
+        );
+
+        let decoded = decode_tx_content(&hex::encode(encode_tx_content(&t3.tx_content))).unwrap();
+        assert!(matches!(
+            decoded.tx_param,
+            TransactionParams::TransferFrom(TransferFromTransactionParams { owner, to, .. })
+                if owner == wallet.address() && to == receiver.address()
+        ));
+
+        execute_transactions(&[t1, t2, t3], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(60));
+        let result =
+            TransactionContent::get_balance("ABC", receiver.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::from(40));
+        let result = TransactionContent::get_allowance(
+            "ABC",
+            wallet.address(),
+            spender.address(),
+            &appchain(&connection),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, U256::from(20));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_transfer_from_insufficient_allowance() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let spender = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_approve_transaction("ABC", &wallet, spender.address(), 10, 1);
+        let t3 = create_transfer_from_transaction(
+            "ABC",
+            &spender,
+            wallet.address(),
+            spender.address(),
+            11,
+            0,
+        );
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        t2.execute_on(&SCHEME, &mut state).await.unwrap();
+        assert!(matches!(
+            t3.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::InsufficientAllowance { have, need })
+                if have == U256::from(10) && need == U256::from(11)
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_fail_transfer_from_insufficient_balance() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let spender = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_approve_transaction("ABC", &wallet, spender.address(), 1000, 1);
+        let t3 = create_transfer_from_transaction(
+            "ABC",
+            &spender,
+            wallet.address(),
+            spender.address(),
+            101,
+            0,
+        );
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        t2.execute_on(&SCHEME, &mut state).await.unwrap();
+        assert!(matches!(
+            t3.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::InsufficientBalance { .. })
+        ));
+        assert_eq!(
+            state
+                .get_allowance("ABC", wallet.address(), spender.address())
+                .await
+                .unwrap(),
+            U256::from(1000)
+        );
+    }
+
+    #[async_std::test]
+    async fn execute_fail_approve_token_not_initialized() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let spender = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_approve_transaction("ABC", &wallet, spender.address(), 10, 0);
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::TickerNotInitialized(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn execute_multi_transfer() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let wallet3 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_multi_transfer_transaction(
+            "ABC",
+            &wallet,
+            &[
+                (wallet2.address(), 30),
+                (wallet3.address(), 20),
+                (wallet2.address(), 5),
+            ],
+            1,
+        );
+
+        let decoded = decode_tx_content(&hex::encode(encode_tx_content(&t2.tx_content))).unwrap();
+        match decoded.tx_param {
+            TransactionParams::MultiTransfer(params) => assert_eq!(params.transfers.len(), 3),
+            _ => panic!("expected a multi transfer"),
+        }
 
-        let result = TransactionContent::get_balance("ABC", wallet2.address(), &connection)
+        execute_transactions(&[t1, t2], &SCHEME, &appchain(&connection))
             .await
             .unwrap();
-        assert_eq!(result, 100);
+
+        for (holder, expected) in [
+            (wallet.address(), 45),
+            (wallet2.address(), 35),
+            (wallet3.address(), 20),
+        ] {
+            let result = TransactionContent::get_balance("ABC", holder, &appchain(&connection))
+                .await
+                .unwrap();
+            assert_eq!(result, U256::from(expected));
+        }
+    }
+
+    #[async_std::test]
+    async fn execute_fail_multi_transfer_insufficient_balance() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_multi_transfer_transaction(
+            "ABC",
+            &wallet,
+            &[(wallet2.address(), 60), (wallet2.address(), 41)],
+            1,
+        );
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        assert!(matches!(
+            t2.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::InsufficientBalance { have, need })
+                if have == U256::from(100) && need == U256::from(101)
+        ));
+        assert_eq!(
+            state.get_balance("ABC", wallet2.address()).await.unwrap(),
+            U256::zero()
+        );
+    }
+
+    const STEP_TICKERS: [&str; 2] = ["ABC", "XYZ"];
+
+    // Random sequences of mints and transfers between a few accounts, for the property tests of
+    // the executor.
+    #[derive(Debug, Clone)]
+    enum StepOp {
+        Mint {
+            ticker: usize,
+            owner: usize,
+            supply: U256,
+        },
+        Transfer {
+            ticker: usize,
+            to: usize,
+            amount: U256,
+        },
+    }
+
+    // Sent by account `sender`, with a nonce off by `nonce_delta` from the expected one
+    #[derive(Debug, Clone)]
+    struct Step {
+        sender: usize,
+        nonce_delta: i64,
+        op: StepOp,
+    }
+
+    fn step_accounts() -> Vec<LocalWallet> {
+        (1u8..=3)
+            .map(|i| LocalWallet::from_bytes(&[i; 32]).unwrap())
+            .collect()
+    }
+
+    fn arb_step(amount: BoxedStrategy<U256>) -> impl Strategy<Value = Step> {
+        let accounts = 0..step_accounts().len();
+        let mint = (0..STEP_TICKERS.len(), accounts.clone(), amount.clone()).prop_map(
+            |(ticker, owner, supply)| StepOp::Mint {
+                ticker,
+                owner,
+                supply,
+            },
+        );
+        let transfer = (0..STEP_TICKERS.len(), accounts.clone(), amount)
+            .prop_map(|(ticker, to, amount)| StepOp::Transfer { ticker, to, amount });
+        let op = prop_oneof![1 => mint, 3 => transfer];
+        let nonce_delta = prop_oneof![8 => Just(0i64), 1 => Just(-1i64), 1 => Just(1i64)];
+
+        (accounts, nonce_delta, op).prop_map(|(sender, nonce_delta, op)| Step {
+            sender,
+            nonce_delta,
+            op,
+        })
+    }
+
+    // Amounts that don't fit in a uint16 need the v2 format
+    fn create_step_transaction(step: &Step, accounts: &[LocalWallet], nonce: u32) -> Transaction {
+        let (tx_type, amount, tx_param) = match &step.op {
+            StepOp::Mint {
+                ticker,
+                owner,
+                supply,
+            } => (
+                0,
+                *supply,
+                TransactionParams::Mint(MintTransactionParams {
+                    token_ticker: STEP_TICKERS[*ticker].to_string(),
+                    owner: accounts[*owner].address(),
+                    supply: *supply,
+                }),
+            ),
+            StepOp::Transfer { ticker, to, amount } => (
+                1,
+                *amount,
+                TransactionParams::Transfer(TransferTransactionParams {
+                    token_ticker: STEP_TICKERS[*ticker].to_string(),
+                    to: accounts[*to].address(),
+                    amount: *amount,
+                }),
+            ),
+        };
+        let tx_type = if amount > U256::from(u16::MAX) {
+            tx_type | TX_FORMAT_V2
+        } else {
+            tx_type
+        };
+        let nonce = u32::try_from(i64::from(nonce) + step.nonce_delta).unwrap_or(u32::MAX);
+
+        create_transaction(&accounts[step.sender], tx_type, tx_param, nonce)
+    }
+
+    // What a step can change: each ticker's metadata and balances, and the nonces
+    #[derive(Debug, PartialEq)]
+    struct Observation {
+        tokens: Vec<(Option<TokenMetadata>, Vec<U256>)>,
+        nonces: Vec<u32>,
+    }
+
+    async fn observe(state: &InMemoryBackend, accounts: &[LocalWallet]) -> Observation {
+        let mut tokens = Vec::new();
+        for ticker in STEP_TICKERS {
+            let mut balances = Vec::new();
+            for account in accounts {
+                balances.push(state.get_balance(ticker, account.address()).await.unwrap());
+            }
+            tokens.push((state.get_token_metadata(ticker).await.unwrap(), balances));
+        }
+        let mut nonces = Vec::new();
+        for account in accounts {
+            nonces.push(state.get_nonce(account.address()).await.unwrap());
+        }
+
+        Observation { tokens, nonces }
+    }
+
+    async fn check_executor_invariants(steps: &[Step]) -> Result<(), TestCaseError> {
+        let accounts = step_accounts();
+        let mut state = in_memory();
+
+        for step in steps {
+            let before = observe(&state, &accounts).await;
+            let nonce = before.nonces[step.sender];
+            let tx = create_step_transaction(step, &accounts, nonce);
+            let result = tx.execute_on(&SCHEME, &mut state).await;
+            let after = observe(&state, &accounts).await;
+
+            match (&step.op, result) {
+                (_, Err(e)) => {
+                    // A rejected transaction leaves no trace, not even a nonce bump
+                    prop_assert_eq!(&after, &before, "{} changed the state", e);
+                    if step.nonce_delta == 0 {
+                        if let StepOp::Transfer { ticker, amount, .. } = &step.op {
+                            let (metadata, balances) = &before.tokens[*ticker];
+                            if metadata.is_some() && balances[step.sender] < *amount {
+                                prop_assert!(
+                                    matches!(e, SpvmError::InsufficientBalance { .. }),
+                                    "{}",
+                                    e
+                                );
+                            }
+                        }
+                    }
+                }
+                (op, Ok(())) => {
+                    prop_assert_eq!(step.nonce_delta, 0);
+                    for (i, (before, after)) in before.nonces.iter().zip(&after.nonces).enumerate()
+                    {
+                        let bump = u32::from(i == step.sender);
+                        prop_assert_eq!(*after, before + bump);
+                    }
+
+                    match op {
+                        StepOp::Mint { ticker, supply, .. } => {
+                            let metadata = after.tokens[*ticker].0.as_ref().unwrap();
+                            prop_assert_eq!(metadata.total_supply, *supply);
+                        }
+                        StepOp::Transfer { ticker, to, amount } => {
+                            let (metadata, balances) = &before.tokens[*ticker];
+                            let (metadata_after, balances_after) = &after.tokens[*ticker];
+                            prop_assert_eq!(metadata_after, metadata);
+                            for (i, (before, after)) in
+                                balances.iter().zip(balances_after).enumerate()
+                            {
+                                let expected = if i == step.sender && i == *to {
+                                    *before
+                                } else if i == step.sender {
+                                    before - amount
+                                } else if i == *to {
+                                    before + amount
+                                } else {
+                                    *before
+                                };
+                                prop_assert_eq!(*after, expected);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Supplies are only moved around, so balances can't wrap around below zero or above
+            // the supply
+            for (metadata, balances) in &after.tokens {
+                let supply = metadata.as_ref().map_or(U256::zero(), |m| m.total_supply);
+                let mut total = U256::zero();
+                for balance in balances {
+                    prop_assert!(*balance <= supply);
+                    total = total.checked_add(*balance).unwrap();
+                }
+                prop_assert_eq!(total, supply);
+            }
+        }
+
+        Ok(())
+    }
+
+    proptest! {
+        // Every step signs and recovers a transaction, which is slow in debug builds
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn executor_keeps_invariants(
+            steps in proptest::collection::vec(arb_step(arb_amount(true)), 1..32)
+        ) {
+            async_std::task::block_on(check_executor_invariants(&steps))?;
+        }
+
+        #[test]
+        fn executor_keeps_invariants_with_small_amounts(
+            steps in proptest::collection::vec(
+                arb_step((0..1000u64).prop_map(U256::from).boxed()),
+                1..32,
+            )
+        ) {
+            async_std::task::block_on(check_executor_invariants(&steps))?;
+        }
+    }
+
+    // Runs random transaction sequences through the executor and through the SPVM-1 contract
+    // deployed on a local anvil, and compares the state after every step. Needs the `anvil`
+    // binary of Foundry on the PATH: cargo test -p spvm-rs -- --ignored differential
+    mod differential {
+        use super::*;
+        use ethers::{
+            middleware::SignerMiddleware,
+            providers::{Http, Middleware, Provider},
+            utils::{Anvil, AnvilInstance},
+        };
+        use proptest::test_runner::{TestCaseError, TestRunner};
+        use std::sync::Arc;
+
+        mod spvm1 {
+            ethers::contract::abigen!(Spvm, "../../apps/proposer/contracts/SPVM.json");
+        }
+
+        type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+        // Proposes `tx` alone in the next block of the contract. Returns whether it was accepted.
+        async fn propose(
+            spvm: &spvm1::Spvm<Client>,
+            proposer: &LocalWallet,
+            number: u32,
+            parent_hash: H256,
+            tx: &Transaction,
+        ) -> Result<bool, String> {
+            let client = spvm.client();
+            // Blocks can only be proposed in even L1 blocks, and the proposal is mined in the next
+            let l1_number = client.get_block_number().await.map_err(|e| e.to_string())?;
+            if l1_number.as_u64() % 2 == 0 {
+                client
+                    .provider()
+                    .request::<_, String>("evm_mine", ())
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+
+            let block_hash = contract_block_hash(parent_hash, std::slice::from_ref(tx));
+            let block = spvm1::Block {
+                transactions: vec![spvm1::Spvmtransaction {
+                    tx_content: spvm1::TransactionContent {
+                        from: tx.tx_content.from,
+                        tx_type: tx.tx_content.tx_type,
+                        tx_param: encode_tx_params(&tx.tx_content.tx_param),
+                        nonce: tx.tx_content.nonce,
+                    },
+                    transaction_hash: tx.tx_hash.0,
+                    signature: tx.signature.to_vec().into(),
+                }],
+                block_hash: block_hash.0,
+                parent_hash: parent_hash.0,
+                block_number: number,
+                proposer: proposer.address(),
+                proposer_signature: proposer.sign_hash(block_hash).unwrap().to_vec().into(),
+            };
+
+            // A reverting block is rejected when its gas is estimated
+            match spvm.propose_block(block).send().await {
+                Ok(pending) => {
+                    let receipt = pending.await.map_err(|e| e.to_string())?;
+                    Ok(receipt.and_then(|r| r.status) == Some(1.into()))
+                }
+                Err(_) => Ok(false),
+            }
+        }
+
+        async fn compare_state(
+            spvm: &spvm1::Spvm<Client>,
+            state: &InMemoryBackend,
+            accounts: &[LocalWallet],
+        ) -> Result<(), String> {
+            for ticker in STEP_TICKERS {
+                let on_chain = spvm
+                    .initialized_tickers(ticker.to_string())
+                    .call()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let local = state.is_ticker_initialized(ticker).await.unwrap();
+                if on_chain != local {
+                    return Err(format!(
+                        "{} is initialized on chain: {}, locally: {}",
+                        ticker, on_chain, local
+                    ));
+                }
+
+                for account in accounts {
+                    let on_chain = spvm
+                        .get_balance(ticker.to_string(), account.address())
+                        .call()
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let local = state.get_balance(ticker, account.address()).await.unwrap();
+                    if U256::from(on_chain) != local {
+                        return Err(format!(
+                            "Balance of {} in {:#x} is {} on chain, {} locally",
+                            ticker,
+                            account.address(),
+                            on_chain,
+                            local
+                        ));
+                    }
+                }
+            }
+
+            for account in accounts {
+                let on_chain = spvm
+                    .nonces(account.address())
+                    .call()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let local = state.get_nonce(account.address()).await.unwrap();
+                if on_chain != local {
+                    return Err(format!(
+                        "Nonce of {:#x} is {} on chain, {} locally",
+                        account.address(),
+                        on_chain,
+                        local
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+
+        // Deploys a fresh contract and runs `steps` through both implementations
+        async fn run_steps(anvil: &AnvilInstance, steps: &[Step]) -> Result<(), String> {
+            let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+            let proposer =
+                LocalWallet::from(anvil.keys()[0].clone()).with_chain_id(anvil.chain_id());
+            let client = Arc::new(SignerMiddleware::new(provider, proposer.clone()));
+            let spvm = spvm1::Spvm::deploy(client, ())
+                .map_err(|e| e.to_string())?
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let accounts = step_accounts();
+            let mut state = in_memory();
+            let mut number = 1;
+            let mut parent_hash = H256::zero();
+
+            for (index, step) in steps.iter().enumerate() {
+                let nonce = state
+                    .get_nonce(accounts[step.sender].address())
+                    .await
+                    .unwrap();
+                let mut tx = create_step_transaction(step, &accounts, nonce);
+                resign(&mut tx, &accounts[step.sender], &CONTRACT_SIGNATURE_SCHEME);
+
+                // A failing block changes nothing on chain
+                let mut next = state.clone();
+                let local = tx.execute_on(&CONTRACT_SIGNATURE_SCHEME, &mut next).await;
+                let on_chain = propose(&spvm, &proposer, number, parent_hash, &tx).await?;
+                if local.is_ok() != on_chain {
+                    return Err(format!(
+                        "Step {} is accepted on chain: {}, locally: {:?}",
+                        index, on_chain, local
+                    ));
+                }
+                if on_chain {
+                    state = next;
+                    parent_hash = contract_block_hash(parent_hash, std::slice::from_ref(&tx));
+                    number += 1;
+                }
+
+                compare_state(&spvm, &state, &accounts)
+                    .await
+                    .map_err(|e| format!("After step {}: {}", index, e))?;
+            }
+
+            Ok(())
+        }
+
+        #[test]
+        #[ignore = "needs anvil"]
+        fn executor_matches_spvm1_contract() {
+            let anvil = Anvil::new().spawn();
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+
+            // Failures are shrunk to a minimal sequence of steps, printed with the mismatch
+            let mut runner = TestRunner::new(ProptestConfig {
+                cases: 32,
+                ..ProptestConfig::default()
+            });
+            // Amounts stay small: SPVM-1 only has legacy transactions and uint16 balances, which
+            // can't overflow since supplies are only moved around
+            let steps = proptest::collection::vec(
+                arb_step((0..1000u64).prop_map(U256::from).boxed()),
+                1..24,
+            );
+            let result = runner.run(&steps, |steps| {
+                runtime
+                    .block_on(run_steps(&anvil, &steps))
+                    .map_err(TestCaseError::fail)
+            });
+            if let Err(e) = result {
+                panic!("{}", e);
+            }
+        }
     }
 }
@@ -0,0 +1,393 @@
+//! Sparse Merkle tree committing to the state of an appchain.
+//!
+//! Every non-zero balance and nonce is a leaf, placed at a 256-bit key derived from what it is
+//! the value of ([`balance_key`], [`nonce_key`]). Leaves hash as `keccak256(0x00 ‖ key ‖ value)`
+//! and inner nodes as `keccak256(0x01 ‖ left ‖ right)`. An empty subtree hashes to zero and a
+//! subtree holding a single leaf hashes to that leaf, so paths are only as deep as needed to tell
+//! the leaves apart.
+//!
+//! A [`BalanceProof`] lets anyone holding a trusted state root check a balance served by an
+//! untrusted node. Nothing on L1 provides such a root yet: the SPVM-1 contract's blocks have no
+//! state root, so the root is only in the blocks the proposer stores, and a proof checked against
+//! a root served by the gateway is only as trustworthy as the gateway.
+
+use ::entity::{nonces, state};
+use ethers::{
+    core::{
+        abi::{encode, Token},
+        utils::keccak256,
+    },
+    types::{Address, H256, U256},
+};
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::backend::chain_column;
+use crate::query::parse_token_balance;
+use crate::{Appchain, SpvmError};
+
+const BALANCE_LEAF: u8 = 0;
+const NONCE_LEAF: u8 = 1;
+
+/// Key of the balance of `owner` for `ticker`:
+/// `keccak256(abi.encode(uint8(0), keccak256(ticker), owner))`.
+pub fn balance_key(ticker: &str, owner: Address) -> H256 {
+    leaf_key(BALANCE_LEAF, keccak256(ticker), owner)
+}
+
+/// Key of the nonce of `owner`: `keccak256(abi.encode(uint8(1), bytes32(0), owner))`.
+pub fn nonce_key(owner: Address) -> H256 {
+    leaf_key(NONCE_LEAF, [0u8; 32], owner)
+}
+
+fn leaf_key(kind: u8, ticker_hash: [u8; 32], owner: Address) -> H256 {
+    H256(keccak256(encode(&[
+        Token::Uint(U256::from(kind)),
+        Token::FixedBytes(ticker_hash.to_vec()),
+        Token::Address(owner),
+    ])))
+}
+
+/// Balances and nonces of one appchain, as the leaves of a sparse Merkle tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateTree {
+    // Ordered by key, which is the order of the leaves in the tree
+    leaves: BTreeMap<H256, H256>,
+}
+
+impl StateTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every balance and nonce of `appchain`.
+    pub async fn load<C: ConnectionTrait>(appchain: &Appchain<'_, C>) -> Result<Self, SpvmError> {
+        let mut tree = Self::new();
+
+        let balances = state::Entity::find()
+            .filter(state::Column::ChainId.eq(chain_column(appchain.id())))
+            .all(appchain.db())
+            .await?;
+        for record in &balances {
+            let balance = parse_token_balance(record)?;
+            tree.set_balance(&balance.ticker, balance.holder, balance.amount);
+        }
+
+        let nonces = nonces::Entity::find()
+            .filter(nonces::Column::ChainId.eq(chain_column(appchain.id())))
+            .all(appchain.db())
+            .await?;
+        for record in nonces {
+            let owner = Address::from_str(&record.owner_address).map_err(|e| {
+                SpvmError::Db(DbErr::Type(format!(
+                    "Invalid owner_address {:?} stored for a nonce: {}",
+                    record.owner_address, e
+                )))
+            })?;
+            tree.set_nonce(owner, record.nonce as u32);
+        }
+
+        Ok(tree)
+    }
+
+    /// A zero balance removes the leaf, so the root doesn't depend on whether it is stored.
+    pub fn set_balance(&mut self, ticker: &str, owner: Address, balance: U256) {
+        self.set_leaf(balance_key(ticker, owner), balance);
+    }
+
+    pub fn set_nonce(&mut self, owner: Address, nonce: u32) {
+        self.set_leaf(nonce_key(owner), U256::from(nonce));
+    }
+
+    fn set_leaf(&mut self, key: H256, value: U256) {
+        if value.is_zero() {
+            self.leaves.remove(&key);
+        } else {
+            self.leaves.insert(key, leaf_value(value));
+        }
+    }
+
+    /// Proves the balance of `owner` for `ticker` against [`StateTree::root`]. Zero balances are
+    /// proven by showing that their leaf is absent.
+    pub fn balance_proof(&self, ticker: &str, owner: Address) -> BalanceProof {
+        let key = balance_key(ticker, owner);
+        let leaves: Vec<(H256, H256)> = self.leaves.iter().map(|(k, v)| (*k, *v)).collect();
+
+        // Walk down until the subtree on the key's path holds at most one leaf
+        let mut path = &leaves[..];
+        let mut siblings = Vec::new();
+        while path.len() > 1 {
+            let depth = siblings.len();
+            let split = path.partition_point(|(k, _)| !key_bit(k, depth));
+            let (left, right) = path.split_at(split);
+            if key_bit(&key, depth) {
+                siblings.push(subtree_hash(left, depth + 1));
+                path = right;
+            } else {
+                siblings.push(subtree_hash(right, depth + 1));
+                path = left;
+            }
+        }
+
+        let (balance, other_leaf) = match path.first() {
+            Some((k, v)) if *k == key => (U256::from_big_endian(v.as_bytes()), None),
+            leaf => (U256::zero(), leaf.copied()),
+        };
+
+        BalanceProof {
+            ticker: ticker.to_string(),
+            owner,
+            balance,
+            siblings,
+            other_leaf,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Root hash of the tree, zero when it is empty.
+    pub fn root(&self) -> H256 {
+        let leaves: Vec<(H256, H256)> = self.leaves.iter().map(|(k, v)| (*k, *v)).collect();
+        subtree_hash(&leaves, 0)
+    }
+}
+
+/// Proof that `owner` holds `balance` of `ticker` in the state committed to by a state root.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BalanceProof {
+    pub ticker: String,
+    pub owner: Address,
+    pub balance: U256,
+    /// Hashes of the sibling subtrees along the path of the balance's key, from the root down.
+    pub siblings: Vec<H256>,
+    /// For a zero balance, the (key, value) of the only leaf where the balance's leaf would be,
+    /// if that place isn't empty.
+    pub other_leaf: Option<(H256, H256)>,
+}
+
+impl BalanceProof {
+    /// Checks the proof against `state_root`.
+    pub fn verify(&self, state_root: H256) -> bool {
+        let key = balance_key(&self.ticker, self.owner);
+        if self.siblings.len() > 256 {
+            return false;
+        }
+
+        let mut node = match (self.balance.is_zero(), &self.other_leaf) {
+            (false, None) => leaf_hash(&key, &leaf_value(self.balance)),
+            (true, None) => H256::zero(),
+            // The other leaf must sit in the subtree the path ends in
+            (true, Some((other_key, value))) => {
+                let shares_path = (0..self.siblings.len())
+                    .all(|depth| key_bit(other_key, depth) == key_bit(&key, depth));
+                if *other_key == key || !shares_path {
+                    return false;
+                }
+                leaf_hash(other_key, value)
+            }
+            (false, Some(_)) => return false,
+        };
+
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            node = if key_bit(&key, depth) {
+                node_hash(sibling, &node)
+            } else {
+                node_hash(&node, sibling)
+            };
+        }
+
+        node == state_root
+    }
+}
+
+/// State root of `appchain`, see [`StateTree`].
+pub async fn state_root<C: ConnectionTrait>(appchain: &Appchain<'_, C>) -> Result<H256, SpvmError> {
+    Ok(StateTree::load(appchain).await?.root())
+}
+
+fn leaf_value(value: U256) -> H256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    H256(bytes)
+}
+
+fn leaf_hash(key: &H256, value: &H256) -> H256 {
+    H256(keccak256(
+        [&[0u8][..], key.as_bytes(), value.as_bytes()].concat(),
+    ))
+}
+
+fn node_hash(left: &H256, right: &H256) -> H256 {
+    H256(keccak256(
+        [&[1u8][..], left.as_bytes(), right.as_bytes()].concat(),
+    ))
+}
+
+// Bit `depth` of `key`, starting from the most significant one. Set bits go right.
+fn key_bit(key: &H256, depth: usize) -> bool {
+    key[depth / 8] >> (7 - depth % 8) & 1 == 1
+}
+
+// `leaves` are sorted by key and share their first `depth` bits
+fn subtree_hash(leaves: &[(H256, H256)], depth: usize) -> H256 {
+    match leaves {
+        [] => H256::zero(),
+        [(key, value)] => leaf_hash(key, value),
+        _ => {
+            let split = leaves.partition_point(|(key, _)| !key_bit(key, depth));
+            node_hash(
+                &subtree_hash(&leaves[..split], depth + 1),
+                &subtree_hash(&leaves[split..], depth + 1),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{Address, H256, U256},
+    };
+
+    #[test]
+    fn state_root_is_canonical() {
+        let owner = Address::random();
+        let owner2 = Address::random();
+        assert_eq!(StateTree::new().root(), H256::zero());
+
+        let mut tree = StateTree::new();
+        tree.set_balance("ABC", owner, U256::from(5));
+        tree.set_balance("DEF", owner2, U256::from(7));
+        tree.set_nonce(owner, 1);
+
+        let mut reordered = StateTree::new();
+        reordered.set_nonce(owner, 1);
+        reordered.set_balance("XYZ", owner2, U256::from(9));
+        reordered.set_balance("DEF", owner2, U256::from(7));
+        reordered.set_balance("ABC", owner, U256::from(5));
+        assert_ne!(reordered.root(), tree.root());
+
+        // Zero balances are not leaves
+        reordered.set_balance("XYZ", owner2, U256::zero());
+        assert_eq!(reordered.len(), 3);
+        assert_eq!(reordered.root(), tree.root());
+
+        tree.set_balance("ABC", owner, U256::from(6));
+        assert_ne!(reordered.root(), tree.root());
+    }
+
+    #[async_std::test]
+    async fn state_root_of_appchain() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        assert_eq!(
+            state_root(&appchain(&connection)).await.unwrap(),
+            H256::zero()
+        );
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet, wallet2.address(), 100, 1);
+        execute_transactions(&[t1, t2], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        // The sender's balance is now zero, which commits like a missing balance
+        let mut expected = StateTree::new();
+        expected.set_balance("ABC", wallet2.address(), U256::from(100));
+        expected.set_nonce(wallet.address(), 2);
+        assert_eq!(
+            StateTree::load(&appchain(&connection)).await.unwrap(),
+            expected
+        );
+        assert_eq!(
+            state_root(&appchain(&connection)).await.unwrap(),
+            expected.root()
+        );
+        assert_eq!(
+            state_root(&Appchain::new(CHAIN_ID + 1, &connection))
+                .await
+                .unwrap(),
+            H256::zero()
+        );
+    }
+
+    #[test]
+    fn balance_proofs_verify() {
+        let owners: Vec<Address> = (0..8).map(|_| Address::random()).collect();
+        let mut tree = StateTree::new();
+        for (i, owner) in owners.iter().enumerate() {
+            tree.set_balance("ABC", *owner, U256::from(i + 1));
+            tree.set_nonce(*owner, i as u32 + 1);
+        }
+        let root = tree.root();
+
+        for (i, owner) in owners.iter().enumerate() {
+            let proof = tree.balance_proof("ABC", *owner);
+            assert_eq!(proof.balance, U256::from(i + 1));
+            assert!(proof.verify(root));
+
+            let mut tampered = proof.clone();
+            tampered.balance += U256::one();
+            assert!(!tampered.verify(root));
+
+            let mut other_ticker = proof.clone();
+            other_ticker.ticker = "DEF".to_string();
+            assert!(!other_ticker.verify(root));
+        }
+
+        let proof = tree.balance_proof("ABC", owners[0]);
+        assert!(!proof.verify(H256::random()));
+        tree.set_balance("ABC", owners[1], U256::from(100));
+        assert!(!proof.verify(tree.root()));
+    }
+
+    #[test]
+    fn balance_proofs_of_missing_balances() {
+        let owner = Address::random();
+        assert!(StateTree::new()
+            .balance_proof("ABC", owner)
+            .verify(H256::zero()));
+
+        let mut tree = StateTree::new();
+        tree.set_balance("ABC", owner, U256::from(5));
+        let root = tree.root();
+
+        // A lone leaf sits where any other balance would be
+        let proof = tree.balance_proof("DEF", owner);
+        assert_eq!(proof.balance, U256::zero());
+        assert!(proof.other_leaf.is_some());
+        assert!(proof.verify(root));
+
+        // Claiming the balance is missing doesn't verify when it isn't
+        let mut hidden = tree.balance_proof("ABC", owner);
+        hidden.balance = U256::zero();
+        assert!(!hidden.verify(root));
+        let mut hidden = proof.clone();
+        hidden.ticker = "ABC".to_string();
+        assert!(!hidden.verify(root));
+
+        for _ in 0..16 {
+            tree.set_balance("ABC", Address::random(), U256::from(1));
+        }
+        let root = tree.root();
+        for _ in 0..16 {
+            let proof = tree.balance_proof("ABC", Address::random());
+            assert_eq!(proof.balance, U256::zero());
+            assert!(proof.verify(root));
+        }
+    }
+}
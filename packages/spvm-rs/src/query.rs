@@ -0,0 +1,194 @@
+use ::entity::{initialized_tickers, state};
+use ethers::types::{Address, U256};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::backend::{chain_column, parse_amount, parse_token_metadata};
+use crate::{Appchain, SpvmError, StateBackend, TokenMetadata};
+
+/// Balance of one holder for one ticker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenBalance {
+    pub ticker: String,
+    pub holder: Address,
+    pub amount: U256,
+}
+
+/// Lists every initialized ticker of `appchain`, ordered by ticker.
+pub async fn list_tickers<C: ConnectionTrait>(
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<TokenMetadata>, SpvmError> {
+    initialized_tickers::Entity::find()
+        .filter(initialized_tickers::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(initialized_tickers::Column::IsInitialized.eq(true))
+        .order_by_asc(initialized_tickers::Column::Ticker)
+        .all(appchain.db())
+        .await?
+        .into_iter()
+        .map(parse_token_metadata)
+        .collect()
+}
+
+/// Total supply of `ticker`, as tracked in its [`TokenMetadata`]. Zero if it is not initialized.
+pub async fn get_total_supply<C: ConnectionTrait>(
+    ticker: &str,
+    appchain: &Appchain<'_, C>,
+) -> Result<U256, SpvmError> {
+    Ok(appchain
+        .state()
+        .get_token_metadata(ticker)
+        .await?
+        .map_or(U256::zero(), |metadata| metadata.total_supply))
+}
+
+/// Lists the holders of `ticker` with a non-zero balance, ordered by address.
+///
+/// `page` starts at 0. Each page holds at most `page_size` holders.
+pub async fn list_holders<C: ConnectionTrait>(
+    ticker: &str,
+    page: u64,
+    page_size: u64,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<TokenBalance>, SpvmError> {
+    state::Entity::find()
+        .filter(state::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(state::Column::Ticker.eq(ticker))
+        .filter(state::Column::Amount.ne("0"))
+        .order_by_asc(state::Column::OwnerAddress)
+        .paginate(appchain.db(), page_size)
+        .fetch_page(page)
+        .await?
+        .iter()
+        .map(parse_token_balance)
+        .collect()
+}
+
+/// Returns the non-zero balances of `holder` across all tickers, ordered by ticker.
+pub async fn get_balances<C: ConnectionTrait>(
+    holder: Address,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<TokenBalance>, SpvmError> {
+    state::Entity::find()
+        .filter(state::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(state::Column::OwnerAddress.eq(format!("{:#x}", holder)))
+        .filter(state::Column::Amount.ne("0"))
+        .order_by_asc(state::Column::Ticker)
+        .all(appchain.db())
+        .await?
+        .iter()
+        .map(parse_token_balance)
+        .collect()
+}
+
+pub(crate) fn parse_token_balance(record: &state::Model) -> Result<TokenBalance, SpvmError> {
+    let holder = Address::from_str(&record.owner_address).map_err(|e| {
+        SpvmError::Db(DbErr::Type(format!(
+            "Invalid owner_address {:?} stored for {}: {}",
+            record.owner_address, record.ticker, e
+        )))
+    })?;
+
+    Ok(TokenBalance {
+        ticker: record.ticker.clone(),
+        holder,
+        amount: parse_amount(record)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{Address, U256},
+    };
+
+    #[async_std::test]
+    async fn query_tickers_and_balances() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("DEF", &wallet, wallet.address(), 100, 0);
+        let t2 = create_mint_transaction("ABC", &wallet, wallet.address(), 10, 1);
+        let t3 = create_transfer_transaction("ABC", &wallet, wallet2.address(), 10, 2);
+        execute_transactions(&[t1, t2, t3], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let tickers: Vec<String> = list_tickers(&appchain(&connection))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|metadata| metadata.ticker)
+            .collect();
+        assert_eq!(tickers, vec!["ABC", "DEF"]);
+        assert_eq!(
+            get_total_supply("ABC", &appchain(&connection))
+                .await
+                .unwrap(),
+            U256::from(10)
+        );
+
+        // The emptied ABC balance is left out
+        assert_eq!(
+            get_balances(wallet.address(), &appchain(&connection))
+                .await
+                .unwrap(),
+            vec![TokenBalance {
+                ticker: "DEF".to_string(),
+                holder: wallet.address(),
+                amount: U256::from(100),
+            }]
+        );
+        assert_eq!(
+            get_balances(wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap(),
+            vec![TokenBalance {
+                ticker: "ABC".to_string(),
+                holder: wallet2.address(),
+                amount: U256::from(10),
+            }]
+        );
+    }
+
+    #[async_std::test]
+    async fn query_holders_paginated() {
+        let (_, connection) = setup().await;
+        let mut holders: Vec<Address> = (0..5)
+            .map(|_| LocalWallet::new(&mut thread_rng()).address())
+            .collect();
+        for holder in &holders {
+            TransactionContent::set_balance("ABC", *holder, U256::from(1), &appchain(&connection))
+                .await
+                .unwrap();
+        }
+        holders.sort();
+
+        let page0 = list_holders("ABC", 0, 2, &appchain(&connection))
+            .await
+            .unwrap();
+        let page2 = list_holders("ABC", 2, 2, &appchain(&connection))
+            .await
+            .unwrap();
+        let page3 = list_holders("ABC", 3, 2, &appchain(&connection))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            page0.iter().map(|b| b.holder).collect::<Vec<_>>(),
+            holders[0..2]
+        );
+        assert_eq!(
+            page2.iter().map(|b| b.holder).collect::<Vec<_>>(),
+            holders[4..]
+        );
+        assert!(page3.is_empty());
+    }
+}
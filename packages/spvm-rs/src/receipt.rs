@@ -0,0 +1,429 @@
+//! Receipts recording the outcome of every transaction executed in a block.
+
+use ::entity::receipts;
+use ethers::types::{Address, TxHash};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, NotSet, PaginatorTrait,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::backend::chain_column;
+use crate::block::{invalid_column, parse_column};
+use crate::journal::{insert_undo_journal, Journaled, UndoEntry};
+use crate::{Appchain, BalanceChange, SignatureScheme, SpvmError, StateBackend, Transaction};
+
+/// Outcome of executing a transaction at some position of a block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub tx_hash: TxHash,
+    pub sender: Address,
+    pub block_number: u32,
+    /// Index of the transaction in its block.
+    pub position: u32,
+    pub status: ReceiptStatus,
+    /// Balances changed by the transaction, empty if it failed.
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ReceiptStatus {
+    Success,
+    /// The [`SpvmError::code`] and message of the error the transaction failed with.
+    Failure {
+        code: String,
+        message: String,
+    },
+}
+
+impl Receipt {
+    pub fn is_success(&self) -> bool {
+        self.status == ReceiptStatus::Success
+    }
+}
+
+/// Executes `txs` as block `block_number` of `appchain` and writes a receipt for each of them.
+///
+/// Like [`crate::execute_transactions`], the block is applied as a whole or not at all. When a
+/// transaction fails, the block is rolled back and only the receipt of the failing transaction
+/// is written, so the reason stays on record, then its error is returned. Otherwise the block's
+/// undo journal is stored with the receipts, see [`crate::rollback_to`].
+pub async fn execute_block<C>(
+    block_number: u32,
+    txs: &[Transaction],
+    scheme: &SignatureScheme,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<Receipt>, SpvmError>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    let txn = appchain.begin().await?;
+    let batch = appchain.on(&txn);
+    let mut state = Journaled::new(batch.state());
+
+    let mut receipts = Vec::with_capacity(txs.len());
+    for (position, tx) in txs.iter().enumerate() {
+        let mut receipt = Receipt {
+            tx_hash: tx.tx_hash,
+            sender: tx.tx_content.from,
+            block_number,
+            position: position as u32,
+            status: ReceiptStatus::Success,
+            balance_changes: Vec::new(),
+        };
+
+        // The journal entries the transaction adds give the balances it changes
+        let start = state.entries.len();
+        match tx.execute_on(scheme, &mut state).await {
+            Ok(()) => {}
+            Err(SpvmError::Db(e)) => return Err(SpvmError::Db(e)),
+            Err(error) => {
+                txn.rollback().await?;
+                receipt.status = ReceiptStatus::Failure {
+                    code: error.code().to_string(),
+                    message: error.to_string(),
+                };
+                insert_receipt(&receipt, appchain).await?;
+                return Err(error);
+            }
+        }
+
+        receipt.balance_changes = balance_changes(&state.entries[start..], &state).await?;
+        receipts.push(receipt);
+    }
+
+    for receipt in &receipts {
+        insert_receipt(receipt, &batch).await?;
+    }
+    insert_undo_journal(block_number, &state.entries, &batch).await?;
+    txn.commit().await?;
+
+    Ok(receipts)
+}
+
+// Balances overwritten by the journal `entries`, from their value before the first write to
+// their value in `state`. Balances written back to their old value are left out.
+async fn balance_changes<B: StateBackend>(
+    entries: &[UndoEntry],
+    state: &B,
+) -> Result<Vec<BalanceChange>, SpvmError> {
+    let mut before = BTreeMap::new();
+    for entry in entries {
+        if let UndoEntry::Balance {
+            ticker,
+            holder,
+            before: balance,
+        } = entry
+        {
+            before.entry((ticker.clone(), *holder)).or_insert(*balance);
+        }
+    }
+
+    let mut changes = Vec::new();
+    for ((ticker, holder), before) in before {
+        let after = state.get_balance(&ticker, holder).await?;
+        if before != after {
+            changes.push(BalanceChange {
+                ticker,
+                holder,
+                before,
+                after,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Returns the receipts of the transaction with hash `tx_hash` on `appchain`, oldest first. A
+/// transaction has several receipts if it failed in a block before being included in another.
+pub async fn get_receipts<C: ConnectionTrait>(
+    tx_hash: TxHash,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<Receipt>, SpvmError> {
+    receipts::Entity::find()
+        .filter(receipts::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(receipts::Column::TxHash.eq(format!("{:#x}", tx_hash)))
+        .order_by_asc(receipts::Column::Id)
+        .all(appchain.db())
+        .await?
+        .iter()
+        .map(parse_receipt)
+        .collect()
+}
+
+/// Lists the receipts of transactions sent by `sender` on `appchain`, oldest first.
+///
+/// `page` starts at 0. Each page holds at most `page_size` receipts.
+pub async fn get_receipts_by_sender<C: ConnectionTrait>(
+    sender: Address,
+    page: u64,
+    page_size: u64,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<Receipt>, SpvmError> {
+    receipts::Entity::find()
+        .filter(receipts::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(receipts::Column::Sender.eq(format!("{:#x}", sender)))
+        .order_by_asc(receipts::Column::Id)
+        .paginate(appchain.db(), page_size)
+        .fetch_page(page)
+        .await?
+        .iter()
+        .map(parse_receipt)
+        .collect()
+}
+
+/// Returns the receipts of the transactions of block `block_number` of `appchain`, in execution
+/// order.
+///
+/// A block is only kept if all of its transactions succeed, so failure receipts left by attempts
+/// to execute a block under that number are not part of it. They are still returned by
+/// [`get_receipts`] and [`get_receipts_by_sender`].
+pub async fn get_block_receipts<C: ConnectionTrait>(
+    block_number: u32,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<Receipt>, SpvmError> {
+    receipts::Entity::find()
+        .filter(receipts::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(receipts::Column::BlockNumber.eq(i64::from(block_number)))
+        .filter(receipts::Column::Success.eq(true))
+        .order_by_asc(receipts::Column::Id)
+        .all(appchain.db())
+        .await?
+        .iter()
+        .map(parse_receipt)
+        .collect()
+}
+
+async fn insert_receipt<C: ConnectionTrait>(
+    receipt: &Receipt,
+    appchain: &Appchain<'_, C>,
+) -> Result<(), SpvmError> {
+    let (success, error_code, error_message) = match &receipt.status {
+        ReceiptStatus::Success => (true, None, None),
+        ReceiptStatus::Failure { code, message } => {
+            (false, Some(code.clone()), Some(message.clone()))
+        }
+    };
+    let balance_changes = serde_json::to_string(&receipt.balance_changes)
+        .map_err(|e| SpvmError::Decode(format!("Unserializable balance changes: {}", e)))?;
+
+    receipts::ActiveModel {
+        id: NotSet,
+        chain_id: Set(chain_column(appchain.id())),
+        tx_hash: Set(format!("{:#x}", receipt.tx_hash)),
+        sender: Set(format!("{:#x}", receipt.sender)),
+        block_number: Set(receipt.block_number.into()),
+        position: Set(receipt.position as i32),
+        success: Set(success),
+        error_code: Set(error_code),
+        error_message: Set(error_message),
+        balance_changes: Set(balance_changes),
+    }
+    .insert(appchain.db())
+    .await?;
+
+    Ok(())
+}
+
+fn parse_receipt(record: &receipts::Model) -> Result<Receipt, SpvmError> {
+    let status = if record.success {
+        ReceiptStatus::Success
+    } else {
+        ReceiptStatus::Failure {
+            code: record.error_code.clone().unwrap_or_default(),
+            message: record.error_message.clone().unwrap_or_default(),
+        }
+    };
+
+    Ok(Receipt {
+        tx_hash: parse_column(&record.tx_hash, "receipts.tx_hash")?,
+        sender: parse_column(&record.sender, "receipts.sender")?,
+        block_number: parse_column(&record.block_number, "receipts.block_number")?,
+        position: parse_column(&record.position, "receipts.position")?,
+        status,
+        balance_changes: serde_json::from_str(&record.balance_changes)
+            .map_err(|e| invalid_column("receipts.balance_changes", &record.balance_changes, e))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::U256,
+    };
+
+    #[async_std::test]
+    async fn execute_block_writes_receipts() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet, wallet2.address(), 30, 1);
+        let receipts = execute_block(
+            1,
+            &[t1.clone(), t2.clone()],
+            &SCHEME,
+            &appchain(&connection),
+        )
+        .await
+        .unwrap();
+
+        // Changes are ordered by holder
+        let mut balance_changes = vec![
+            BalanceChange {
+                ticker: "ABC".to_string(),
+                holder: wallet.address(),
+                before: U256::from(100),
+                after: U256::from(70),
+            },
+            BalanceChange {
+                ticker: "ABC".to_string(),
+                holder: wallet2.address(),
+                before: U256::zero(),
+                after: U256::from(30),
+            },
+        ];
+        balance_changes.sort_by_key(|change| change.holder);
+        assert_eq!(
+            receipts[1],
+            Receipt {
+                tx_hash: t2.tx_hash,
+                sender: wallet.address(),
+                block_number: 1,
+                position: 1,
+                status: ReceiptStatus::Success,
+                balance_changes,
+            }
+        );
+        assert_eq!(receipts[0].balance_changes[0].after, U256::from(100));
+        assert_eq!(
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap(),
+            U256::from(30)
+        );
+
+        assert_eq!(
+            get_receipts(t2.tx_hash, &appchain(&connection))
+                .await
+                .unwrap(),
+            vec![receipts[1].clone()]
+        );
+        assert_eq!(
+            get_block_receipts(1, &appchain(&connection)).await.unwrap(),
+            receipts
+        );
+        assert_eq!(
+            get_receipts_by_sender(wallet.address(), 1, 1, &appchain(&connection))
+                .await
+                .unwrap(),
+            vec![receipts[1].clone()]
+        );
+        assert!(
+            get_receipts_by_sender(wallet2.address(), 0, 10, &appchain(&connection))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            get_receipts(t2.tx_hash, &Appchain::new(CHAIN_ID + 1, &connection))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[async_std::test]
+    async fn execute_block_keeps_receipt_of_failure() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        execute_block(1, &[t1], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let t2 = create_transfer_transaction("ABC", &wallet, wallet2.address(), 30, 1);
+        let t3 = create_transfer_transaction("ABC", &wallet, wallet2.address(), 100, 2);
+        assert!(matches!(
+            execute_block(
+                2,
+                &[t2.clone(), t3.clone()],
+                &SCHEME,
+                &appchain(&connection)
+            )
+            .await,
+            Err(SpvmError::InsufficientBalance { .. })
+        ));
+
+        // The block is rolled back, only the failure is recorded
+        assert_eq!(
+            TransactionContent::get_balance("ABC", wallet.address(), &appchain(&connection))
+                .await
+                .unwrap(),
+            U256::from(100)
+        );
+        assert!(get_receipts(t2.tx_hash, &appchain(&connection))
+            .await
+            .unwrap()
+            .is_empty());
+        let failed = get_receipts(t3.tx_hash, &appchain(&connection))
+            .await
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].block_number, 2);
+        assert_eq!(failed[0].tx_hash, t3.tx_hash);
+        assert_eq!(failed[0].position, 1);
+        assert!(!failed[0].is_success());
+        assert!(matches!(
+            &failed[0].status,
+            ReceiptStatus::Failure { code, .. } if code == "INSUFFICIENT_BALANCE"
+        ));
+
+        // Retried without the failing transaction
+        execute_block(
+            2,
+            std::slice::from_ref(&t2),
+            &SCHEME,
+            &appchain(&connection),
+        )
+        .await
+        .unwrap();
+        let receipts = get_block_receipts(2, &appchain(&connection)).await.unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].tx_hash, t2.tx_hash);
+    }
+
+    #[async_std::test]
+    async fn block_receipts_leave_out_failed_attempts() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet, wallet2.address(), 200, 1);
+        assert!(
+            execute_block(1, &[t1.clone(), t2], &SCHEME, &appchain(&connection))
+                .await
+                .is_err()
+        );
+
+        // Block 1 is executed again with other transactions at the same positions
+        let t3 = create_transfer_transaction("ABC", &wallet, wallet2.address(), 30, 1);
+        let receipts = execute_block(1, &[t1, t3], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_block_receipts(1, &appchain(&connection)).await.unwrap(),
+            receipts
+        );
+        assert!(receipts.iter().all(Receipt::is_success));
+    }
+}
@@ -0,0 +1,288 @@
+//! Hashing of transaction contents for signing.
+//!
+//! Wallets sign `Transaction::tx_hash`, which is derived from the content according to a
+//! [`SignatureScheme`]. There is no default: services configure the scheme of each appchain.
+//! EIP-712 typed data is the one to use, so wallets can show what is being signed and signatures
+//! can't be replayed on another chain or SPVM deployment. The legacy raw hash is only there for
+//! compatibility with the SPVM-1 contract and has to be chosen explicitly.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use ethers::{
+    core::{
+        abi::{encode, Token},
+        utils::keccak256,
+    },
+    types::{
+        transaction::eip712::{EIP712Domain, Eip712DomainType, TypedData},
+        Address, TxHash, U256,
+    },
+};
+
+use crate::{encode_tx_content, encode_tx_params, SpvmError, TransactionContent};
+
+pub const SPVM_DOMAIN_NAME: &str = "SPVM";
+pub const SPVM_DOMAIN_VERSION: &str = "1";
+
+/// EIP-712 type of [`TransactionContent`]: the struct of the SPVM-1 contract plus the appchain id,
+/// which is 0 for legacy transactions.
+pub const TRANSACTION_CONTENT_TYPE: &str =
+    "TransactionContent(address from,uint8 txType,bytes txParam,uint32 nonce,uint64 chainId)";
+
+/// EIP-712 signing domain of an SPVM deployment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpvmDomain {
+    /// Chain id of the chain (or appchain) the SPVM runs on.
+    pub chain_id: U256,
+    /// Address of the SPVM contract.
+    pub verifying_contract: Address,
+}
+
+impl SpvmDomain {
+    pub fn new(chain_id: impl Into<U256>, verifying_contract: Address) -> Self {
+        Self {
+            chain_id: chain_id.into(),
+            verifying_contract,
+        }
+    }
+
+    pub fn eip712_domain(&self) -> EIP712Domain {
+        EIP712Domain {
+            name: Some(SPVM_DOMAIN_NAME.to_string()),
+            version: Some(SPVM_DOMAIN_VERSION.to_string()),
+            chain_id: Some(self.chain_id),
+            verifying_contract: Some(self.verifying_contract),
+            salt: None,
+        }
+    }
+
+    pub fn separator(&self) -> [u8; 32] {
+        self.eip712_domain().separator()
+    }
+}
+
+/// How `Transaction::tx_hash` is derived from the transaction content. The signature is always
+/// over `tx_hash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// EIP-712 typed data hash of the content in the given domain.
+    Eip712(SpvmDomain),
+    /// Raw `keccak256(encode_tx_content(..))`, which is what the SPVM-1 contract verifies.
+    /// Kept for compatibility only: the hash is opaque to wallets and isn't bound to a domain.
+    Legacy,
+}
+
+impl SignatureScheme {
+    pub fn tx_hash(&self, tx_content: &TransactionContent) -> TxHash {
+        match self {
+            SignatureScheme::Eip712(domain) => eip712_tx_hash(tx_content, domain),
+            SignatureScheme::Legacy => legacy_tx_hash(tx_content),
+        }
+    }
+}
+
+/// Parses the scheme of a service's config: `legacy`, or `eip712:<chain id>:<SPVM address>`.
+impl FromStr for SignatureScheme {
+    type Err = SpvmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SpvmError::Decode(format!("invalid signature scheme {:?}", s));
+
+        match s.split(':').collect::<Vec<_>>()[..] {
+            ["legacy"] => Ok(SignatureScheme::Legacy),
+            ["eip712", chain_id, verifying_contract] => {
+                let chain_id = U256::from_dec_str(chain_id).map_err(|_| invalid())?;
+                let verifying_contract =
+                    Address::from_str(verifying_contract).map_err(|_| invalid())?;
+                Ok(SignatureScheme::Eip712(SpvmDomain::new(
+                    chain_id,
+                    verifying_contract,
+                )))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// `hashStruct` of the content as defined by EIP-712.
+pub fn tx_content_struct_hash(tx_content: &TransactionContent) -> [u8; 32] {
+    keccak256(encode(&[
+        Token::FixedBytes(keccak256(TRANSACTION_CONTENT_TYPE).to_vec()),
+        Token::Address(tx_content.from),
+        Token::Uint(U256::from(tx_content.tx_type)),
+        Token::FixedBytes(keccak256(encode_tx_params(&tx_content.tx_param)).to_vec()),
+        Token::Uint(U256::from(tx_content.nonce)),
+        Token::Uint(U256::from(tx_content.chain_id)),
+    ]))
+}
+
+pub fn eip712_tx_hash(tx_content: &TransactionContent, domain: &SpvmDomain) -> TxHash {
+    let digest_input = [
+        &[0x19, 0x01],
+        &domain.separator()[..],
+        &tx_content_struct_hash(tx_content)[..],
+    ]
+    .concat();
+
+    TxHash::from(keccak256(digest_input))
+}
+
+pub fn legacy_tx_hash(tx_content: &TransactionContent) -> TxHash {
+    TxHash::from(keccak256(encode_tx_content(tx_content)))
+}
+
+/// The content as EIP-712 typed data, e.g. for `eth_signTypedData_v4`. Its hash is
+/// [`eip712_tx_hash`].
+pub fn tx_content_typed_data(tx_content: &TransactionContent, domain: &SpvmDomain) -> TypedData {
+    let field = |name: &str, r#type: &str| Eip712DomainType {
+        name: name.to_string(),
+        r#type: r#type.to_string(),
+    };
+
+    let types = BTreeMap::from([
+        (
+            "EIP712Domain".to_string(),
+            vec![
+                field("name", "string"),
+                field("version", "string"),
+                field("chainId", "uint256"),
+                field("verifyingContract", "address"),
+            ],
+        ),
+        (
+            "TransactionContent".to_string(),
+            vec![
+                field("from", "address"),
+                field("txType", "uint8"),
+                field("txParam", "bytes"),
+                field("nonce", "uint32"),
+                field("chainId", "uint64"),
+            ],
+        ),
+    ]);
+
+    let message = BTreeMap::from([
+        ("from".to_string(), format!("{:#x}", tx_content.from).into()),
+        ("txType".to_string(), tx_content.tx_type.into()),
+        (
+            "txParam".to_string(),
+            encode_tx_params(&tx_content.tx_param).to_string().into(),
+        ),
+        ("nonce".to_string(), tx_content.nonce.into()),
+        ("chainId".to_string(), tx_content.chain_id.into()),
+    ]);
+
+    TypedData {
+        domain: domain.eip712_domain(),
+        types,
+        primary_type: "TransactionContent".to_string(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::tests::arb_tx_content;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{transaction::eip712::Eip712, Address, U256},
+    };
+    use proptest::prelude::*;
+
+    // Re-signs `tx` under another scheme
+    #[async_std::test]
+    async fn execute_eip712_typed_data_signature() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+        let SignatureScheme::Eip712(domain) = SCHEME else {
+            unreachable!()
+        };
+
+        let mut t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        // What a wallet signs through eth_signTypedData_v4
+        t1.signature = wallet
+            .sign_typed_data(&tx_content_typed_data(&t1.tx_content, &domain))
+            .await
+            .unwrap();
+
+        t1.execute_on(&SCHEME, &mut state).await.unwrap();
+        assert_eq!(
+            state.get_balance("ABC", wallet.address()).await.unwrap(),
+            U256::from(100)
+        );
+    }
+
+    #[async_std::test]
+    async fn execute_fail_eip712_other_domain() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        for domain in [
+            SpvmDomain::new(1, Address::zero()),
+            SpvmDomain::new(31337, wallet.address()),
+        ] {
+            let mut t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+            resign(&mut t1, &wallet, &SignatureScheme::Eip712(domain));
+
+            assert!(matches!(
+                t1.execute_on(&SCHEME, &mut state).await,
+                Err(SpvmError::HashMismatch)
+            ));
+        }
+    }
+
+    #[async_std::test]
+    async fn execute_legacy_signature_only_with_legacy_scheme() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let mut state = in_memory();
+
+        let mut t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        resign(&mut t1, &wallet, &SignatureScheme::Legacy);
+
+        assert!(matches!(
+            t1.execute_on(&SCHEME, &mut state).await,
+            Err(SpvmError::HashMismatch)
+        ));
+        t1.execute_on(&SignatureScheme::Legacy, &mut state)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn parse_signature_scheme() {
+        assert_eq!(
+            "legacy".parse::<SignatureScheme>().unwrap(),
+            SignatureScheme::Legacy
+        );
+        assert_eq!(
+            "eip712:31337:0x1111111111111111111111111111111111111111"
+                .parse::<SignatureScheme>()
+                .unwrap(),
+            SignatureScheme::Eip712(SpvmDomain::new(31337, Address::repeat_byte(0x11)))
+        );
+
+        for s in ["", "eip712", "eip712:31337", "eip712:abc:0x11", "legacy:1"] {
+            assert!(matches!(
+                s.parse::<SignatureScheme>(),
+                Err(SpvmError::Decode(_))
+            ));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn eip712_tx_hash_matches_typed_data(tx_content in arb_tx_content()) {
+            let domain = SpvmDomain::new(31337, Address::repeat_byte(0x11));
+            let typed_data = tx_content_typed_data(&tx_content, &domain);
+
+            prop_assert_eq!(
+                TxHash::from(typed_data.encode_eip712().unwrap()),
+                eip712_tx_hash(&tx_content, &domain)
+            );
+        }
+    }
+}
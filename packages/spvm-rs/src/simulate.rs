@@ -0,0 +1,325 @@
+use async_trait::async_trait;
+use ethers::types::{Address, U256};
+use sea_orm::ConnectionTrait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{Appchain, SignatureScheme, SpvmError, StateBackend, TokenMetadata, Transaction};
+
+/// Outcome of simulating an ordered list of transactions.
+///
+/// Changes only cover the transactions before `failure`, since a block with a failing
+/// transaction is rejected as a whole and nothing after it would run.
+#[derive(Debug, Default)]
+pub struct Simulation {
+    pub balance_changes: Vec<BalanceChange>,
+    pub nonce_changes: Vec<NonceChange>,
+    pub failure: Option<SimulationFailure>,
+}
+
+impl Simulation {
+    pub fn is_ok(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub ticker: String,
+    pub holder: Address,
+    pub before: U256,
+    pub after: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonceChange {
+    pub owner: Address,
+    pub before: u32,
+    pub after: u32,
+}
+
+#[derive(Debug)]
+pub struct SimulationFailure {
+    /// Position of the failing transaction in the simulated list.
+    pub index: usize,
+    pub error: SpvmError,
+}
+
+/// Simulates `txs` against the state of `appchain` without writing to it.
+pub async fn simulate_transactions<C: ConnectionTrait>(
+    txs: &[Transaction],
+    scheme: &SignatureScheme,
+    appchain: &Appchain<'_, C>,
+) -> Result<Simulation, SpvmError> {
+    simulate_transactions_on(txs, scheme, &appchain.state()).await
+}
+
+/// Simulates `txs` in order on top of `state`, which is only read from.
+///
+/// Execution stops at the first transaction that fails validation; it is reported in
+/// [`Simulation::failure`]. Database errors are not a property of the transactions and are
+/// returned as `Err` instead.
+pub async fn simulate_transactions_on<B: StateBackend>(
+    txs: &[Transaction],
+    scheme: &SignatureScheme,
+    state: &B,
+) -> Result<Simulation, SpvmError> {
+    let mut overlay = Overlay::new(state);
+    let mut failure = None;
+
+    for (index, tx) in txs.iter().enumerate() {
+        let checkpoint = overlay.writes.clone();
+        match tx.execute_on(scheme, &mut overlay).await {
+            Ok(()) => {}
+            Err(SpvmError::Db(e)) => return Err(SpvmError::Db(e)),
+            Err(error) => {
+                overlay.writes = checkpoint;
+                failure = Some(SimulationFailure { index, error });
+                break;
+            }
+        }
+    }
+
+    let mut balance_changes = Vec::new();
+    for ((ticker, holder), after) in &overlay.writes.balances {
+        let before = state.get_balance(ticker, *holder).await?;
+        if before != *after {
+            balance_changes.push(BalanceChange {
+                ticker: ticker.clone(),
+                holder: *holder,
+                before,
+                after: *after,
+            });
+        }
+    }
+
+    let mut nonce_changes = Vec::new();
+    for (owner, after) in &overlay.writes.nonces {
+        let before = state.get_nonce(*owner).await?;
+        nonce_changes.push(NonceChange {
+            owner: *owner,
+            before,
+            after: *after,
+        });
+    }
+
+    Ok(Simulation {
+        balance_changes,
+        nonce_changes,
+        failure,
+    })
+}
+
+// Writes made during a simulation. Ordered maps keep the reported changes deterministic.
+#[derive(Clone, Default)]
+struct Writes {
+    balances: BTreeMap<(String, Address), U256>,
+    allowances: BTreeMap<(String, Address, Address), U256>,
+    nonces: BTreeMap<Address, u32>,
+    tokens: BTreeMap<String, TokenMetadata>,
+}
+
+// Reads fall through to the base state unless the simulation already wrote the value.
+struct Overlay<'a, B: StateBackend> {
+    base: &'a B,
+    writes: Writes,
+}
+
+impl<'a, B: StateBackend> Overlay<'a, B> {
+    fn new(base: &'a B) -> Self {
+        Self {
+            base,
+            writes: Writes::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<B: StateBackend> StateBackend for Overlay<'_, B> {
+    fn chain_id(&self) -> u64 {
+        self.base.chain_id()
+    }
+
+    fn accepts_legacy_txs(&self) -> bool {
+        self.base.accepts_legacy_txs()
+    }
+
+    async fn get_balance(&self, ticker: &str, holder: Address) -> Result<U256, SpvmError> {
+        match self.writes.balances.get(&(ticker.to_string(), holder)) {
+            Some(balance) => Ok(*balance),
+            None => self.base.get_balance(ticker, holder).await,
+        }
+    }
+
+    async fn set_balance(
+        &mut self,
+        ticker: &str,
+        holder: Address,
+        balance: U256,
+    ) -> Result<(), SpvmError> {
+        self.writes
+            .balances
+            .insert((ticker.to_string(), holder), balance);
+        Ok(())
+    }
+
+    async fn get_allowance(
+        &self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+    ) -> Result<U256, SpvmError> {
+        match self
+            .writes
+            .allowances
+            .get(&(ticker.to_string(), owner, spender))
+        {
+            Some(allowance) => Ok(*allowance),
+            None => self.base.get_allowance(ticker, owner, spender).await,
+        }
+    }
+
+    async fn set_allowance(
+        &mut self,
+        ticker: &str,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), SpvmError> {
+        self.writes
+            .allowances
+            .insert((ticker.to_string(), owner, spender), amount);
+        Ok(())
+    }
+
+    async fn get_nonce(&self, owner: Address) -> Result<u32, SpvmError> {
+        match self.writes.nonces.get(&owner) {
+            Some(nonce) => Ok(*nonce),
+            None => self.base.get_nonce(owner).await,
+        }
+    }
+
+    async fn bump_nonce(&mut self, owner: Address) -> Result<(), SpvmError> {
+        let nonce = self
+            .get_nonce(owner)
+            .await?
+            .checked_add(1)
+            .ok_or(SpvmError::NonceOverflow(owner))?;
+        self.writes.nonces.insert(owner, nonce);
+        Ok(())
+    }
+
+    async fn is_ticker_initialized(&self, ticker: &str) -> Result<bool, SpvmError> {
+        if self.writes.tokens.contains_key(ticker) {
+            return Ok(true);
+        }
+        self.base.is_ticker_initialized(ticker).await
+    }
+
+    async fn initialize_ticker(&mut self, ticker: &str) -> Result<(), SpvmError> {
+        if self.get_token_metadata(ticker).await?.is_none() {
+            self.set_token_metadata(&TokenMetadata::new(ticker)).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_token_metadata(&self, ticker: &str) -> Result<Option<TokenMetadata>, SpvmError> {
+        match self.writes.tokens.get(ticker) {
+            Some(metadata) => Ok(Some(metadata.clone())),
+            None => self.base.get_token_metadata(ticker).await,
+        }
+    }
+
+    async fn set_token_metadata(&mut self, metadata: &TokenMetadata) -> Result<(), SpvmError> {
+        self.writes
+            .tokens
+            .insert(metadata.ticker.clone(), metadata.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::U256,
+    };
+
+    #[async_std::test]
+    async fn simulate_reports_changes_without_writing() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 30, 0);
+
+        let simulation = simulate_transactions(&[t1, t2], &SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        assert!(simulation.is_ok());
+        assert_eq!(simulation.balance_changes.len(), 2);
+        assert!(simulation.balance_changes.contains(&BalanceChange {
+            ticker: "ABC".to_string(),
+            holder: wallet2.address(),
+            before: U256::zero(),
+            after: U256::from(70),
+        }));
+        assert!(simulation.balance_changes.contains(&BalanceChange {
+            ticker: "ABC".to_string(),
+            holder: wallet.address(),
+            before: U256::zero(),
+            after: U256::from(30),
+        }));
+        assert_eq!(simulation.nonce_changes.len(), 2);
+
+        let result =
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap();
+        assert_eq!(result, U256::zero());
+    }
+
+    #[async_std::test]
+    async fn simulate_reports_first_failure() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let state = in_memory();
+
+        let t1 = create_mint_transaction("ABC", &wallet, wallet2.address(), 100, 0);
+        let t2 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 200, 0);
+        let t3 = create_transfer_transaction("ABC", &wallet2, wallet.address(), 50, 0);
+
+        let simulation = simulate_transactions_on(&[t1, t2, t3], &SCHEME, &state)
+            .await
+            .unwrap();
+
+        let failure = simulation.failure.unwrap();
+        assert_eq!(failure.index, 1);
+        assert!(matches!(
+            failure.error,
+            SpvmError::InsufficientBalance { .. }
+        ));
+        assert_eq!(
+            simulation.balance_changes,
+            vec![BalanceChange {
+                ticker: "ABC".to_string(),
+                holder: wallet2.address(),
+                before: U256::zero(),
+                after: U256::from(100),
+            }]
+        );
+        assert_eq!(
+            simulation.nonce_changes,
+            vec![NonceChange {
+                owner: wallet.address(),
+                before: 0,
+                after: 1,
+            }]
+        );
+    }
+}
@@ -0,0 +1,380 @@
+//! Snapshots of the full state of an appchain, to bootstrap a node without replaying history.
+//!
+//! A snapshot file is JSON: `{"version": .., "checksum": .., "snapshot": {..}}`, where the
+//! checksum is the keccak256 of the JSON encoding of the snapshot. Files of another version or
+//! with a wrong checksum are rejected before anything is imported.
+
+use ::entity::{allowances, blocks, initialized_tickers, nonces, state};
+use ethers::{
+    core::utils::keccak256,
+    types::{Address, H256, U256},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::backend::chain_column;
+use crate::query::parse_token_balance;
+use crate::{
+    get_latest_block, insert_block, list_tickers, state_root, Appchain, Block, SpvmError,
+    StateBackend, TokenBalance, TokenMetadata,
+};
+
+/// Version of the snapshot file format written by [`Snapshot::to_bytes`].
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// State of one appchain: every ticker, non-zero balance, nonce and allowance, with the latest
+/// stored block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub chain_id: u64,
+    /// Latest stored block when the snapshot was taken, if any.
+    pub head: Option<Block>,
+    /// Root of the [`crate::StateTree`] of the state below.
+    pub state_root: H256,
+    pub tickers: Vec<TokenMetadata>,
+    pub balances: Vec<TokenBalance>,
+    pub nonces: Vec<AccountNonce>,
+    pub allowances: Vec<TokenAllowance>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountNonce {
+    pub owner: Address,
+    pub nonce: u32,
+}
+
+/// Amount of `ticker` that `spender` may transfer out of `owner`'s balance.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenAllowance {
+    pub ticker: String,
+    pub owner: Address,
+    pub spender: Address,
+    pub amount: U256,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    version: u32,
+    checksum: H256,
+    snapshot: serde_json::Value,
+}
+
+impl Snapshot {
+    /// keccak256 of the JSON encoding of the snapshot.
+    pub fn checksum(&self) -> H256 {
+        // Serializing plain structs and vectors can't fail
+        H256(keccak256(serde_json::to_vec(self).unwrap()))
+    }
+
+    /// Encodes the snapshot as a file of the current [`SNAPSHOT_VERSION`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let file = SnapshotFile {
+            version: SNAPSHOT_VERSION,
+            checksum: self.checksum(),
+            snapshot: serde_json::to_value(self).unwrap(),
+        };
+
+        serde_json::to_vec_pretty(&file).unwrap()
+    }
+
+    /// Decodes a snapshot file, checking its version and checksum.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, SpvmError> {
+        let file: SnapshotFile = serde_json::from_slice(data)
+            .map_err(|e| SpvmError::InvalidSnapshot(format!("Malformed file: {}", e)))?;
+        if file.version != SNAPSHOT_VERSION {
+            return Err(SpvmError::InvalidSnapshot(format!(
+                "Unsupported version {}, expected {}",
+                file.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let snapshot: Snapshot = serde_json::from_value(file.snapshot)
+            .map_err(|e| SpvmError::InvalidSnapshot(format!("Malformed snapshot: {}", e)))?;
+        if snapshot.checksum() != file.checksum {
+            return Err(SpvmError::InvalidSnapshot(format!(
+                "Checksum mismatch: file says {:#x}, content hashes to {:#x}",
+                file.checksum,
+                snapshot.checksum()
+            )));
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// Takes a snapshot of `appchain`.
+///
+/// The tables are read one after the other, so `appchain` should wrap a database transaction
+/// if it can be written to meanwhile.
+pub async fn export_snapshot<C: ConnectionTrait>(
+    appchain: &Appchain<'_, C>,
+) -> Result<Snapshot, SpvmError> {
+    let balances = state::Entity::find()
+        .filter(state::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(state::Column::Amount.ne("0"))
+        .order_by_asc(state::Column::Ticker)
+        .order_by_asc(state::Column::OwnerAddress)
+        .all(appchain.db())
+        .await?
+        .iter()
+        .map(parse_token_balance)
+        .collect::<Result<_, _>>()?;
+
+    let nonces = nonces::Entity::find()
+        .filter(nonces::Column::ChainId.eq(chain_column(appchain.id())))
+        .order_by_asc(nonces::Column::OwnerAddress)
+        .all(appchain.db())
+        .await?
+        .iter()
+        .map(|record| {
+            Ok(AccountNonce {
+                owner: parse_address(&record.owner_address)?,
+                nonce: record.nonce as u32,
+            })
+        })
+        .collect::<Result<_, SpvmError>>()?;
+
+    let allowances = allowances::Entity::find()
+        .filter(allowances::Column::ChainId.eq(chain_column(appchain.id())))
+        .filter(allowances::Column::Amount.ne("0"))
+        .order_by_asc(allowances::Column::Ticker)
+        .order_by_asc(allowances::Column::OwnerAddress)
+        .order_by_asc(allowances::Column::SpenderAddress)
+        .all(appchain.db())
+        .await?
+        .iter()
+        .map(|record| {
+            Ok(TokenAllowance {
+                ticker: record.ticker.clone(),
+                owner: parse_address(&record.owner_address)?,
+                spender: parse_address(&record.spender_address)?,
+                amount: U256::from_dec_str(&record.amount).map_err(|e| {
+                    SpvmError::Db(DbErr::Type(format!(
+                        "Invalid allowance {:?} stored for {}: {}",
+                        record.amount, record.ticker, e
+                    )))
+                })?,
+            })
+        })
+        .collect::<Result<_, SpvmError>>()?;
+
+    Ok(Snapshot {
+        chain_id: appchain.id(),
+        head: get_latest_block(appchain).await?,
+        state_root: state_root(appchain).await?,
+        tickers: list_tickers(appchain).await?,
+        balances,
+        nonces,
+        allowances,
+    })
+}
+
+/// Imports `snapshot` into `appchain`, which must have no state or blocks yet.
+///
+/// The snapshot must be of the same appchain, and the imported state must match its state
+/// root. Nothing is written unless the whole snapshot is imported.
+pub async fn import_snapshot<C>(
+    snapshot: &Snapshot,
+    appchain: &Appchain<'_, C>,
+) -> Result<(), SpvmError>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    if snapshot.chain_id != appchain.id() {
+        return Err(SpvmError::InvalidSnapshot(format!(
+            "Snapshot of appchain {} can't be imported into appchain {}",
+            snapshot.chain_id,
+            appchain.id()
+        )));
+    }
+    if !is_empty(appchain).await? {
+        return Err(SpvmError::InvalidSnapshot(format!(
+            "Appchain {} already has state",
+            appchain.id()
+        )));
+    }
+
+    let txn = appchain.begin().await?;
+    let batch = appchain.on(&txn);
+    let mut state = batch.state();
+
+    for metadata in &snapshot.tickers {
+        state.set_token_metadata(metadata).await?;
+    }
+    for balance in &snapshot.balances {
+        state
+            .set_balance(&balance.ticker, balance.holder, balance.amount)
+            .await?;
+    }
+    for allowance in &snapshot.allowances {
+        state
+            .set_allowance(
+                &allowance.ticker,
+                allowance.owner,
+                allowance.spender,
+                allowance.amount,
+            )
+            .await?;
+    }
+    for nonce in &snapshot.nonces {
+        nonces::ActiveModel {
+            chain_id: Set(chain_column(batch.id())),
+            owner_address: Set(format!("{:#x}", nonce.owner)),
+            nonce: Set(i64::from(nonce.nonce)),
+        }
+        .insert(&txn)
+        .await?;
+    }
+    if let Some(head) = &snapshot.head {
+        insert_block(head, &batch).await?;
+    }
+
+    let imported_root = state_root(&batch).await?;
+    if imported_root != snapshot.state_root {
+        return Err(SpvmError::InvalidSnapshot(format!(
+            "State root mismatch: snapshot says {:#x}, its state hashes to {:#x}",
+            snapshot.state_root, imported_root
+        )));
+    }
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+async fn is_empty<C: ConnectionTrait>(appchain: &Appchain<'_, C>) -> Result<bool, SpvmError> {
+    let chain_id = chain_column(appchain.id());
+    let tickers = initialized_tickers::Entity::find()
+        .filter(initialized_tickers::Column::ChainId.eq(chain_id))
+        .count(appchain.db())
+        .await?;
+    let nonces = nonces::Entity::find()
+        .filter(nonces::Column::ChainId.eq(chain_id))
+        .count(appchain.db())
+        .await?;
+    let blocks = blocks::Entity::find()
+        .filter(blocks::Column::ChainId.eq(chain_id))
+        .count(appchain.db())
+        .await?;
+
+    // Balances and allowances can't exist without their ticker
+    Ok(tickers == 0 && nonces == 0 && blocks == 0)
+}
+
+fn parse_address(value: &str) -> Result<Address, SpvmError> {
+    Address::from_str(value).map_err(|e| {
+        SpvmError::Db(DbErr::Type(format!(
+            "Invalid address {:?} stored: {}",
+            value, e
+        )))
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{H256, U256},
+    };
+    use sea_orm::DatabaseConnection;
+
+    pub(crate) async fn populate(
+        wallet: &LocalWallet,
+        connection: &DatabaseConnection,
+    ) -> Snapshot {
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+        let txs = [
+            create_mint_transaction("ABC", wallet, wallet.address(), 100, 0),
+            create_transfer_transaction("ABC", wallet, wallet2.address(), 30, 1),
+            create_approve_transaction("ABC", wallet, wallet2.address(), 20, 2),
+        ];
+        execute_block(1, &txs, &SCHEME, &appchain(connection))
+            .await
+            .unwrap();
+        let mut block = create_block(wallet, 1, H256::zero(), txs.to_vec());
+        block.state_root = state_root(&appchain(connection)).await.unwrap();
+        insert_block(&block, &appchain(connection)).await.unwrap();
+
+        export_snapshot(&appchain(connection)).await.unwrap()
+    }
+
+    #[async_std::test]
+    async fn snapshot_round_trip() {
+        let (wallet, connection) = setup().await;
+        let snapshot = populate(&wallet, &connection).await;
+        assert_eq!(snapshot.chain_id, CHAIN_ID);
+        assert_eq!(snapshot.head.as_ref().unwrap().number, 1);
+        assert_eq!(snapshot.tickers.len(), 1);
+        assert_eq!(snapshot.balances.len(), 2);
+        assert_eq!(snapshot.allowances[0].amount, U256::from(20));
+        assert_eq!(
+            snapshot.nonces,
+            vec![AccountNonce {
+                owner: wallet.address(),
+                nonce: 3,
+            }]
+        );
+
+        let decoded = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let (_, fresh) = setup().await;
+        import_snapshot(&decoded, &appchain(&fresh)).await.unwrap();
+        assert_eq!(export_snapshot(&appchain(&fresh)).await.unwrap(), snapshot);
+        assert_eq!(
+            get_latest_block(&appchain(&fresh)).await.unwrap(),
+            snapshot.head
+        );
+
+        assert!(matches!(
+            import_snapshot(&decoded, &appchain(&fresh)).await,
+            Err(SpvmError::InvalidSnapshot(_))
+        ));
+        assert!(matches!(
+            import_snapshot(&decoded, &Appchain::new(CHAIN_ID + 1, &fresh)).await,
+            Err(SpvmError::InvalidSnapshot(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn snapshot_rejects_invalid_files() {
+        let (wallet, connection) = setup().await;
+        let snapshot = populate(&wallet, &connection).await;
+        let bytes = String::from_utf8(snapshot.to_bytes()).unwrap();
+
+        let tampered = bytes.replacen("\"version\": 1", "\"version\": 2", 1);
+        assert!(matches!(
+            Snapshot::from_bytes(tampered.as_bytes()),
+            Err(SpvmError::InvalidSnapshot(msg)) if msg.contains("version")
+        ));
+        let tampered = bytes.replacen("\"nonce\": 3", "\"nonce\": 4", 1);
+        assert!(matches!(
+            Snapshot::from_bytes(tampered.as_bytes()),
+            Err(SpvmError::InvalidSnapshot(msg)) if msg.contains("Checksum")
+        ));
+        assert!(matches!(
+            Snapshot::from_bytes(b"{}"),
+            Err(SpvmError::InvalidSnapshot(_))
+        ));
+
+        // A consistent file with inconsistent content is caught on import
+        let mut forged = snapshot.clone();
+        forged.balances[0].amount += U256::one();
+        let forged = Snapshot::from_bytes(&forged.to_bytes()).unwrap();
+        let (_, fresh) = setup().await;
+        assert!(matches!(
+            import_snapshot(&forged, &appchain(&fresh)).await,
+            Err(SpvmError::InvalidSnapshot(msg)) if msg.contains("State root")
+        ));
+        assert_eq!(state_root(&appchain(&fresh)).await.unwrap(), H256::zero());
+        assert_eq!(get_latest_block(&appchain(&fresh)).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,293 @@
+//! Derivation of an appchain's state from the blocks stored by its SPVM-1 contract.
+//!
+//! Services that execute the transactions they see off-chain can drift away from the contract.
+//! Replaying the contract's blocks in order with [`sync_block`] rebuilds the state the contract
+//! has, checking each block's hash and its link to the previous one first.
+
+use ethers::{
+    core::utils::keccak256,
+    types::{Address, Bytes, Signature, H256},
+};
+use sea_orm::{ConnectionTrait, TransactionTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    encode_spvm_transactions, execute_block, get_latest_block, insert_block, state_root, Appchain,
+    Block, Receipt, SignatureScheme, SpvmError, Transaction,
+};
+
+/// Scheme of the transaction hashes checked by the SPVM-1 contract.
+pub const CONTRACT_SIGNATURE_SCHEME: SignatureScheme = SignatureScheme::Legacy;
+
+/// A block as stored by the SPVM-1 contract: the `blocks` getter with the result of
+/// `getTransactionsInBlock`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractBlock {
+    pub number: u32,
+    pub block_hash: H256,
+    pub parent_hash: H256,
+    pub transactions: Vec<Transaction>,
+    pub proposer: Address,
+    pub proposer_signature: Bytes,
+}
+
+/// `keccak256(abi.encodePacked(parentHash, abi.encode(transactions)))`, the block hash checked
+/// by the SPVM-1 contract. The genesis block's hash is zero.
+pub fn contract_block_hash(parent_hash: H256, transactions: &[Transaction]) -> H256 {
+    let mut data = parent_hash.as_bytes().to_vec();
+    data.extend_from_slice(&encode_spvm_transactions(transactions));
+
+    H256(keccak256(data))
+}
+
+impl ContractBlock {
+    /// Checks the block's hash and that it follows `parent`, the previous block synced from the
+    /// contract, or the genesis block if `None`.
+    pub fn verify(&self, parent: Option<&Block>) -> Result<(), SpvmError> {
+        let hash = contract_block_hash(self.parent_hash, &self.transactions);
+        if hash != self.block_hash {
+            return Err(SpvmError::InvalidBlock(format!(
+                "Block {} has hash {:#x}, its content hashes to {:#x}",
+                self.number, self.block_hash, hash
+            )));
+        }
+
+        let (number, parent_hash) = match parent {
+            Some(parent) => (parent.number + 1, parent.contract_hash()),
+            None => (1, H256::zero()),
+        };
+        if self.number != number {
+            return Err(SpvmError::InvalidBlock(format!(
+                "Expected block {}, got block {}",
+                number, self.number
+            )));
+        }
+        if self.parent_hash != parent_hash {
+            return Err(SpvmError::InvalidBlock(format!(
+                "Block {} has parent {:#x}, expected {:#x}",
+                self.number, self.parent_hash, parent_hash
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Verifies `block` against the latest block of `appchain`, then executes and stores it.
+///
+/// `appchain` must only hold blocks synced from the contract: they are stored with the parent
+/// hash and transactions of the contract's block, so its hash can be recomputed, its proposer's
+/// signature over that hash, the state root after executing it and no timestamp. A transaction
+/// that fails means the local state differs from the contract's; its error is returned, with the
+/// failure receipt written as by [`execute_block`], and the block is not stored.
+pub async fn sync_block<C>(
+    block: &ContractBlock,
+    appchain: &Appchain<'_, C>,
+) -> Result<Vec<Receipt>, SpvmError>
+where
+    C: ConnectionTrait + TransactionTrait,
+{
+    block.verify(get_latest_block(appchain).await?.as_ref())?;
+    // SPVM-1 also accepts ERC-1271 signatures, which a block can't store
+    let signature = Signature::try_from(block.proposer_signature.as_ref()).map_err(|e| {
+        SpvmError::InvalidBlock(format!(
+            "Block {} has an unsupported proposer signature: {}",
+            block.number, e
+        ))
+    })?;
+
+    let txn = appchain.begin().await?;
+    let batch = appchain.on(&txn);
+
+    let receipts = match execute_block(
+        block.number,
+        &block.transactions,
+        &CONTRACT_SIGNATURE_SCHEME,
+        &batch,
+    )
+    .await
+    {
+        Ok(receipts) => receipts,
+        Err(e) => {
+            // Keep the failure receipt
+            txn.commit().await?;
+            return Err(e);
+        }
+    };
+
+    insert_block(
+        &Block {
+            number: block.number,
+            parent_hash: block.parent_hash,
+            transactions: block.transactions.clone(),
+            proposer: block.proposer,
+            signature,
+            state_root: state_root(&batch).await?,
+            timestamp: 0,
+        },
+        &batch,
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok(receipts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::{H256, U256},
+    };
+
+    fn create_contract_block(
+        proposer: &LocalWallet,
+        number: u32,
+        parent_hash: H256,
+        mut transactions: Vec<Transaction>,
+        signers: &[&LocalWallet],
+    ) -> ContractBlock {
+        for (tx, signer) in transactions.iter_mut().zip(signers) {
+            resign(tx, signer, &CONTRACT_SIGNATURE_SCHEME);
+        }
+        let block_hash = contract_block_hash(parent_hash, &transactions);
+
+        ContractBlock {
+            number,
+            block_hash,
+            parent_hash,
+            transactions,
+            proposer: proposer.address(),
+            proposer_signature: proposer.sign_hash(block_hash).unwrap().to_vec().into(),
+        }
+    }
+
+    #[async_std::test]
+    async fn sync_replays_contract_blocks() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        let b1 = create_contract_block(
+            &wallet,
+            1,
+            H256::zero(),
+            vec![
+                create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0),
+                create_transfer_transaction("ABC", &wallet, wallet2.address(), 30, 1),
+            ],
+            &[&wallet, &wallet],
+        );
+        let b2 = create_contract_block(
+            &wallet2,
+            2,
+            b1.block_hash,
+            vec![create_transfer_transaction(
+                "ABC",
+                &wallet2,
+                wallet.address(),
+                10,
+                0,
+            )],
+            &[&wallet2],
+        );
+
+        // Out of order, or not linked to the genesis block
+        assert!(matches!(
+            sync_block(&b2, &appchain(&connection)).await,
+            Err(SpvmError::InvalidBlock(_))
+        ));
+        let mut orphan = b1.clone();
+        orphan.parent_hash = H256::random();
+        orphan.block_hash = contract_block_hash(orphan.parent_hash, &orphan.transactions);
+        assert!(matches!(
+            sync_block(&orphan, &appchain(&connection)).await,
+            Err(SpvmError::InvalidBlock(msg)) if msg.contains("parent")
+        ));
+
+        let receipts = sync_block(&b1, &appchain(&connection)).await.unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert!(matches!(
+            sync_block(&b1, &appchain(&connection)).await,
+            Err(SpvmError::InvalidBlock(_))
+        ));
+
+        let mut tampered = b2.clone();
+        tampered.transactions.pop();
+        assert!(matches!(
+            sync_block(&tampered, &appchain(&connection)).await,
+            Err(SpvmError::InvalidBlock(msg)) if msg.contains("hashes to")
+        ));
+        sync_block(&b2, &appchain(&connection)).await.unwrap();
+
+        assert_eq!(
+            TransactionContent::get_balance("ABC", wallet2.address(), &appchain(&connection))
+                .await
+                .unwrap(),
+            U256::from(20)
+        );
+        let head = get_latest_block(&appchain(&connection))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.number, 2);
+        assert_eq!(head.parent_hash, b1.block_hash);
+        assert_eq!(head.proposer, wallet2.address());
+        assert_eq!(
+            head.state_root,
+            state_root(&appchain(&connection)).await.unwrap()
+        );
+        assert_eq!(
+            contract_block_hash(head.parent_hash, &head.transactions),
+            b2.block_hash
+        );
+    }
+
+    #[async_std::test]
+    async fn sync_stops_at_diverging_blocks() {
+        let (wallet, connection) = setup().await;
+        let wallet2 = LocalWallet::new(&mut thread_rng());
+
+        // Spends a balance the local state doesn't have, as when it drifted from the contract's
+        let block = create_contract_block(
+            &wallet,
+            1,
+            H256::zero(),
+            vec![create_transfer_transaction(
+                "ABC",
+                &wallet,
+                wallet2.address(),
+                10,
+                0,
+            )],
+            &[&wallet],
+        );
+        assert!(matches!(
+            sync_block(&block, &appchain(&connection)).await,
+            Err(SpvmError::TickerNotInitialized(_))
+        ));
+        assert_eq!(
+            get_latest_block(&appchain(&connection)).await.unwrap(),
+            None
+        );
+        let failed = get_receipts(block.transactions[0].tx_hash, &appchain(&connection))
+            .await
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert!(!failed[0].is_success());
+        assert!(get_block_receipts(1, &appchain(&connection))
+            .await
+            .unwrap()
+            .is_empty());
+
+        let mut unsigned = block.clone();
+        unsigned.proposer_signature = vec![0u8; 64].into();
+        assert!(matches!(
+            sync_block(&unsigned, &appchain(&connection)).await,
+            Err(SpvmError::InvalidBlock(msg)) if msg.contains("signature")
+        ));
+    }
+}
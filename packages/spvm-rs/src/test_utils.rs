@@ -0,0 +1,359 @@
+//! Fixtures for tests of crates built on spvm-rs, behind the `test-utils` feature.
+//!
+//! [`setup_db`] gives every test its own in-memory SQLite database with the migrations applied,
+//! so tests need no `DB` variable and can't see each other's tickers, nonces or blocks. The
+//! builders sign transactions for [`CHAIN_ID`] with [`SCHEME`].
+
+use ethers::{
+    core::rand::thread_rng,
+    signers::{LocalWallet, Signer},
+    types::{Address, H256, U256},
+};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{Database, DatabaseConnection};
+
+use crate::{
+    Appchain, ApproveTransactionParams, Block, BurnTransactionParams, InMemoryBackend,
+    IssueTransactionParams, MintTransactionParams, MultiTransferTransactionParams, SignatureScheme,
+    SpvmDomain, Transaction, TransactionContent, TransactionParams, TransferFromTransactionParams,
+    TransferTransactionParams, TX_FORMAT_V2,
+};
+
+/// Appchain of [`appchain`] and of the v2 transactions built by [`create_transaction`].
+pub const CHAIN_ID: u64 = 1;
+
+/// Scheme the builders sign with: EIP-712 for an SPVM contract at the zero address on anvil's
+/// default chain.
+pub const SCHEME: SignatureScheme = SignatureScheme::Eip712(SpvmDomain {
+    chain_id: U256([31337, 0, 0, 0]),
+    verifying_contract: Address::zero(),
+});
+
+/// Opens a new in-memory SQLite database and applies every migration to it.
+pub async fn setup_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    Migrator::up(&db, None).await.unwrap();
+
+    db
+}
+
+/// A fresh database, see [`setup_db`], with a random wallet to send transactions from.
+pub async fn setup() -> (LocalWallet, DatabaseConnection) {
+    (LocalWallet::new(&mut thread_rng()), setup_db().await)
+}
+
+/// Appchain [`CHAIN_ID`] of `db`, accepting the legacy transactions most builders make.
+pub fn appchain(db: &DatabaseConnection) -> Appchain<'_, DatabaseConnection> {
+    Appchain::new(CHAIN_ID, db).accept_legacy_txs()
+}
+
+/// State of [`CHAIN_ID`] in memory, accepting the legacy transactions most builders make.
+pub fn in_memory() -> InMemoryBackend {
+    InMemoryBackend::new(CHAIN_ID).accept_legacy_txs()
+}
+
+/// Builds a transaction from `signer` and signs it with [`SCHEME`]. v2 transactions are bound
+/// to [`CHAIN_ID`].
+pub fn create_transaction(
+    signer: &LocalWallet,
+    tx_type: u8,
+    tx_param: TransactionParams,
+    nonce: u32,
+) -> Transaction {
+    let tx_content = TransactionContent {
+        from: signer.address(),
+        tx_type,
+        tx_param,
+        nonce,
+        chain_id: if tx_type & TX_FORMAT_V2 == 0 {
+            0
+        } else {
+            CHAIN_ID
+        },
+    };
+
+    let tx_hash = SCHEME.tx_hash(&tx_content);
+    let signature = signer.sign_hash(tx_hash).unwrap();
+
+    Transaction {
+        tx_content,
+        tx_hash,
+        signature,
+    }
+}
+
+/// A legacy transaction minting `supply` of `ticker` to `owner`.
+pub fn create_mint_transaction(
+    ticker: &str,
+    from: &LocalWallet,
+    owner: Address,
+    supply: u64,
+    nonce: u32,
+) -> Transaction {
+    create_transaction(
+        from,
+        0,
+        TransactionParams::Mint(MintTransactionParams {
+            token_ticker: ticker.to_string(),
+            owner,
+            supply: U256::from(supply),
+        }),
+        nonce,
+    )
+}
+
+/// A legacy transaction sending `amount` of `ticker` to `to`.
+pub fn create_transfer_transaction(
+    ticker: &str,
+    from: &LocalWallet,
+    to: Address,
+    amount: u64,
+    nonce: u32,
+) -> Transaction {
+    create_transaction(
+        from,
+        1,
+        TransactionParams::Transfer(TransferTransactionParams {
+            token_ticker: ticker.to_string(),
+            to,
+            amount: U256::from(amount),
+        }),
+        nonce,
+    )
+}
+
+/// A legacy transaction burning `amount` of the sender's `ticker`.
+pub fn create_burn_transaction(
+    ticker: &str,
+    from: &LocalWallet,
+    amount: u64,
+    nonce: u32,
+) -> Transaction {
+    create_transaction(
+        from,
+        2,
+        TransactionParams::Burn(BurnTransactionParams {
+            token_ticker: ticker.to_string(),
+            amount: U256::from(amount),
+        }),
+        nonce,
+    )
+}
+
+/// A legacy transaction issuing `amount` more of `ticker` to `to`.
+pub fn create_issue_transaction(
+    ticker: &str,
+    from: &LocalWallet,
+    to: Address,
+    amount: u64,
+    nonce: u32,
+) -> Transaction {
+    create_transaction(
+        from,
+        4,
+        TransactionParams::Issue(IssueTransactionParams {
+            token_ticker: ticker.to_string(),
+            to,
+            amount: U256::from(amount),
+        }),
+        nonce,
+    )
+}
+
+/// A legacy transaction allowing `spender` to transfer up to `amount` of the sender's `ticker`.
+pub fn create_approve_transaction(
+    ticker: &str,
+    from: &LocalWallet,
+    spender: Address,
+    amount: u64,
+    nonce: u32,
+) -> Transaction {
+    create_transaction(
+        from,
+        5,
+        TransactionParams::Approve(ApproveTransactionParams {
+            token_ticker: ticker.to_string(),
+            spender,
+            amount: U256::from(amount),
+        }),
+        nonce,
+    )
+}
+
+/// A legacy transaction sending `amount` of `owner`'s `ticker` to `to`.
+pub fn create_transfer_from_transaction(
+    ticker: &str,
+    from: &LocalWallet,
+    owner: Address,
+    to: Address,
+    amount: u64,
+    nonce: u32,
+) -> Transaction {
+    create_transaction(
+        from,
+        6,
+        TransactionParams::TransferFrom(TransferFromTransactionParams {
+            token_ticker: ticker.to_string(),
+            owner,
+            to,
+            amount: U256::from(amount),
+        }),
+        nonce,
+    )
+}
+
+/// A legacy transaction sending `ticker` to each recipient of `transfers`.
+pub fn create_multi_transfer_transaction(
+    ticker: &str,
+    from: &LocalWallet,
+    transfers: &[(Address, u64)],
+    nonce: u32,
+) -> Transaction {
+    create_transaction(
+        from,
+        7,
+        TransactionParams::MultiTransfer(MultiTransferTransactionParams {
+            token_ticker: ticker.to_string(),
+            transfers: transfers
+                .iter()
+                .map(|(to, amount)| (*to, U256::from(*amount)))
+                .collect(),
+        }),
+        nonce,
+    )
+}
+
+/// Block `number` of `transactions`, signed by `proposer`. The state root is random.
+pub fn create_block(
+    proposer: &LocalWallet,
+    number: u32,
+    parent_hash: H256,
+    transactions: Vec<Transaction>,
+) -> Block {
+    let mut block = Block {
+        number,
+        parent_hash,
+        transactions,
+        proposer: proposer.address(),
+        state_root: H256::random(),
+        timestamp: 1_700_000_000 + u64::from(number),
+        ..Default::default()
+    };
+    block.signature = proposer.sign_hash(block.hash()).unwrap();
+
+    block
+}
+
+/// Signs `tx` again with another scheme, e.g. [`crate::CONTRACT_SIGNATURE_SCHEME`] for the
+/// SPVM-1 contract, or after changing its content.
+pub fn resign(tx: &mut Transaction, signer: &LocalWallet, scheme: &SignatureScheme) {
+    tx.tx_hash = scheme.tx_hash(&tx.tx_content);
+    tx.signature = signer.sign_hash(tx.tx_hash).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_tickers, StateBackend};
+    use sea_orm::{ConnectionTrait, Statement};
+
+    #[async_std::test]
+    async fn tests_get_fresh_databases() {
+        let (wallet, connection) = setup().await;
+        let t1 = create_mint_transaction("ABC", &wallet, wallet.address(), 100, 0);
+        t1.execute_transaction(&SCHEME, &appchain(&connection))
+            .await
+            .unwrap();
+
+        let (_, other) = setup().await;
+        assert!(list_tickers(&appchain(&other)).await.unwrap().is_empty());
+        t1.execute_transaction(&SCHEME, &appchain(&other))
+            .await
+            .unwrap();
+    }
+
+    // A database migrated before chain ids and full address keys, with balances and nonces keyed
+    // by the abbreviated `Display` of the owner. Its rows go to the appchain in APPCHAIN_ID.
+    #[async_std::test]
+    async fn migrations_expand_or_archive_baseline_keys() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, Some(1)).await.unwrap();
+
+        let (known, unknown, duplicate) = (
+            LocalWallet::new(&mut thread_rng()).address(),
+            LocalWallet::new(&mut thread_rng()).address(),
+            LocalWallet::new(&mut thread_rng()).address(),
+        );
+        db.execute_unprepared(&format!(
+            "INSERT INTO initialized_tickers (ticker, is_initialized) VALUES ('ABC', true);
+             INSERT INTO state (ticker, owner_address, amount)
+                 VALUES ('ABC', '{known}', 100), ('ABC', '{unknown}', 50);
+             INSERT INTO nonces (owner_address, nonce)
+                 VALUES ('{known:#x}', 3), ('{duplicate}', 1), ('{duplicate:#x}', 2);"
+        ))
+        .await
+        .unwrap();
+
+        // Only this test migrates rows from before chain ids, so nothing else reads it
+        std::env::set_var("APPCHAIN_ID", CHAIN_ID.to_string());
+        Migrator::up(&db, None).await.unwrap();
+
+        let state = appchain(&db).state();
+        assert_eq!(
+            state.get_balance("ABC", known).await.unwrap(),
+            U256::from(100)
+        );
+        assert_eq!(
+            state.get_balance("ABC", unknown).await.unwrap(),
+            U256::zero()
+        );
+        assert_eq!(state.get_nonce(known).await.unwrap(), 3);
+        assert_eq!(state.get_nonce(duplicate).await.unwrap(), 2);
+
+        let archived = db
+            .query_all(Statement::from_string(
+                db.get_database_backend(),
+                "SELECT source_table, owner_address, value FROM archived_address_keys
+                 ORDER BY source_table",
+            ))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| {
+                (
+                    row.try_get::<String>("", "source_table").unwrap(),
+                    row.try_get::<String>("", "owner_address").unwrap(),
+                    row.try_get::<String>("", "value").unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            archived,
+            [
+                ("nonces".into(), duplicate.to_string(), "1".into()),
+                ("state".into(), unknown.to_string(), "50".into()),
+            ]
+        );
+    }
+
+    // Nonces past i32::MAX used to be stored as the bits of an i32
+    #[async_std::test]
+    async fn migrations_widen_negative_nonces() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, Some(9)).await.unwrap();
+
+        let owner = LocalWallet::new(&mut thread_rng()).address();
+        db.execute_unprepared(&format!(
+            "INSERT INTO nonces (chain_id, owner_address, nonce) VALUES ({CHAIN_ID}, '{owner:#x}', -1)"
+        ))
+        .await
+        .unwrap();
+
+        Migrator::up(&db, None).await.unwrap();
+
+        assert_eq!(
+            appchain(&db).state().get_nonce(owner).await.unwrap(),
+            u32::MAX
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{Database, TransactionTrait};
+use spvm_rs::{export_snapshot, import_snapshot, Appchain, Snapshot};
+use std::env;
+
+// Exports the SPVM state of an appchain to a snapshot file, or bootstraps an empty database
+// from one. The database and appchain are read from DB and APPCHAIN_ID.
+//
+//   snapshot export <file>
+//   snapshot import <file>
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenvy::dotenv().ok();
+    let args: Vec<String> = env::args().collect();
+    let (command, path) = match args.as_slice() {
+        [_, command, path] => (command.as_str(), path.as_str()),
+        _ => return Err("usage: snapshot <export|import> <file>".into()),
+    };
+
+    let db = Database::connect(env::var("DB").expect("DB is not set in .env file")).await?;
+    Migrator::up(&db, None).await?;
+    let chain_id = env::var("APPCHAIN_ID")
+        .expect("APPCHAIN_ID is not set in .env file")
+        .parse::<u64>()?;
+
+    match command {
+        "export" => {
+            // Read every table in one transaction so the snapshot is consistent
+            let txn = db.begin().await?;
+            let snapshot = export_snapshot(&Appchain::new(chain_id, &txn)).await?;
+            txn.commit().await?;
+
+            std::fs::write(path, snapshot.to_bytes())?;
+            println!(
+                "Exported appchain {} at block {:?} with state root {:#x} to {}",
+                chain_id,
+                snapshot.head.as_ref().map(|block| block.number),
+                snapshot.state_root,
+                path
+            );
+        }
+        "import" => {
+            let snapshot = Snapshot::from_bytes(&std::fs::read(path)?)?;
+            import_snapshot(&snapshot, &Appchain::new(chain_id, &db)).await?;
+            println!(
+                "Imported appchain {} at block {:?} with state root {:#x} from {}",
+                chain_id,
+                snapshot.head.as_ref().map(|block| block.number),
+                snapshot.state_root,
+                path
+            );
+        }
+        _ => {
+            return Err(format!("unknown command {:?}, expected export or import", command).into())
+        }
+    }
+
+    Ok(())
+}
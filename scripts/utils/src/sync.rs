@@ -0,0 +1,72 @@
+use ethers::contract::abigen;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, H256};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::Database;
+use spvm_rs::{decode_spvm_transactions, get_latest_block, sync_block, Appchain, ContractBlock};
+use std::env;
+use std::sync::Arc;
+
+abigen!(Spvm, "../../apps/proposer/contracts/SPVM.json");
+
+// Rebuilds the SPVM state of an appchain by replaying the blocks stored by its SPVM-1 contract,
+// from the last block synced into the database up to the contract's latest block. The database,
+// appchain, provider and contract are read from DB, APPCHAIN_ID, PROVIDER and SPVM_ADDRESS.
+//
+//   sync
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenvy::dotenv().ok();
+    let db = Database::connect(env::var("DB").expect("DB is not set in .env file")).await?;
+    Migrator::up(&db, None).await?;
+    let chain_id = env::var("APPCHAIN_ID")
+        .expect("APPCHAIN_ID is not set in .env file")
+        .parse::<u64>()?;
+    let spvm_address: Address = env::var("SPVM_ADDRESS")
+        .expect("SPVM_ADDRESS is not set in .env file")
+        .parse()?;
+    let provider = Provider::<Http>::try_from(
+        env::var("PROVIDER").expect("PROVIDER is not set in .env file"),
+    )?;
+    let client = Arc::new(provider);
+    let spvm = Spvm::new(spvm_address, client.clone());
+
+    // SPVM-1 blocks can only hold legacy transactions
+    let appchain = Appchain::new(chain_id, &db).accept_legacy_txs();
+    let synced = get_latest_block(&appchain)
+        .await?
+        .map_or(0, |block| block.number);
+    let latest = spvm.block_number().call().await?;
+
+    for number in synced + 1..=latest {
+        let (block_hash, parent_hash, _, proposer, proposer_signature) =
+            spvm.blocks(number).call().await?;
+        // The transactions are decoded from the raw return data, which keeps the exact encoding
+        // the block hash was computed over
+        let call = spvm.get_transactions_in_block(number);
+        let transactions = decode_spvm_transactions(&client.call(&call.tx, None).await?)?;
+
+        let block = ContractBlock {
+            number,
+            block_hash: H256(block_hash),
+            parent_hash: H256(parent_hash),
+            transactions,
+            proposer,
+            proposer_signature,
+        };
+        let receipts = sync_block(&block, &appchain).await?;
+        println!(
+            "Synced block {} of appchain {} with {} transactions",
+            number,
+            chain_id,
+            receipts.len()
+        );
+    }
+
+    println!(
+        "Appchain {} is synced up to block {} of contract {:#x}",
+        chain_id, latest, spvm_address
+    );
+
+    Ok(())
+}